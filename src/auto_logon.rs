@@ -1,8 +1,15 @@
-use windows_registry::LOCAL_MACHINE;
+use crate::{flags::Flags, registry_key::RegistryKey};
 
-pub fn is_auto_logon_enabled() -> anyhow::Result<bool> {
-    let key = LOCAL_MACHINE.open("SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion\\Winlogon")?;
+pub fn score_auto_logon(local_machine: &dyn RegistryKey, flags: &mut Flags) -> anyhow::Result<()> {
+    let key = local_machine.open("SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion\\Winlogon")?;
     let value = key.get_string("AutoAdminLogon")?;
 
-    Ok(value == "1")
+    if value == "1" {
+        flags.medium_penalty(
+            "auto_logon.enabled",
+            "windows is set to log on automatically",
+        );
+    }
+
+    Ok(())
 }
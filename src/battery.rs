@@ -1,43 +1,75 @@
+use serde::{Deserialize, Serialize};
+#[cfg(windows)]
 use windows::{
     Devices::{Enumeration::DeviceInformation, Power::Battery},
     System::Power::BatteryStatus,
 };
 
+use crate::flags::Flags;
+#[cfg(windows)]
 use crate::util::get_devices_iter;
 
-pub fn get_battery() -> anyhow::Result<bool> {
-    let selector = Battery::GetDeviceSelector()?;
-    let batteries = get_devices_iter(&selector)?;
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct BatteryFacts {
+    pub batteries: Vec<BatteryReport>,
+}
 
-    for device in batteries {
-        if get_device_as_battery(&device).unwrap_or_default() {
-            return Ok(true);
-        }
-    }
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BatteryReport {
+    pub present: bool,
+    pub charge_rate_mw: Option<i32>,
+    pub design_capacity_mwh: Option<i32>,
+    pub full_charge_capacity_mwh: Option<i32>,
+    pub remaining_capacity_mwh: Option<i32>,
+}
 
-    Ok(false)
+impl BatteryReport {
+    fn has_real_capacity(&self) -> bool {
+        self.present
+            && [
+                self.charge_rate_mw,
+                self.design_capacity_mwh,
+                self.full_charge_capacity_mwh,
+                self.remaining_capacity_mwh,
+            ]
+            .into_iter()
+            .flatten()
+            .any(|value| value != 0)
+    }
 }
 
-fn get_device_as_battery(device: &DeviceInformation) -> anyhow::Result<bool> {
-    let battery = Battery::FromIdAsync(&device.Id()?)?.get()?;
-    let battery_report = battery.GetReport()?;
+#[cfg(windows)]
+pub fn collect_battery() -> anyhow::Result<BatteryFacts> {
+    let selector = Battery::GetDeviceSelector()?;
+    let batteries = get_devices_iter(&selector)?
+        .filter_map(|device| get_battery_report(&device).ok())
+        .collect();
+
+    Ok(BatteryFacts { batteries })
+}
 
-    if battery_report.Status()? == BatteryStatus::NotPresent {
-        return Ok(false);
+pub fn score_battery(facts: &BatteryFacts, flags: &mut Flags) {
+    if facts.batteries.iter().any(BatteryReport::has_real_capacity) {
+        flags.extreme_bonus("battery.present", "has a battery reporting real capacity");
     }
+}
 
-    let values = [
-        battery_report.ChargeRateInMilliwatts(),
-        battery_report.DesignCapacityInMilliwattHours(),
-        battery_report.FullChargeCapacityInMilliwattHours(),
-        battery_report.RemainingCapacityInMilliwattHours(),
-    ];
+#[cfg(windows)]
+fn get_battery_report(device: &DeviceInformation) -> anyhow::Result<BatteryReport> {
+    let battery = Battery::FromIdAsync(&device.Id()?)?.get()?;
+    let battery_report = battery.GetReport()?;
 
-    let found_valid_value = values
-        .into_iter()
-        .flatten()
-        .filter_map(|value| value.GetInt32().ok())
-        .any(|value| value != 0);
+    let read = |value: windows_core::Result<windows::Foundation::IReference<i32>>| {
+        value.ok().and_then(|value| value.GetInt32().ok())
+    };
 
-    Ok(found_valid_value)
+    Ok(BatteryReport {
+        present: battery_report.Status()? != BatteryStatus::NotPresent,
+        charge_rate_mw: read(battery_report.ChargeRateInMilliwatts()),
+        design_capacity_mwh: read(battery_report.DesignCapacityInMilliwattHours()),
+        full_charge_capacity_mwh: read(battery_report.FullChargeCapacityInMilliwattHours()),
+        remaining_capacity_mwh: read(battery_report.RemainingCapacityInMilliwattHours()),
+    })
 }
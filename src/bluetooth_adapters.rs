@@ -1,44 +1,89 @@
+use serde::{Deserialize, Serialize};
+#[cfg(windows)]
 use windows::Devices::{Bluetooth::BluetoothAdapter, Enumeration::DeviceInformation};
 
-use crate::{debug_println, flags::Flags, util::get_devices_iter};
+#[cfg(windows)]
+use crate::util::get_devices_iter;
+use crate::{debug_println, flags::Flags};
 
-pub fn score_bluetooth_adapters(flags: &mut Flags) -> anyhow::Result<()> {
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct BluetoothFacts {
+    pub adapters: Vec<BluetoothAdapterFacts>,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct BluetoothAdapterFacts {
+    pub classic_secure_connections: bool,
+    pub low_energy: bool,
+    pub classic: bool,
+    pub peripheral_role: bool,
+    pub central_role: bool,
+    pub advertisement_offload: bool,
+}
+
+impl BluetoothAdapterFacts {
+    const fn is_valid(&self) -> bool {
+        self.classic_secure_connections
+            || self.low_energy
+            || self.classic
+            || self.peripheral_role
+            || self.central_role
+            || self.advertisement_offload
+    }
+}
+
+#[cfg(windows)]
+pub fn collect_bluetooth_adapters() -> anyhow::Result<BluetoothFacts> {
     let selector = BluetoothAdapter::GetDeviceSelector()?;
 
-    let bluetooth_adapter_count = get_devices_iter(&selector)?
-        .filter(|device| is_valid_bluetooth_adapter(device).unwrap_or_default())
+    let adapters = get_devices_iter(&selector)?
+        .filter_map(|device| get_bluetooth_adapter(&device).ok())
+        .collect();
+
+    Ok(BluetoothFacts { adapters })
+}
+
+pub fn score_bluetooth_adapters(facts: &BluetoothFacts, flags: &mut Flags) {
+    let bluetooth_adapter_count = facts
+        .adapters
+        .iter()
+        .filter(|adapter| adapter.is_valid())
         .count();
 
     match bluetooth_adapter_count {
-        0 => flags.medium_penalty(),
-        _ => flags.large_bonus(),
+        0 => {
+            flags.medium_penalty("bluetooth.none", "no valid bluetooth adapters");
+        }
+        n => {
+            flags
+                .large_bonus("bluetooth.present", "has a valid bluetooth adapter")
+                .evidence(n);
+        }
     }
 
     debug_println!("found {bluetooth_adapter_count} valid Bluetooth adapters");
-    Ok(())
 }
 
-fn is_valid_bluetooth_adapter(device: &DeviceInformation) -> anyhow::Result<bool> {
+#[cfg(windows)]
+fn get_bluetooth_adapter(device: &DeviceInformation) -> anyhow::Result<BluetoothAdapterFacts> {
     let bluetooth_adapter = BluetoothAdapter::FromIdAsync(&device.Id()?)?.get()?;
 
-    let r = [
-        bluetooth_adapter
+    Ok(BluetoothAdapterFacts {
+        classic_secure_connections: bluetooth_adapter
             .AreClassicSecureConnectionsSupported()
             .unwrap_or_default(),
-        bluetooth_adapter.IsLowEnergySupported().unwrap_or_default(),
-        bluetooth_adapter.IsClassicSupported().unwrap_or_default(),
-        bluetooth_adapter
+        low_energy: bluetooth_adapter.IsLowEnergySupported().unwrap_or_default(),
+        classic: bluetooth_adapter.IsClassicSupported().unwrap_or_default(),
+        peripheral_role: bluetooth_adapter
             .IsPeripheralRoleSupported()
             .unwrap_or_default(),
-        bluetooth_adapter
+        central_role: bluetooth_adapter
             .IsCentralRoleSupported()
             .unwrap_or_default(),
-        bluetooth_adapter
+        advertisement_offload: bluetooth_adapter
             .IsAdvertisementOffloadSupported()
             .unwrap_or_default(),
-    ]
-    .into_iter()
-    .any(|x| x);
-
-    Ok(r)
+    })
 }
@@ -0,0 +1,360 @@
+use std::time::Duration;
+
+use crate::{
+    activated::score_license,
+    auto_logon::score_auto_logon,
+    battery::score_battery,
+    bluetooth_adapters::score_bluetooth_adapters,
+    displays::score_displays,
+    facts::FactSource,
+    flags::{Flags, Level},
+    graphics_card::score_graphics_cards,
+    inspect,
+    installed_apps::score_installed_apps,
+    key_timestamps::score_key_timestamps,
+    microsoft_account::score_microsoft_account,
+    os::score_os,
+    printers::score_printers,
+    product_key::score_product_key,
+    profile::ScoringProfile,
+    registry::score_registry,
+    sysinfo::score_sysinfo,
+    system_devices::score_system_devices,
+    usb_devices::score_usb_devices,
+    various_wmi::score_various_wmi,
+    wifi_adapters::score_wifi_adapters,
+};
+
+/// Roughly how long a check takes, the scheduler gives slow checks their own thread
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Cost {
+    /// A few milliseconds at most
+    Fast,
+    /// Tens to hundreds of milliseconds
+    Slow,
+}
+
+impl Cost {
+    /// How long a check of this cost gets before it is reported as timed out
+    #[must_use]
+    pub const fn deadline(self) -> Duration {
+        match self {
+            Self::Fast => Duration::from_secs(2),
+            Self::Slow => Duration::from_secs(5),
+        }
+    }
+}
+
+/// What to do when a check fails to run
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Record the error, but don't affect the score
+    Ignore,
+    /// Add a `<name>.error` penalty of this level
+    Penalize(Level),
+}
+
+impl ErrorPolicy {
+    #[must_use]
+    pub const fn penalty(self) -> Option<Level> {
+        match self {
+            Self::Ignore => None,
+            Self::Penalize(level) => Some(level),
+        }
+    }
+}
+
+pub trait Check: Sync {
+    /// Short name used to pick checks on the command line, e.x. `usb`
+    fn id(&self) -> &'static str;
+    /// Shown in reports and used for rule ids, e.x. `usb devices`
+    fn name(&self) -> &'static str;
+    /// Checks using WMI or shell links are skipped when COM is unavailable
+    fn needs_com(&self) -> bool;
+    fn cost(&self) -> Cost;
+    fn error_policy(&self) -> ErrorPolicy;
+    fn deadline(&self) -> Duration {
+        self.cost().deadline()
+    }
+    fn run(
+        &self,
+        source: &dyn FactSource,
+        profile: &ScoringProfile,
+        flags: &mut Flags,
+    ) -> anyhow::Result<()>;
+}
+
+/// A [`Check`] described by its metadata and a scoring function
+pub struct FnCheck {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub needs_com: bool,
+    pub cost: Cost,
+    pub error_policy: ErrorPolicy,
+    pub run: fn(&dyn FactSource, &ScoringProfile, &mut Flags) -> anyhow::Result<()>,
+}
+
+impl Check for FnCheck {
+    fn id(&self) -> &'static str {
+        self.id
+    }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn needs_com(&self) -> bool {
+        self.needs_com
+    }
+
+    fn cost(&self) -> Cost {
+        self.cost
+    }
+
+    fn error_policy(&self) -> ErrorPolicy {
+        self.error_policy
+    }
+
+    fn run(
+        &self,
+        source: &dyn FactSource,
+        profile: &ScoringProfile,
+        flags: &mut Flags,
+    ) -> anyhow::Result<()> {
+        (self.run)(source, profile, flags)
+    }
+}
+
+/// Every built-in check. Adding a detection only takes an entry here.
+pub static CHECKS: &[&dyn Check] = &[
+    // SLOW CHECK: Takes 150-400ms
+    &FnCheck {
+        id: "system-devices",
+        name: "system devices",
+        needs_com: false,
+        cost: Cost::Slow,
+        error_policy: ErrorPolicy::Penalize(Level::Large),
+        run: |source, profile, f| {
+            let facts = inspect!("system devices", source.system_devices())?;
+            let preload = source.preloaded_registry(&profile.registry)?;
+            score_system_devices(&facts, &preload.local_machine, f)
+        },
+    },
+    // SLOW CHECK: Takes 60-150ms
+    &FnCheck {
+        id: "os",
+        name: "os",
+        needs_com: true,
+        cost: Cost::Slow,
+        error_policy: ErrorPolicy::Penalize(Level::Large),
+        run: |source, profile, f| score_os(&inspect!("os", source.os())?, &profile.os, f),
+    },
+    // SLOW CHECK: Takes ~40-400ms
+    &FnCheck {
+        id: "installed-apps",
+        name: "installed apps",
+        needs_com: true,
+        cost: Cost::Slow,
+        error_policy: ErrorPolicy::Penalize(Level::Large),
+        run: |source, profile, f| {
+            score_installed_apps(
+                &inspect!("installed apps", source.installed_apps())?,
+                &profile.installed_apps,
+                f,
+            );
+            Ok(())
+        },
+    },
+    // SLOW CHECK: Takes ~300ms
+    // Failing to read the license is treated the same as being unlicensed
+    &FnCheck {
+        id: "license",
+        name: "license type",
+        needs_com: false,
+        cost: Cost::Slow,
+        error_policy: ErrorPolicy::Penalize(Level::Medium),
+        run: |source, profile, f| {
+            score_license(
+                &inspect!("license type", source.license())?,
+                &profile.license_keys,
+                f,
+            );
+            Ok(())
+        },
+    },
+    &FnCheck {
+        id: "wifi",
+        name: "wifi adapters",
+        needs_com: false,
+        cost: Cost::Fast,
+        error_policy: ErrorPolicy::Penalize(Level::Medium),
+        run: |source, _, f| {
+            score_wifi_adapters(&inspect!("wifi adapters", source.wifi_adapters())?, f);
+            Ok(())
+        },
+    },
+    &FnCheck {
+        id: "displays",
+        name: "displays",
+        needs_com: false,
+        cost: Cost::Fast,
+        error_policy: ErrorPolicy::Penalize(Level::Large),
+        run: |source, _, f| {
+            score_displays(&inspect!("displays", source.displays())?, f);
+            Ok(())
+        },
+    },
+    &FnCheck {
+        id: "battery",
+        name: "battery info",
+        needs_com: false,
+        cost: Cost::Fast,
+        error_policy: ErrorPolicy::Ignore,
+        run: |source, _, f| {
+            score_battery(&inspect!("battery info", source.battery())?, f);
+            Ok(())
+        },
+    },
+    &FnCheck {
+        id: "sysinfo",
+        name: "sysinfo",
+        needs_com: false,
+        cost: Cost::Fast,
+        error_policy: ErrorPolicy::Penalize(Level::Large),
+        run: |source, profile, f| {
+            score_sysinfo(&inspect!("sysinfo", source.sysinfo())?, &profile.sysinfo, f)
+        },
+    },
+    &FnCheck {
+        id: "usb",
+        name: "usb devices",
+        needs_com: false,
+        cost: Cost::Fast,
+        error_policy: ErrorPolicy::Penalize(Level::Large),
+        run: |source, _, f| {
+            score_usb_devices(&inspect!("usb devices", source.usb_devices())?, f);
+            Ok(())
+        },
+    },
+    // Loads the registry preload shared with system devices and key timestamps, unless one of them got to it first
+    &FnCheck {
+        id: "registry",
+        name: "registry",
+        needs_com: false,
+        cost: Cost::Slow,
+        error_policy: ErrorPolicy::Ignore,
+        run: |source, profile, f| {
+            score_registry(
+                &*source.preloaded_registry(&profile.registry)?,
+                &profile.registry,
+                f,
+            );
+            Ok(())
+        },
+    },
+    // Reads a few thousand keys
+    &FnCheck {
+        id: "key-timestamps",
+        name: "key timestamps",
+        needs_com: false,
+        cost: Cost::Slow,
+        error_policy: ErrorPolicy::Ignore,
+        run: |source, profile, f| {
+            let preload = source.preloaded_registry(&profile.registry)?;
+            inspect!(
+                "key timestamps",
+                score_key_timestamps(&preload.local_machine, &profile.key_timestamps, f)
+            )
+        },
+    },
+    // Takes ~66ms
+    &FnCheck {
+        id: "microsoft-account",
+        name: "microsoft account",
+        needs_com: true,
+        cost: Cost::Fast,
+        error_policy: ErrorPolicy::Penalize(Level::Small),
+        run: |source, _, f| {
+            score_microsoft_account(
+                &inspect!("microsoft account", source.microsoft_account())?,
+                f,
+            );
+            Ok(())
+        },
+    },
+    // this can be spoofed, and either way laptops can have discrete graphics cards
+    &FnCheck {
+        id: "graphics",
+        name: "graphics card",
+        needs_com: true,
+        cost: Cost::Fast,
+        error_policy: ErrorPolicy::Penalize(Level::Medium),
+        run: |source, _, f| {
+            score_graphics_cards(&inspect!("graphics card", source.graphics_cards())?, f);
+            Ok(())
+        },
+    },
+    &FnCheck {
+        id: "bluetooth",
+        name: "bluetooth",
+        needs_com: false,
+        cost: Cost::Fast,
+        error_policy: ErrorPolicy::Penalize(Level::Large),
+        run: |source, _, f| {
+            score_bluetooth_adapters(&inspect!("bluetooth", source.bluetooth_adapters())?, f);
+            Ok(())
+        },
+    },
+    // Takes ~53ms
+    &FnCheck {
+        id: "wmi",
+        name: "various wmi",
+        needs_com: true,
+        cost: Cost::Fast,
+        error_policy: ErrorPolicy::Penalize(Level::Large),
+        run: |source, _, f| {
+            score_various_wmi(&inspect!("various wmi", source.various_wmi())?, f);
+            Ok(())
+        },
+    },
+    &FnCheck {
+        id: "auto-logon",
+        name: "auto logon",
+        needs_com: false,
+        cost: Cost::Fast,
+        error_policy: ErrorPolicy::Ignore,
+        run: |source, _, f| inspect!("auto logon", score_auto_logon(&*source.registry()?, f)),
+    },
+    &FnCheck {
+        id: "product-key",
+        name: "product key",
+        needs_com: false,
+        cost: Cost::Fast,
+        error_policy: ErrorPolicy::Ignore,
+        run: |source, profile, f| {
+            inspect!(
+                "product key",
+                score_product_key(&*source.registry()?, &profile.license_keys, f)
+            )
+        },
+    },
+    &FnCheck {
+        id: "printers",
+        name: "printers",
+        needs_com: false,
+        cost: Cost::Fast,
+        error_policy: ErrorPolicy::Penalize(Level::Medium),
+        run: |source, _, f| {
+            score_printers(&inspect!("printers", source.printers())?, f);
+            Ok(())
+        },
+    },
+];
+
+/// The built-in check with this id
+#[must_use]
+pub fn by_id(id: &str) -> Option<&'static dyn Check> {
+    CHECKS.iter().copied().find(|check| check.id() == id)
+}
@@ -0,0 +1,388 @@
+//! CIM DATETIME, the string WMI returns for dates (`InstallDate`, `DriverDate`, `ReleaseDate`, `LastBootUpTime`).
+//!
+//! A timestamp is `yyyymmddHHMMSS.mmmmmmsUUU`, `s` the sign of the UTC offset `UUU` in minutes. An interval is
+//! `ddddddddHHMMSS.mmmmmm:000`. Any field can be all `*` when it isn't significant, and the microseconds can end in
+//! `*` past the digits that are.
+use std::fmt;
+
+use anyhow::{Context, bail, ensure};
+use chrono::{DateTime, FixedOffset, NaiveDate, TimeDelta};
+
+const LENGTH: usize = 25;
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CimDateTime {
+    Timestamp(Timestamp),
+    Interval(Interval),
+}
+
+/// A point in time, None fields were `*`
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Timestamp {
+    pub year: Option<u16>,
+    pub month: Option<u8>,
+    pub day: Option<u8>,
+    pub hour: Option<u8>,
+    pub minute: Option<u8>,
+    pub second: Option<u8>,
+    pub microseconds: Option<Microseconds>,
+    /// Minutes east of UTC
+    pub offset_minutes: i16,
+}
+
+/// A length of time, None fields were `*`
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Interval {
+    pub days: Option<u32>,
+    pub hours: Option<u8>,
+    pub minutes: Option<u8>,
+    pub seconds: Option<u8>,
+    pub microseconds: Option<Microseconds>,
+}
+
+/// The fraction of a second, of which only the first `digits` digits are significant
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Microseconds {
+    pub value: u32,
+    pub digits: u8,
+}
+
+impl CimDateTime {
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        Self::parse_inner(text).with_context(|| format!("bad cim datetime {text:?}"))
+    }
+
+    fn parse_inner(text: &str) -> anyhow::Result<Self> {
+        ensure!(text.is_ascii(), "not ascii");
+        ensure!(
+            text.len() == LENGTH,
+            "{} characters instead of {LENGTH}",
+            text.len()
+        );
+        let field = |start: usize, end: usize| text.get(start..end).unwrap_or_default();
+        ensure!(field(14, 15) == ".", "no . before the microseconds");
+        let microseconds = parse_microseconds(field(15, 21))?;
+
+        match field(21, 22) {
+            ":" => {
+                ensure!(field(22, 25) == "000", "interval offset is not 000");
+                Ok(Self::Interval(Interval {
+                    days: parse_field(field(0, 8), 0, 99_999_999)?,
+                    hours: parse_field(field(8, 10), 0, 23)?,
+                    minutes: parse_field(field(10, 12), 0, 59)?,
+                    seconds: parse_field(field(12, 14), 0, 59)?,
+                    microseconds,
+                }))
+            }
+            sign @ ("+" | "-") => {
+                let offset: i16 = parse_field(field(22, 25), 0, 999)?.context("no utc offset")?;
+                let timestamp = Timestamp {
+                    year: parse_field(field(0, 4), 0, 9999)?,
+                    month: parse_field(field(4, 6), 1, 12)?,
+                    day: parse_field(field(6, 8), 1, 31)?,
+                    hour: parse_field(field(8, 10), 0, 23)?,
+                    minute: parse_field(field(10, 12), 0, 59)?,
+                    // 60 for a leap second
+                    second: parse_field(field(12, 14), 0, 60)?,
+                    microseconds,
+                    offset_minutes: if sign == "-" { -offset } else { offset },
+                };
+                timestamp.validate_day()?;
+                Ok(Self::Timestamp(timestamp))
+            }
+            other => bail!("{other:?} is neither a utc offset sign nor :"),
+        }
+    }
+
+    #[must_use]
+    pub const fn timestamp(self) -> Option<Timestamp> {
+        match self {
+            Self::Timestamp(timestamp) => Some(timestamp),
+            Self::Interval(_) => None,
+        }
+    }
+
+    #[must_use]
+    pub const fn interval(self) -> Option<Interval> {
+        match self {
+            Self::Interval(interval) => Some(interval),
+            Self::Timestamp(_) => None,
+        }
+    }
+}
+
+/// Parses a timestamp that has at least its date, see [`Timestamp::to_datetime`]
+pub fn parse_timestamp(text: &str) -> anyhow::Result<DateTime<FixedOffset>> {
+    let timestamp = CimDateTime::parse(text)?
+        .timestamp()
+        .with_context(|| format!("{text:?} is an interval, not a timestamp"))?;
+    timestamp
+        .to_datetime()
+        .with_context(|| format!("{text:?} has no date"))
+}
+
+/// Parses an interval, see [`Interval::to_duration`]
+pub fn parse_interval(text: &str) -> anyhow::Result<TimeDelta> {
+    let interval = CimDateTime::parse(text)?
+        .interval()
+        .with_context(|| format!("{text:?} is a timestamp, not an interval"))?;
+    Ok(interval.to_duration())
+}
+
+/// A field of digits in `min..=max`, or None if it is all `*`
+fn parse_field<T: TryFrom<u32>>(text: &str, min: u32, max: u32) -> anyhow::Result<Option<T>> {
+    if text.bytes().all(|byte| byte == b'*') {
+        return Ok(None);
+    }
+    ensure!(
+        text.bytes().all(|byte| byte.is_ascii_digit()),
+        "{text:?} is neither digits nor *"
+    );
+
+    let value: u32 = text.parse()?;
+    ensure!(
+        (min..=max).contains(&value),
+        "{value} is not in {min}..={max}"
+    );
+    T::try_from(value)
+        .ok()
+        .with_context(|| format!("{value} is too large"))
+        .map(Some)
+}
+
+/// Six digits, the trailing ones may be `*`
+fn parse_microseconds(text: &str) -> anyhow::Result<Option<Microseconds>> {
+    let digits = text.bytes().take_while(u8::is_ascii_digit).count();
+    ensure!(
+        text.bytes().skip(digits).all(|byte| byte == b'*'),
+        "{text:?} has digits after a *"
+    );
+    if digits == 0 {
+        return Ok(None);
+    }
+
+    let significant: u32 = text.get(..digits).unwrap_or_default().parse()?;
+    let scale = 10u32.pow(u32::try_from(6 - digits)?);
+    Ok(Some(Microseconds {
+        value: significant * scale,
+        digits: u8::try_from(digits)?,
+    }))
+}
+
+impl Timestamp {
+    /// A day past the end of the month is only an error when the year and month are known
+    fn validate_day(&self) -> anyhow::Result<()> {
+        if let (Some(year), Some(month), Some(day)) = (self.year, self.month, self.day) {
+            ensure!(
+                NaiveDate::from_ymd_opt(i32::from(year), u32::from(month), u32::from(day))
+                    .is_some(),
+                "{year:04}-{month:02}-{day:02} is not a date"
+            );
+        }
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn offset(&self) -> Option<FixedOffset> {
+        FixedOffset::east_opt(i32::from(self.offset_minutes) * 60)
+    }
+
+    /// The point in time, the time of day counting from 0 where it is `*`. None without a full date.
+    #[must_use]
+    pub fn to_datetime(&self) -> Option<DateTime<FixedOffset>> {
+        let date = NaiveDate::from_ymd_opt(
+            i32::from(self.year?),
+            u32::from(self.month?),
+            u32::from(self.day?),
+        )?;
+        // chrono writes a leap second as the 59th with the microseconds running past a million
+        let (second, leap) = match self.second.unwrap_or(0) {
+            60 => (59, 1_000_000),
+            second => (second, 0),
+        };
+        let time = date.and_hms_micro_opt(
+            u32::from(self.hour.unwrap_or(0)),
+            u32::from(self.minute.unwrap_or(0)),
+            u32::from(second),
+            self.microseconds.map_or(0, |micros| micros.value) + leap,
+        )?;
+
+        time.and_local_timezone(self.offset()?).single()
+    }
+}
+
+impl Interval {
+    /// The length of the interval, `*` fields counting as 0
+    #[must_use]
+    pub fn to_duration(&self) -> TimeDelta {
+        TimeDelta::days(i64::from(self.days.unwrap_or(0)))
+            + TimeDelta::hours(i64::from(self.hours.unwrap_or(0)))
+            + TimeDelta::minutes(i64::from(self.minutes.unwrap_or(0)))
+            + TimeDelta::seconds(i64::from(self.seconds.unwrap_or(0)))
+            + TimeDelta::microseconds(i64::from(
+                self.microseconds.map_or(0, |micros| micros.value),
+            ))
+    }
+}
+
+/// Writes `value` zero padded to `width`, or `width` `*`s
+fn write_field(
+    f: &mut fmt::Formatter<'_>,
+    value: Option<impl fmt::Display>,
+    width: usize,
+) -> fmt::Result {
+    match value {
+        Some(value) => write!(f, "{value:0width$}"),
+        None => write!(f, "{:*<width$}", ""),
+    }
+}
+
+fn write_microseconds(f: &mut fmt::Formatter<'_>, micros: Option<Microseconds>) -> fmt::Result {
+    let Some(micros) = micros else {
+        return f.write_str("******");
+    };
+    let digits = usize::from(micros.digits);
+    let significant = micros.value / 10u32.pow(6 - u32::from(micros.digits));
+    write!(f, "{significant:0digits$}{:*<rest$}", "", rest = 6 - digits)
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_field(f, self.year, 4)?;
+        write_field(f, self.month, 2)?;
+        write_field(f, self.day, 2)?;
+        write_field(f, self.hour, 2)?;
+        write_field(f, self.minute, 2)?;
+        write_field(f, self.second, 2)?;
+        f.write_str(".")?;
+        write_microseconds(f, self.microseconds)?;
+        let sign = if self.offset_minutes < 0 { '-' } else { '+' };
+        write!(f, "{sign}{:03}", self.offset_minutes.unsigned_abs())
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_field(f, self.days, 8)?;
+        write_field(f, self.hours, 2)?;
+        write_field(f, self.minutes, 2)?;
+        write_field(f, self.seconds, 2)?;
+        f.write_str(".")?;
+        write_microseconds(f, self.microseconds)?;
+        f.write_str(":000")
+    }
+}
+
+impl fmt::Display for CimDateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timestamp(timestamp) => timestamp.fmt(f),
+            Self::Interval(interval) => interval.fmt(f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Datelike, Timelike};
+
+    use super::*;
+
+    #[test]
+    fn utc_offsets() {
+        let east = parse_timestamp("20240315123045.500000+330").unwrap();
+        assert_eq!(east.offset().local_minus_utc(), 330 * 60);
+        assert_eq!(east.to_rfc3339(), "2024-03-15T12:30:45.500+05:30");
+
+        let west = parse_timestamp("20240315123045.000000-030").unwrap();
+        assert_eq!(west.offset().local_minus_utc(), -30 * 60);
+        assert_eq!(west.to_utc().to_rfc3339(), "2024-03-15T13:00:45+00:00");
+    }
+
+    #[test]
+    fn wildcard_fields() {
+        let all = CimDateTime::parse("**************.******+000").unwrap();
+        let timestamp = all.timestamp().unwrap();
+        assert_eq!(timestamp.year, None);
+        assert_eq!(timestamp.second, None);
+        assert_eq!(timestamp.microseconds, None);
+        assert_eq!(timestamp.to_datetime(), None);
+
+        let date_only = CimDateTime::parse("20240315******.******+000").unwrap();
+        let time = date_only.timestamp().unwrap().to_datetime().unwrap();
+        assert_eq!((time.hour(), time.minute(), time.second()), (0, 0, 0));
+    }
+
+    #[test]
+    fn trailing_wildcard_microseconds() {
+        let timestamp = CimDateTime::parse("20240315123045.12****+000")
+            .unwrap()
+            .timestamp()
+            .unwrap();
+        assert_eq!(
+            timestamp.microseconds,
+            Some(Microseconds {
+                value: 120_000,
+                digits: 2
+            })
+        );
+    }
+
+    #[test]
+    fn intervals() {
+        let duration = parse_interval("00000001020304.000005:000").unwrap();
+        assert_eq!(
+            duration,
+            TimeDelta::days(1)
+                + TimeDelta::hours(2)
+                + TimeDelta::minutes(3)
+                + TimeDelta::seconds(4)
+                + TimeDelta::microseconds(5)
+        );
+        parse_timestamp("00000001020304.000005:000").unwrap_err();
+        parse_interval("20240315123045.000000+000").unwrap_err();
+        CimDateTime::parse("00000001020304.000005:060").unwrap_err();
+    }
+
+    #[test]
+    fn leap_second() {
+        let time = parse_timestamp("20161231235960.000000+000").unwrap();
+        assert_eq!((time.hour(), time.minute(), time.second()), (23, 59, 59));
+        assert_eq!(time.nanosecond(), 1_000_000_000);
+        assert_eq!(time.day(), 31);
+    }
+
+    #[test]
+    fn rejected() {
+        // April has 30 days
+        CimDateTime::parse("20240431000000.000000+000").unwrap_err();
+        // A day 31 is fine when the month isn't known
+        CimDateTime::parse("2024**31000000.000000+000").unwrap();
+        CimDateTime::parse("20240315123045.000000+00").unwrap_err();
+        CimDateTime::parse("20240315123045.000000+0000").unwrap_err();
+        CimDateTime::parse("").unwrap_err();
+        CimDateTime::parse("20240315123045.1*2***+000").unwrap_err();
+        CimDateTime::parse("20241315123045.000000+000").unwrap_err();
+        CimDateTime::parse("20240315123045.000000*000").unwrap_err();
+        CimDateTime::parse("2024031512304a.000000+000").unwrap_err();
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for text in [
+            "20240315123045.500000+330",
+            "20240315123045.000000-030",
+            "20240315123045.12****+000",
+            "2024**********.******+000",
+            "**************.******+000",
+            "00000001020304.000005:000",
+            "********020304.******:000",
+        ] {
+            assert_eq!(CimDateTime::parse(text).unwrap().to_string(), text);
+        }
+    }
+}
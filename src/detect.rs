@@ -0,0 +1,284 @@
+use std::{
+    collections::BTreeMap,
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+
+#[cfg(windows)]
+use windows::Win32::System::Com::CoInitialize;
+#[cfg(windows)]
+use wmi::COMLibrary;
+
+#[cfg(windows)]
+use crate::debug_println;
+
+use crate::{
+    checks::{CHECKS, Check},
+    facts::{CachingSource, FactSource},
+    flags::{Finding, FindingKind, Flags},
+    hypervisor::{Hypervisor, VendorTally},
+    profile::ScoringProfile,
+    scheduler::{Timeouts, run_checks},
+    verdict::Verdict,
+};
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone)]
+pub struct DetectOptions {
+    /// Run the checks that need COM (WMI, shell links). Even when set, these are dropped if COM fails to initialize.
+    pub enable_com: bool,
+    pub profile: ScoringProfile,
+    /// Ids of the only checks to run, all of them if empty
+    pub only: Vec<String>,
+    /// Ids of checks not to run
+    pub skip: Vec<String>,
+    /// Deadline of every check, instead of the default for its cost
+    pub check_timeout: Option<Duration>,
+    /// Time the whole run may take. Checks still going when it runs out are reported as timed out.
+    pub budget: Option<Duration>,
+}
+
+impl Default for DetectOptions {
+    fn default() -> Self {
+        Self {
+            enable_com: true,
+            profile: ScoringProfile::default(),
+            only: Vec::new(),
+            skip: Vec::new(),
+            check_timeout: None,
+            budget: None,
+        }
+    }
+}
+
+impl DetectOptions {
+    /// Whether `check` should run under these options
+    #[must_use]
+    pub fn selects(&self, check: &dyn Check) -> bool {
+        let id = check.id();
+        (self.enable_com || !check.needs_com())
+            && (self.only.is_empty() || self.only.iter().any(|only| only == id))
+            && !self.skip.iter().any(|skip| skip == id)
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct CheckReport {
+    pub name: &'static str,
+    pub flags: Flags,
+    /// What the findings in `flags` are worth under the profile, these add up to [`DetectionReport::score`]
+    pub score: i64,
+    pub elapsed: Duration,
+    /// Set if the check failed, in which case `flags` holds its error penalty (if any)
+    pub error: Option<String>,
+    /// Set if the check missed its deadline, `flags` then only holds the timeout penalty
+    pub timed_out: bool,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct DetectionReport {
+    pub score: i64,
+    pub verdict: Verdict,
+    /// Most likely hypervisor, `Unknown` if the verdict points at a VM but no finding named a vendor
+    pub hypervisor: Option<Hypervisor>,
+    /// Penalties attributed to each vendor, heaviest first
+    pub vendors: Vec<VendorTally>,
+    pub checks: Vec<CheckReport>,
+    pub elapsed: Duration,
+    pub com_enabled: bool,
+}
+
+impl DetectionReport {
+    pub fn findings(&self) -> impl Iterator<Item = &Finding> {
+        self.checks.iter().flat_map(|c| c.flags.findings())
+    }
+
+    pub fn penalties(&self) -> impl Iterator<Item = &Finding> {
+        self.checks.iter().flat_map(|c| c.flags.penalties())
+    }
+
+    pub fn bonuses(&self) -> impl Iterator<Item = &Finding> {
+        self.checks.iter().flat_map(|c| c.flags.bonuses())
+    }
+}
+
+/// Sums up the penalties that name a vendor, heaviest vendor first
+fn tally_vendors<'a>(
+    profile: &ScoringProfile,
+    findings: impl IntoIterator<Item = &'a Finding>,
+) -> Vec<VendorTally> {
+    let mut tallies = BTreeMap::new();
+    for finding in findings {
+        if let Some(vendor) = finding.vendor
+            && finding.kind == FindingKind::Penalty
+        {
+            let tally = tallies.entry(vendor).or_insert(VendorTally {
+                vendor,
+                findings: 0,
+                weight: 0,
+            });
+            tally.findings += 1;
+            tally.weight -= profile.finding_value(finding);
+        }
+    }
+
+    let mut tallies = tallies.into_values().collect::<Vec<_>>();
+    tallies.sort_by(|a, b| {
+        b.weight
+            .cmp(&a.weight)
+            .then(b.findings.cmp(&a.findings))
+            .then(a.vendor.cmp(&b.vendor))
+    });
+    tallies
+}
+
+pub fn run_check(
+    check: &dyn Check,
+    source: &dyn FactSource,
+    profile: &ScoringProfile,
+) -> CheckReport {
+    let start = Instant::now();
+    let name = check.name();
+    let mut flags = Flags::new(name);
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| check.run(source, profile, &mut flags)))
+        .unwrap_or_else(|_| Err(anyhow!("check panicked")));
+    let error = result.err().map(|err| {
+        let err = format!("{err:#}");
+        if let Some(level) = check.error_policy().penalty() {
+            flags
+                .penalty(level, format!("{name}.error"), "check failed to run")
+                .evidence(&err);
+        }
+        err
+    });
+
+    CheckReport {
+        name,
+        flags,
+        score: 0,
+        elapsed: start.elapsed(),
+        error,
+        timed_out: false,
+    }
+}
+
+#[cfg(windows)]
+fn init_com() -> bool {
+    // This is very finicky
+    let ret = COMLibrary::new();
+    if ret.is_err() {
+        debug_println!("WARNING: Failed to init COM: {ret:?}");
+
+        if unsafe { CoInitialize(None) }.is_err() {
+            debug_println!("WARNING: Backup COM init failed, removing COM features");
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Initializes COM for the live checks, dropping them from `options` if that fails
+#[cfg(windows)]
+pub fn live_options(options: &DetectOptions) -> DetectOptions {
+    DetectOptions {
+        enable_com: options.enable_com && init_com(),
+        ..options.clone()
+    }
+}
+
+/// Runs every check against the live system
+#[cfg(windows)]
+#[must_use]
+pub fn detect(options: &DetectOptions) -> DetectionReport {
+    detect_with(Arc::new(crate::facts::LiveSource), &live_options(options))
+}
+
+// TODO implement tests running many times and getting the same score
+/// Runs every built-in check against the facts provided by `source`
+#[must_use]
+pub fn detect_with(source: Arc<dyn FactSource>, options: &DetectOptions) -> DetectionReport {
+    detect_checks(source, options, CHECKS)
+}
+
+/// Runs `checks` against the facts provided by `source`.
+///
+/// Slow checks get their own thread, the rest run one after another while those are going. Returns within
+/// the time budget even if checks hang, those are left running in the background. The checks share a single
+/// registry preload.
+#[must_use]
+pub fn detect_checks(
+    source: Arc<dyn FactSource>,
+    options: &DetectOptions,
+    checks: &[&'static dyn Check],
+) -> DetectionReport {
+    let start = Instant::now();
+    let profile = &options.profile;
+
+    let selected = checks
+        .iter()
+        .copied()
+        .filter(|check| options.selects(*check))
+        .collect::<Vec<_>>();
+    let mut checks = run_checks(
+        Arc::new(CachingSource::new(source)),
+        Arc::new(profile.clone()),
+        &selected,
+        Timeouts {
+            check: options.check_timeout,
+            budget: options.budget,
+        },
+    );
+
+    for check in &mut checks {
+        check.score = profile.score(check.flags.findings());
+    }
+    let score = checks.iter().map(|c| c.score).sum();
+    let errored_checks = checks.iter().filter(|c| c.error.is_some()).count();
+    let verdict = profile.verdict.classify(score, errored_checks);
+    let vendors = tally_vendors(profile, checks.iter().flat_map(|c| c.flags.findings()));
+    let hypervisor = vendors.first().map(|t| t.vendor).or_else(|| {
+        matches!(verdict, Verdict::VirtualMachine | Verdict::Suspicious)
+            .then_some(Hypervisor::Unknown)
+    });
+
+    DetectionReport {
+        score,
+        verdict,
+        hypervisor,
+        vendors,
+        checks,
+        elapsed: start.elapsed(),
+        com_enabled: options.enable_com,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reg_export::RegExport;
+
+    #[test]
+    fn check_scores_use_the_profile() {
+        let mut export = RegExport::default();
+        export
+            .import_file(std::path::Path::new("reference/fixtures/vmware.reg"))
+            .unwrap();
+        let options = DetectOptions {
+            profile: ScoringProfile::from_toml("[levels]\nlarge = 1\nextreme = 2\nend_all = 3")
+                .unwrap(),
+            only: vec!["registry".to_owned()],
+            ..DetectOptions::default()
+        };
+
+        let report = detect_with(Arc::new(export.snapshot()), &options);
+        let check = report.checks.first().unwrap();
+        assert!(report.score < 0);
+        assert_eq!(check.score, report.score);
+        assert_ne!(check.score, check.flags.score());
+    }
+}
@@ -1,3 +1,5 @@
+use serde::{Deserialize, Serialize};
+#[cfg(windows)]
 use windows::Devices::{
     Display::{
         DisplayMonitor, DisplayMonitorConnectionKind, DisplayMonitorPhysicalConnectorKind,
@@ -6,7 +8,9 @@ use windows::Devices::{
     Enumeration::DeviceInformation,
 };
 
-use crate::{debug_println, flags::Flags, inspect, util::get_devices_iter};
+use crate::{debug_println, flags::Flags};
+#[cfg(windows)]
+use crate::{inspect, util::get_devices_iter};
 
 // TODO score adapters
 // DisplayAdapterId
@@ -15,148 +19,320 @@ use crate::{debug_println, flags::Flags, inspect, util::get_devices_iter};
 // DisplayAdapterTargetId
 // Gets an opaque ID used by the display adapter to identify which connector the monitor is attached to. This target ID can be used with DisplayConfig APIs.
 
-pub fn score_displays(flags: &mut Flags) -> anyhow::Result<()> {
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct DisplayFacts {
+    pub monitors: Vec<MonitorFacts>,
+    /// Errors for monitors whose properties could not be read
+    pub unreadable: Vec<String>,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MonitorFacts {
+    pub physical_connector: ConnectorKind,
+    pub connection_kind: ConnectionKind,
+    pub usage_kind: UsageKind,
+    pub dolby_vision: bool,
+    pub display_name: String,
+    pub physical_size_in_inches: Option<(f32, f32)>,
+    pub min_luminance_nits: Option<f32>,
+    pub max_luminance_nits: Option<f32>,
+    pub max_average_full_frame_luminance_nits: Option<f32>,
+    pub native_resolution: Option<(i32, i32)>,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConnectorKind {
+    Unknown,
+    /// VGA
+    HD15,
+    AnalogTV,
+    Dvi,
+    Hdmi,
+    Lvds,
+    Sdi,
+    DisplayPort,
+    Other(i32),
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConnectionKind {
+    Internal,
+    Wired,
+    Wireless,
+    Virtual,
+    Other(i32),
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UsageKind {
+    Standard,
+    HeadMounted,
+    SpecialPurpose,
+    Other(i32),
+}
+
+#[cfg(windows)]
+pub fn collect_displays() -> anyhow::Result<DisplayFacts> {
     let selector = DisplayMonitor::GetDeviceSelector()?;
-    let mut valid_displays = 0;
+    let mut facts = DisplayFacts::default();
 
     for display in get_devices_iter(&selector)? {
-        if score_display(&display, flags).is_ok() {
-            valid_displays += 1;
-        } else {
-            flags.large_penalty();
+        match collect_monitor(&display) {
+            Ok(monitor) => facts.monitors.push(monitor),
+            Err(err) => facts.unreadable.push(format!("{err:?}")),
         }
     }
 
-    debug_println!("found {valid_displays} valid displays");
-
-    match valid_displays {
-        0 => flags.extreme_penalty(),
-        2..=4 => flags.small_bonus(),
-        // 1 is fine | but 4 is weird
-        _ => {}
-    }
-
-    Ok(())
+    Ok(facts)
 }
 
-fn score_display(device: &DeviceInformation, flags: &mut Flags) -> anyhow::Result<()> {
+#[cfg(windows)]
+fn collect_monitor(device: &DeviceInformation) -> anyhow::Result<MonitorFacts> {
     let monitor = DisplayMonitor::FromInterfaceIdAsync(&device.Id()?)?.get()?;
 
-    match inspect!(
+    let physical_connector = match inspect!(
         inner,
         "(init) physical connector",
         monitor.PhysicalConnector()
     )? {
-        DisplayMonitorPhysicalConnectorKind::Unknown => {
-            flags.large_penalty();
+        DisplayMonitorPhysicalConnectorKind::Unknown => ConnectorKind::Unknown,
+        DisplayMonitorPhysicalConnectorKind::HD15 => ConnectorKind::HD15,
+        DisplayMonitorPhysicalConnectorKind::AnalogTV => ConnectorKind::AnalogTV,
+        DisplayMonitorPhysicalConnectorKind::Dvi => ConnectorKind::Dvi,
+        DisplayMonitorPhysicalConnectorKind::Hdmi => ConnectorKind::Hdmi,
+        DisplayMonitorPhysicalConnectorKind::Lvds => ConnectorKind::Lvds,
+        DisplayMonitorPhysicalConnectorKind::Sdi => ConnectorKind::Sdi,
+        DisplayMonitorPhysicalConnectorKind::DisplayPort => ConnectorKind::DisplayPort,
+        other => ConnectorKind::Other(other.0),
+    };
+
+    let connection_kind = match inspect!(inner, "connection kind", monitor.ConnectionKind()?) {
+        DisplayMonitorConnectionKind::Internal => ConnectionKind::Internal,
+        DisplayMonitorConnectionKind::Wired => ConnectionKind::Wired,
+        DisplayMonitorConnectionKind::Wireless => ConnectionKind::Wireless,
+        DisplayMonitorConnectionKind::Virtual => ConnectionKind::Virtual,
+        other => ConnectionKind::Other(other.0),
+    };
+
+    let usage_kind = match inspect!(inner, "usage kind", monitor.UsageKind())? {
+        DisplayMonitorUsageKind::Standard => UsageKind::Standard,
+        DisplayMonitorUsageKind::HeadMounted => UsageKind::HeadMounted,
+        DisplayMonitorUsageKind::SpecialPurpose => UsageKind::SpecialPurpose,
+        other => UsageKind::Other(other.0),
+    };
+
+    let display_name = inspect!(inner, "display name", monitor.DisplayName())?.to_string_lossy();
+
+    // "An error code of zero - S_OK - just means that the API returned a null pointer value on the ABI so there was no interface to populate the Ok variant of Result."
+    // https://github.com/microsoft/windows-rs/issues/3322#issuecomment-2408606524
+    // VMware & Vbox: err.code() == HRESULT(0)
+    let physical_size_in_inches = inspect!(
+        inner,
+        "physical size in in",
+        monitor.PhysicalSizeInInches().and_then(|s| s.GetSize())
+    )
+    .ok()
+    .map(|size| (size.Width, size.Height));
+
+    let native_resolution = inspect!(
+        inner,
+        "native res px",
+        monitor.NativeResolutionInRawPixels()
+    )
+    .ok()
+    .map(|resolution| (resolution.Width, resolution.Height));
+
+    Ok(MonitorFacts {
+        physical_connector,
+        connection_kind,
+        usage_kind,
+        dolby_vision: monitor
+            .IsDolbyVisionSupportedInHdrMode()
+            .unwrap_or_default(),
+        display_name,
+        physical_size_in_inches,
+        min_luminance_nits: monitor.MinLuminanceInNits().ok(),
+        max_luminance_nits: inspect!(inner, "max lum nits", monitor.MaxLuminanceInNits()).ok(),
+        max_average_full_frame_luminance_nits: inspect!(
+            inner,
+            "max avg full frame lum nits",
+            monitor.MaxAverageFullFrameLuminanceInNits()
+        )
+        .ok(),
+        native_resolution,
+    })
+}
+
+pub fn score_displays(facts: &DisplayFacts, flags: &mut Flags) {
+    for err in &facts.unreadable {
+        flags
+            .large_penalty("displays.unreadable", "failed to read display properties")
+            .evidence(err);
+    }
+
+    for monitor in &facts.monitors {
+        score_display(monitor, flags);
+    }
+
+    let valid_displays = facts.monitors.len();
+    debug_println!("found {valid_displays} valid displays");
+
+    match valid_displays {
+        0 => {
+            flags.extreme_penalty("displays.none", "no valid displays");
+        }
+        2..=4 => {
+            flags
+                .small_bonus("displays.multiple", "multiple valid displays")
+                .evidence(valid_displays);
+        }
+        // 1 is fine | but 4 is weird
+        _ => {}
+    }
+}
+
+fn score_display(monitor: &MonitorFacts, flags: &mut Flags) {
+    match monitor.physical_connector {
+        ConnectorKind::Unknown => {
+            flags.large_penalty("displays.connector_unknown", "unknown physical connector");
         }
 
-        DisplayMonitorPhysicalConnectorKind::AnalogTV
-        | DisplayMonitorPhysicalConnectorKind::Sdi
-        | DisplayMonitorPhysicalConnectorKind::Lvds => {
+        ConnectorKind::AnalogTV | ConnectorKind::Sdi | ConnectorKind::Lvds => {
             // Maybe increase in the future. This is Composite, SDI (???), or LVDS (???)
-            flags.medium_penalty();
+            let connector = match monitor.physical_connector {
+                ConnectorKind::AnalogTV => "analog tv",
+                ConnectorKind::Sdi => "sdi",
+                _ => "lvds",
+            };
+
+            flags
+                .medium_penalty("displays.connector_unusual", "unusual physical connector")
+                .evidence(connector);
         }
 
-        DisplayMonitorPhysicalConnectorKind::Dvi => {
-            flags.small_penalty();
+        ConnectorKind::Dvi => {
+            flags.small_penalty("displays.connector_dvi", "connected over DVI");
         }
 
-        // VGA
-        DisplayMonitorPhysicalConnectorKind::HD15 => {
+        ConnectorKind::HD15 => {
             // This is the type that VMware & Vbox uses
-            flags.large_penalty();
+            flags.large_penalty("displays.connector_vga", "connected over VGA");
         }
 
-        DisplayMonitorPhysicalConnectorKind::Hdmi
-        | DisplayMonitorPhysicalConnectorKind::DisplayPort => {
-            flags.medium_bonus();
+        ConnectorKind::Hdmi | ConnectorKind::DisplayPort => {
+            flags.medium_bonus(
+                "displays.connector_modern",
+                "connected over HDMI or DisplayPort",
+            );
         }
 
-        _ => flags.large_penalty(),
+        ConnectorKind::Other(kind) => {
+            flags
+                .large_penalty(
+                    "displays.connector_other",
+                    "unrecognized physical connector",
+                )
+                .evidence(kind);
+        }
     }
 
-    match inspect!(inner, "connection kind", monitor.ConnectionKind()?) {
+    match monitor.connection_kind {
         // Laptop
-        DisplayMonitorConnectionKind::Internal => flags.medium_bonus(),
-        DisplayMonitorConnectionKind::Wired => {}
-        DisplayMonitorConnectionKind::Wireless => flags.small_penalty(),
-        DisplayMonitorConnectionKind::Virtual => flags.large_penalty(),
-        _ => flags.medium_penalty(),
+        ConnectionKind::Internal => {
+            flags.medium_bonus("displays.internal", "internal display");
+        }
+        ConnectionKind::Wired => {}
+        ConnectionKind::Wireless => {
+            flags.small_penalty("displays.wireless", "wireless display");
+        }
+        ConnectionKind::Virtual => {
+            flags.large_penalty("displays.virtual", "virtual display");
+        }
+        ConnectionKind::Other(kind) => {
+            flags
+                .medium_penalty("displays.connection_other", "unrecognized connection kind")
+                .evidence(kind);
+        }
     }
 
-    match inspect!(inner, "usage kind", monitor.UsageKind())? {
-        DisplayMonitorUsageKind::Standard => {}
-        // DisplayMonitorUsageKind::HeadMounted | DisplayMonitorUsageKind::SpecialPurpose{
-        _ => flags.large_penalty(),
+    match monitor.usage_kind {
+        UsageKind::Standard => {}
+        UsageKind::HeadMounted => {
+            flags.large_penalty("displays.usage_head_mounted", "head mounted display");
+        }
+        UsageKind::SpecialPurpose => {
+            flags.large_penalty("displays.usage_special_purpose", "special purpose display");
+        }
+        UsageKind::Other(kind) => {
+            flags
+                .large_penalty("displays.usage_other", "unrecognized display usage")
+                .evidence(kind);
+        }
     }
 
-    if monitor
-        .IsDolbyVisionSupportedInHdrMode()
-        .unwrap_or_default()
-    {
-        flags.large_bonus();
+    if monitor.dolby_vision {
+        flags.large_bonus("displays.dolby_vision", "supports Dolby Vision HDR");
     }
 
     // VMware & Vbox fails this
-    if inspect!(inner, "display name", monitor.DisplayName())?.is_empty() {
-        flags.large_penalty();
+    if monitor.display_name.is_empty() {
+        flags.large_penalty("displays.no_name", "display has no name");
     }
 
-    // "An error code of zero - S_OK - just means that the API returned a null pointer value on the ABI so there was no interface to populate the Ok variant of Result."
-    // https://github.com/microsoft/windows-rs/issues/3322#issuecomment-2408606524
-    match inspect!(
-        inner,
-        "physical size in in",
-        monitor.PhysicalSizeInInches().and_then(|s| s.GetSize())
-    ) {
-        Ok(_resolution) => {}
-        Err(_err) => {
-            // VMware & Vbox: err.code() == HRESULT(0)
-            flags.large_penalty();
-        }
+    // VMware & Vbox fails this
+    if monitor.physical_size_in_inches.is_none() {
+        flags.large_penalty("displays.no_physical_size", "display has no physical size");
     }
 
-    let max_luminance = inspect!(inner, "max lum nits", monitor.MaxLuminanceInNits());
-    match (monitor.MinLuminanceInNits(), &max_luminance) {
-        (Ok(0.0), Ok(0.0)) => {
+    match (monitor.min_luminance_nits, monitor.max_luminance_nits) {
+        (Some(0.0), Some(0.0)) => {
             // VMware & Vbox
-            flags.large_penalty();
+            flags.large_penalty("displays.zero_luminance", "display reports zero luminance");
+        }
+        (None, _) | (_, None) => {
+            flags.medium_penalty("displays.no_luminance", "failed to read display luminance");
         }
-        (Err(_), _) | (_, Err(_)) => flags.medium_penalty(),
         _ => {}
     }
 
-    match inspect!(
-        inner,
-        "max avg full frame lum nits",
-        monitor.MaxAverageFullFrameLuminanceInNits()
-    ) {
-        Ok(0.0) | Err(_) => flags.medium_penalty(),
-        Ok(l) => {
+    match monitor.max_average_full_frame_luminance_nits {
+        Some(0.0) | None => {
+            flags.medium_penalty(
+                "displays.no_full_frame_luminance",
+                "no max average full frame luminance",
+            );
+        }
+        Some(l) => {
             // If these match up that's good
-            if let Ok(ml) = &max_luminance {
-                if (l - *ml).abs() < 0.01 {
-                    flags.small_bonus();
+            if let Some(ml) = monitor.max_luminance_nits {
+                if (l - ml).abs() < 0.01 {
+                    flags.small_bonus(
+                        "displays.luminance_consistent",
+                        "full frame and max luminance match",
+                    );
                 } else {
-                    flags.medium_penalty();
+                    flags
+                        .medium_penalty(
+                            "displays.luminance_inconsistent",
+                            "full frame and max luminance differ",
+                        )
+                        .evidence(format!("{l} != {ml}"));
                 }
             }
         }
     }
 
-    match inspect!(
-        inner,
-        "native res px",
-        monitor.NativeResolutionInRawPixels()
-    ) {
-        Ok(resolution) => {
-            score_display_size(resolution.Width, resolution.Height, flags);
+    match monitor.native_resolution {
+        Some((width, height)) => score_display_size(width, height, flags),
+        None => {
+            flags.large_penalty("displays.no_resolution", "failed to read native resolution");
         }
-        Err(_) => flags.large_penalty(),
     }
-
-    Ok(())
 }
 
 // https://store.steampowered.com/hwsurvey
@@ -184,10 +360,19 @@ fn score_display(device: &DeviceInformation, flags: &mut Flags) -> anyhow::Resul
 
 fn score_display_size(width: i32, height: i32, flags: &mut Flags) {
     if width < 256 || height < 256 {
-        flags.extreme_penalty();
+        flags
+            .extreme_penalty("displays.resolution_tiny", "resolution below 256px")
+            .evidence(format!("{width}x{height}"));
     } else if width < 1366 || height < 768 {
-        flags.large_penalty();
+        flags
+            .large_penalty("displays.resolution_low", "resolution below 1366x768")
+            .evidence(format!("{width}x{height}"));
     } else if width < 1920 || height < 1080 {
-        flags.small_penalty();
+        flags
+            .small_penalty(
+                "displays.resolution_below_fhd",
+                "resolution below 1920x1080",
+            )
+            .evidence(format!("{width}x{height}"));
     }
 }
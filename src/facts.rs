@@ -0,0 +1,215 @@
+use std::sync::{Arc, OnceLock};
+
+use anyhow::anyhow;
+
+use crate::registry::{RegistryRules, preload_registry};
+pub use crate::{
+    activated::{
+        ActivationType, KmsHost, LicenseFacts, LicenseState, LicenseStatus, read_kms_host,
+    },
+    battery::{BatteryFacts, BatteryReport},
+    bluetooth_adapters::{BluetoothAdapterFacts, BluetoothFacts},
+    displays::{ConnectionKind, ConnectorKind, DisplayFacts, MonitorFacts, UsageKind},
+    graphics_card::{GraphicsCard, GraphicsCardFacts},
+    installed_apps::{InstalledAppsFacts, InstalledShortcut, LnkShortcutType, UrlShortcutType},
+    microsoft_account::AccountFacts,
+    os::{OsFacts, Win32OperatingSystem},
+    printers::{PrinterFacts, Win32Printer},
+    registry::current_control_set,
+    registry_key::{MemoryKey, RegValue, RegistryKey},
+    registry_plan::Preload,
+    sysinfo::{DiskSpaceReport, SysinfoFacts},
+    system_devices::{PciDevice, SystemDeviceFacts},
+    usb_devices::{UsbDevice, UsbFacts},
+    various_wmi::{
+        CimCard, CimChassis, CimUserDevice, VariousWmiFacts, Win32Bios, Win32ComputerSystem,
+    },
+    wifi_adapters::{WifiFacts, WifiInterface},
+};
+
+/// Where the checks get their raw data from. Scoring only ever sees what is returned here,
+/// so any source (the live system, a recorded snapshot, hand written fixtures) scores the same way.
+pub trait FactSource: Send + Sync {
+    fn system_devices(&self) -> anyhow::Result<SystemDeviceFacts>;
+    fn os(&self) -> anyhow::Result<OsFacts>;
+    fn installed_apps(&self) -> anyhow::Result<InstalledAppsFacts>;
+    fn license(&self) -> anyhow::Result<LicenseFacts>;
+    fn wifi_adapters(&self) -> anyhow::Result<WifiFacts>;
+    fn displays(&self) -> anyhow::Result<DisplayFacts>;
+    fn battery(&self) -> anyhow::Result<BatteryFacts>;
+    fn sysinfo(&self) -> anyhow::Result<SysinfoFacts>;
+    fn usb_devices(&self) -> anyhow::Result<UsbFacts>;
+    /// `HKEY_LOCAL_MACHINE`
+    fn registry(&self) -> anyhow::Result<Box<dyn RegistryKey + '_>>;
+    fn microsoft_account(&self) -> anyhow::Result<AccountFacts>;
+    fn graphics_cards(&self) -> anyhow::Result<GraphicsCardFacts>;
+    fn bluetooth_adapters(&self) -> anyhow::Result<BluetoothFacts>;
+    fn various_wmi(&self) -> anyhow::Result<VariousWmiFacts>;
+    fn printers(&self) -> anyhow::Result<PrinterFacts>;
+
+    /// The registry copied into memory as far as the checks walking it need, loaded again on every call unless
+    /// wrapped in a [`CachingSource`]
+    fn preloaded_registry(&self, rules: &RegistryRules) -> anyhow::Result<Arc<Preload>> {
+        Ok(Arc::new(preload_registry(&*self.registry()?, rules)))
+    }
+}
+
+/// Passes facts through from another source, loading the registry preload once for every check that asks.
+///
+/// The preload is loaded for the rules of the first call, later calls get the same copy whatever their rules.
+pub struct CachingSource {
+    inner: Arc<dyn FactSource>,
+    preload: OnceLock<Result<Arc<Preload>, String>>,
+}
+
+impl CachingSource {
+    #[must_use]
+    pub fn new(inner: Arc<dyn FactSource>) -> Self {
+        Self {
+            inner,
+            preload: OnceLock::new(),
+        }
+    }
+}
+
+impl FactSource for CachingSource {
+    fn system_devices(&self) -> anyhow::Result<SystemDeviceFacts> {
+        self.inner.system_devices()
+    }
+
+    fn os(&self) -> anyhow::Result<OsFacts> {
+        self.inner.os()
+    }
+
+    fn installed_apps(&self) -> anyhow::Result<InstalledAppsFacts> {
+        self.inner.installed_apps()
+    }
+
+    fn license(&self) -> anyhow::Result<LicenseFacts> {
+        self.inner.license()
+    }
+
+    fn wifi_adapters(&self) -> anyhow::Result<WifiFacts> {
+        self.inner.wifi_adapters()
+    }
+
+    fn displays(&self) -> anyhow::Result<DisplayFacts> {
+        self.inner.displays()
+    }
+
+    fn battery(&self) -> anyhow::Result<BatteryFacts> {
+        self.inner.battery()
+    }
+
+    fn sysinfo(&self) -> anyhow::Result<SysinfoFacts> {
+        self.inner.sysinfo()
+    }
+
+    fn usb_devices(&self) -> anyhow::Result<UsbFacts> {
+        self.inner.usb_devices()
+    }
+
+    fn registry(&self) -> anyhow::Result<Box<dyn RegistryKey + '_>> {
+        self.inner.registry()
+    }
+
+    fn microsoft_account(&self) -> anyhow::Result<AccountFacts> {
+        self.inner.microsoft_account()
+    }
+
+    fn graphics_cards(&self) -> anyhow::Result<GraphicsCardFacts> {
+        self.inner.graphics_cards()
+    }
+
+    fn bluetooth_adapters(&self) -> anyhow::Result<BluetoothFacts> {
+        self.inner.bluetooth_adapters()
+    }
+
+    fn various_wmi(&self) -> anyhow::Result<VariousWmiFacts> {
+        self.inner.various_wmi()
+    }
+
+    fn printers(&self) -> anyhow::Result<PrinterFacts> {
+        self.inner.printers()
+    }
+
+    fn preloaded_registry(&self, rules: &RegistryRules) -> anyhow::Result<Arc<Preload>> {
+        self.preload
+            .get_or_init(|| {
+                self.inner
+                    .preloaded_registry(rules)
+                    .map_err(|err| format!("{err:#}"))
+            })
+            .clone()
+            .map_err(|err| anyhow!(err))
+    }
+}
+
+/// Reads everything from the running system
+#[cfg(windows)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, Default)]
+pub struct LiveSource;
+
+#[cfg(windows)]
+impl FactSource for LiveSource {
+    fn system_devices(&self) -> anyhow::Result<SystemDeviceFacts> {
+        crate::system_devices::collect_system_devices()
+    }
+
+    fn os(&self) -> anyhow::Result<OsFacts> {
+        crate::os::collect_os()
+    }
+
+    fn installed_apps(&self) -> anyhow::Result<InstalledAppsFacts> {
+        crate::installed_apps::collect_installed_apps()
+    }
+
+    fn license(&self) -> anyhow::Result<LicenseFacts> {
+        crate::activated::collect_license()
+    }
+
+    fn wifi_adapters(&self) -> anyhow::Result<WifiFacts> {
+        crate::wifi_adapters::collect_wifi_adapters()
+    }
+
+    fn displays(&self) -> anyhow::Result<DisplayFacts> {
+        crate::displays::collect_displays()
+    }
+
+    fn battery(&self) -> anyhow::Result<BatteryFacts> {
+        crate::battery::collect_battery()
+    }
+
+    fn sysinfo(&self) -> anyhow::Result<SysinfoFacts> {
+        Ok(crate::sysinfo::collect_sysinfo())
+    }
+
+    fn usb_devices(&self) -> anyhow::Result<UsbFacts> {
+        crate::usb_devices::collect_usb_devices()
+    }
+
+    fn registry(&self) -> anyhow::Result<Box<dyn RegistryKey + '_>> {
+        Ok(Box::new(windows_registry::LOCAL_MACHINE))
+    }
+
+    fn microsoft_account(&self) -> anyhow::Result<AccountFacts> {
+        crate::microsoft_account::collect_microsoft_account()
+    }
+
+    fn graphics_cards(&self) -> anyhow::Result<GraphicsCardFacts> {
+        crate::graphics_card::collect_graphics_cards()
+    }
+
+    fn bluetooth_adapters(&self) -> anyhow::Result<BluetoothFacts> {
+        crate::bluetooth_adapters::collect_bluetooth_adapters()
+    }
+
+    fn various_wmi(&self) -> anyhow::Result<VariousWmiFacts> {
+        crate::various_wmi::collect_various_wmi()
+    }
+
+    fn printers(&self) -> anyhow::Result<PrinterFacts> {
+        crate::printers::collect_printers()
+    }
+}
@@ -1,13 +1,17 @@
 #![allow(dead_code)]
 
-use std::mem;
 #[cfg(debug_assertions)]
 use std::panic;
+use std::{borrow::Cow, fmt, mem};
 
 use pastey::paste;
+use serde::{Deserialize, Serialize};
+
+use crate::hypervisor::Hypervisor;
 
 #[cfg_attr(debug_assertions, derive(Debug))]
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Level {
     Tiny,
     Small,
@@ -18,7 +22,9 @@ pub enum Level {
 }
 
 impl Level {
-    const fn value(self) -> i64 {
+    /// Built-in weight of this level, see `ScoringProfile` for overriding it
+    #[must_use]
+    pub const fn value(self) -> i64 {
         match self {
             Self::Tiny => 1,
             Self::Small => 3,
@@ -28,17 +34,118 @@ impl Level {
             Self::EndAll => 5000,
         }
     }
+
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Tiny => "tiny",
+            Self::Small => "small",
+            Self::Medium => "medium",
+            Self::Large => "large",
+            Self::Extreme => "extreme",
+            Self::EndAll => "end all",
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingKind {
+    Penalty,
+    Bonus,
+}
+
+impl FindingKind {
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Penalty => "penalty",
+            Self::Bonus => "bonus",
+        }
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize)]
+pub struct Finding {
+    /// Stable identifier of the rule that fired, e.x. `sysinfo.ram_tiny`
+    pub rule_id: Cow<'static, str>,
+    /// Name of the check that produced this finding
+    pub check: &'static str,
+    pub reason: Cow<'static, str>,
+    /// The observed value that triggered the rule, if there was one worth keeping
+    pub evidence: Option<String>,
+    pub level: Level,
+    pub kind: FindingKind,
+    /// Hypervisor this finding points at, if it names one
+    pub vendor: Option<Hypervisor>,
+}
+
+impl Finding {
+    /// Attaches the observed value to this finding
+    pub fn evidence(&mut self, value: impl fmt::Display) -> &mut Self {
+        self.evidence = Some(value.to_string());
+        self
+    }
+
+    pub const fn vendor(&mut self, vendor: Hypervisor) -> &mut Self {
+        self.vendor = Some(vendor);
+        self
+    }
+
+    /// Attributes this finding to whichever hypervisor `text` names, if any
+    pub fn vendor_from(&mut self, text: &str) -> &mut Self {
+        self.vendor = Hypervisor::from_text(text).or(self.vendor);
+        self
+    }
+
+    /// Signed score contribution, negative for penalties
+    #[must_use]
+    pub const fn value(&self) -> i64 {
+        match self.kind {
+            FindingKind::Penalty => -self.level.value(),
+            FindingKind::Bonus => self.level.value(),
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} [{}] {}",
+            self.level,
+            self.kind.name(),
+            self.rule_id,
+            self.reason
+        )?;
+        if let Some(evidence) = &self.evidence {
+            write!(f, " ({evidence})")?;
+        }
+
+        Ok(())
+    }
 }
 
 #[cfg_attr(debug_assertions, derive(Debug))]
 #[derive(Clone)]
 pub struct Flags {
-    penalties: Vec<Level>,
-    bonuses: Vec<Level>,
+    check: &'static str,
+    findings: Vec<Finding>,
+    /// Don't print findings as they are added in debug builds
+    #[cfg_attr(not(debug_assertions), allow(dead_code))]
+    quiet: bool,
 }
 
 #[cfg(debug_assertions)]
-fn print_caller(t: &str, level: Level, location: &panic::Location<'_>) {
+fn print_caller(t: &str, finding: &Finding, location: &panic::Location<'_>) {
     use crate::debug_println;
 
     let color = if t == "PENALTY" {
@@ -54,8 +161,11 @@ fn print_caller(t: &str, level: Level, location: &panic::Location<'_>) {
     };
 
     debug_println!(
-        "{color}{t}{}: {level:?} at {}:{}:{}",
+        "{color}{t}{}: {:?} [{}] {} at {}:{}:{}",
         crate::util::colors::DEFAULT,
+        finding.level,
+        finding.rule_id,
+        finding.reason,
         location.file(),
         location.line(),
         location.column()
@@ -63,69 +173,141 @@ fn print_caller(t: &str, level: Level, location: &panic::Location<'_>) {
 }
 
 impl Flags {
-    pub fn new() -> Self {
+    #[must_use]
+    pub fn new(check: &'static str) -> Self {
+        Self {
+            check,
+            findings: Vec::with_capacity(50),
+            quiet: false,
+        }
+    }
+
+    /// Flags that aren't printed as they are added in debug builds, for findings that are only looked at in bulk
+    #[must_use]
+    pub fn quiet(check: &'static str) -> Self {
+        Self {
+            quiet: true,
+            ..Self::new(check)
+        }
+    }
+
+    /// Empty flags for the same check, as quiet as these
+    #[must_use]
+    pub fn empty_like(&self) -> Self {
         Self {
-            penalties: Vec::with_capacity(50),
-            bonuses: Vec::with_capacity(50),
+            quiet: self.quiet,
+            ..Self::new(self.check)
         }
     }
 
     #[cfg(debug_assertions)]
     #[track_caller]
     #[inline]
-    pub fn penalty(&mut self, level: Level) {
-        print_caller("PENALTY GENERIC", level, panic::Location::caller());
-        self.inner_penalty(level);
+    pub fn penalty(
+        &mut self,
+        level: Level,
+        rule_id: impl Into<Cow<'static, str>>,
+        reason: impl Into<Cow<'static, str>>,
+    ) -> &mut Finding {
+        let location = panic::Location::caller();
+        let quiet = self.quiet;
+        let finding = self.push(FindingKind::Penalty, level, rule_id.into(), reason.into());
+        if !quiet {
+            print_caller("PENALTY GENERIC", finding, location);
+        }
+        finding
     }
 
     #[cfg(not(debug_assertions))]
     #[inline]
-    pub fn penalty(&mut self, level: Level) {
-        self.inner_penalty(level);
+    pub fn penalty(
+        &mut self,
+        level: Level,
+        rule_id: impl Into<Cow<'static, str>>,
+        reason: impl Into<Cow<'static, str>>,
+    ) -> &mut Finding {
+        self.push(FindingKind::Penalty, level, rule_id.into(), reason.into())
     }
 
     #[cfg(debug_assertions)]
     #[track_caller]
     #[inline]
-    pub fn bonus(&mut self, level: Level) {
-        self.inner_bonus(level);
+    pub fn bonus(
+        &mut self,
+        level: Level,
+        rule_id: impl Into<Cow<'static, str>>,
+        reason: impl Into<Cow<'static, str>>,
+    ) -> &mut Finding {
+        self.push(FindingKind::Bonus, level, rule_id.into(), reason.into())
     }
 
     #[cfg(not(debug_assertions))]
     #[inline]
-    pub fn bonus(&mut self, level: Level) {
-        self.inner_bonus(level);
+    pub fn bonus(
+        &mut self,
+        level: Level,
+        rule_id: impl Into<Cow<'static, str>>,
+        reason: impl Into<Cow<'static, str>>,
+    ) -> &mut Finding {
+        self.push(FindingKind::Bonus, level, rule_id.into(), reason.into())
     }
 
     #[inline]
-    fn inner_penalty(&mut self, level: Level) {
-        self.penalties.push(level);
+    fn push(
+        &mut self,
+        kind: FindingKind,
+        level: Level,
+        rule_id: Cow<'static, str>,
+        reason: Cow<'static, str>,
+    ) -> &mut Finding {
+        self.findings.push(Finding {
+            rule_id,
+            check: self.check,
+            reason,
+            evidence: None,
+            level,
+            kind,
+            vendor: None,
+        });
+
+        self.findings.last_mut().expect("just pushed")
     }
 
     #[inline]
-    fn inner_bonus(&mut self, level: Level) {
-        self.bonuses.push(level);
+    #[must_use]
+    pub const fn check(&self) -> &'static str {
+        self.check
     }
 
     #[inline]
-    pub fn penalties(&self) -> &[Level] {
-        &self.penalties
+    #[must_use]
+    pub fn findings(&self) -> &[Finding] {
+        &self.findings
     }
 
-    #[inline]
-    pub fn bonuses(&self) -> &[Level] {
-        &self.bonuses
+    pub fn penalties(&self) -> impl Iterator<Item = &Finding> {
+        self.findings
+            .iter()
+            .filter(|f| f.kind == FindingKind::Penalty)
+    }
+
+    pub fn bonuses(&self) -> impl Iterator<Item = &Finding> {
+        self.findings
+            .iter()
+            .filter(|f| f.kind == FindingKind::Bonus)
     }
 
+    #[must_use]
     pub fn score(&self) -> i64 {
-        let penalty_score: i64 = self.penalties.iter().copied().map(Level::value).sum();
-        let bonus_score: i64 = self.bonuses.iter().copied().map(Level::value).sum();
-        bonus_score - penalty_score
+        self.findings.iter().map(Finding::value).sum()
+    }
+
+    pub fn extend(&mut self, findings: impl IntoIterator<Item = Finding>) {
+        self.findings.extend(findings);
     }
 
     pub fn merge(&mut self, other: &mut Self) {
-        self.penalties.extend(mem::take(&mut other.penalties));
-        self.bonuses.extend(mem::take(&mut other.bonuses));
+        self.findings.extend(mem::take(&mut other.findings));
     }
 }
 
@@ -137,29 +319,55 @@ macro_rules! impl_flags {
                     #[cfg(debug_assertions)]
                     #[track_caller]
                     #[inline]
-                    pub fn [<$name _penalty>](&mut self) {
-                        print_caller("PENALTY", $level, std::panic::Location::caller());
-                        self.inner_penalty($level);
+                    pub fn [<$name _penalty>](
+                        &mut self,
+                        rule_id: impl Into<Cow<'static, str>>,
+                        reason: impl Into<Cow<'static, str>>,
+                    ) -> &mut Finding {
+                        let location = std::panic::Location::caller();
+                        let quiet = self.quiet;
+                        let finding = self.push(FindingKind::Penalty, $level, rule_id.into(), reason.into());
+                        if !quiet {
+                            print_caller("PENALTY", finding, location);
+                        }
+                        finding
                     }
 
                     #[cfg(not(debug_assertions))]
                     #[inline]
-                    pub fn [<$name _penalty>](&mut self) {
-                        self.inner_penalty($level);
+                    pub fn [<$name _penalty>](
+                        &mut self,
+                        rule_id: impl Into<Cow<'static, str>>,
+                        reason: impl Into<Cow<'static, str>>,
+                    ) -> &mut Finding {
+                        self.push(FindingKind::Penalty, $level, rule_id.into(), reason.into())
                     }
 
                     #[cfg(debug_assertions)]
                     #[track_caller]
                     #[inline]
-                    pub fn [<$name _bonus>](&mut self) {
-                        print_caller("BONUS", $level, std::panic::Location::caller());
-                        self.inner_bonus($level);
+                    pub fn [<$name _bonus>](
+                        &mut self,
+                        rule_id: impl Into<Cow<'static, str>>,
+                        reason: impl Into<Cow<'static, str>>,
+                    ) -> &mut Finding {
+                        let location = std::panic::Location::caller();
+                        let quiet = self.quiet;
+                        let finding = self.push(FindingKind::Bonus, $level, rule_id.into(), reason.into());
+                        if !quiet {
+                            print_caller("BONUS", finding, location);
+                        }
+                        finding
                     }
 
                     #[cfg(not(debug_assertions))]
                     #[inline]
-                    pub fn [<$name _bonus>](&mut self) {
-                        self.inner_bonus($level);
+                    pub fn [<$name _bonus>](
+                        &mut self,
+                        rule_id: impl Into<Cow<'static, str>>,
+                        reason: impl Into<Cow<'static, str>>,
+                    ) -> &mut Finding {
+                        self.push(FindingKind::Bonus, $level, rule_id.into(), reason.into())
                     }
                 )*
             }
@@ -1,31 +1,39 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+#[cfg(windows)]
 use wmi::{COMLibrary, WMIConnection};
 
-use crate::flags::Flags;
+use crate::{flags::Flags, hypervisor::Hypervisor};
 
 #[cfg_attr(debug_assertions, derive(Debug))]
-#[derive(Deserialize)]
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct GraphicsCardFacts {
+    pub cards: Vec<GraphicsCard>,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
-struct GraphicsCard {
+pub struct GraphicsCard {
     #[serde(default)]
-    description: String,
+    pub description: String,
     #[serde(default)]
-    caption: String,
+    pub caption: String,
     #[serde(default)]
-    video_processor: String,
+    pub video_processor: String,
     #[serde(default, rename = "DeviceID")]
-    device_id: String,
+    pub device_id: String,
     #[serde(default)]
-    name: String,
+    pub name: String,
     #[serde(default)]
-    installed_display_drivers: String,
+    pub installed_display_drivers: String,
     #[serde(default)]
-    inf_section: String,
+    pub inf_section: String,
     #[serde(default)]
-    adapter_dac_type: String,
+    pub adapter_dac_type: String,
 }
 
-pub fn score_graphics_cards(flags: &mut Flags) -> anyhow::Result<()> {
+#[cfg(windows)]
+pub fn collect_graphics_cards() -> anyhow::Result<GraphicsCardFacts> {
     // https://learn.microsoft.com/en-us/windows/win32/cimwin32prov/win32-videocontroller
     let com_con = unsafe { COMLibrary::assume_initialized() };
     let wmi_con = WMIConnection::new(com_con)?;
@@ -36,28 +44,59 @@ pub fn score_graphics_cards(flags: &mut Flags) -> anyhow::Result<()> {
     // AdapterDACType exists and isn't n/a
 
     // Description, Caption, DitherType, VideoProcessor, DeviceID, Name, InstalledDisplayDrivers, InfSection, StatusInfo, AdapterDACType
-    let graphics_cards = wmi_con.raw_query::<GraphicsCard>("SELECT Description, Caption, VideoProcessor, DeviceID, Name, InstalledDisplayDrivers, InfSection, AdapterDACType FROM Win32_VideoController")?;
-    if graphics_cards.is_empty() {
-        flags.large_penalty();
-        return Ok(());
-    }
+    let cards = wmi_con.raw_query::<GraphicsCard>("SELECT Description, Caption, VideoProcessor, DeviceID, Name, InstalledDisplayDrivers, InfSection, AdapterDACType FROM Win32_VideoController")?;
 
-    for gc in graphics_cards {
-        score_graphics_card(&gc, flags);
+    Ok(GraphicsCardFacts { cards })
+}
+
+pub fn score_graphics_cards(facts: &GraphicsCardFacts, flags: &mut Flags) {
+    if facts.cards.is_empty() {
+        flags.large_penalty("graphics.none", "no graphics cards");
+        return;
     }
 
-    Ok(())
+    for gc in &facts.cards {
+        score_graphics_card(gc, flags);
+    }
 }
 
 // No bonuses here since it's common for hardened VMs to passthrough the host's graphics card
-#[rustfmt::skip]
 fn score_graphics_card(gc: &GraphicsCard, flags: &mut Flags) {
-    if gc.description.contains("VMware") { flags.large_penalty(); }
-    if gc.caption.contains("VMware") { flags.large_penalty(); }
-    if gc.video_processor.contains("VMware") { flags.large_penalty(); }
-    if gc.device_id.contains("VMware") { flags.large_penalty(); }
-    if gc.name.contains("VMware") { flags.large_penalty(); }
-    if gc.installed_display_drivers.contains("vm3dum") { flags.large_penalty(); }
-    if gc.inf_section.contains("VM3D") { flags.large_penalty(); }
-    if gc.adapter_dac_type.is_empty() || gc.adapter_dac_type == "n/a" { flags.large_penalty(); }
+    for (field, value) in [
+        ("Description", &gc.description),
+        ("Caption", &gc.caption),
+        ("VideoProcessor", &gc.video_processor),
+        ("DeviceID", &gc.device_id),
+        ("Name", &gc.name),
+    ] {
+        if value.contains("VMware") {
+            flags
+                .large_penalty("graphics.vmware", "graphics card names VMware")
+                .evidence(format!("{field}: {value}"))
+                .vendor(Hypervisor::VMware);
+        }
+    }
+
+    if gc.installed_display_drivers.contains("vm3dum") {
+        flags
+            .large_penalty("graphics.vm3d_driver", "VMware SVGA 3D display driver")
+            .evidence(&gc.installed_display_drivers)
+            .vendor(Hypervisor::VMware);
+    }
+
+    if gc.inf_section.contains("VM3D") {
+        flags
+            .large_penalty("graphics.vm3d_inf", "VMware SVGA 3D inf section")
+            .evidence(&gc.inf_section)
+            .vendor(Hypervisor::VMware);
+    }
+
+    if gc.adapter_dac_type.is_empty() || gc.adapter_dac_type == "n/a" {
+        flags
+            .large_penalty(
+                "graphics.no_dac_type",
+                "graphics card has no adapter DAC type",
+            )
+            .evidence(&gc.name);
+    }
 }
@@ -0,0 +1,810 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, bail, ensure};
+
+use crate::{
+    facts::{
+        AccountFacts, BatteryFacts, BluetoothFacts, DisplayFacts, FactSource, GraphicsCardFacts,
+        InstalledAppsFacts, LicenseFacts, OsFacts, PrinterFacts, SysinfoFacts, SystemDeviceFacts,
+        UsbFacts, VariousWmiFacts, WifiFacts,
+    },
+    registry_key::{RegValue, RegistryKey, Subkeys, utf16_string},
+    win_time::FileTime,
+};
+
+/// Hive bins start right after the 4KiB base block, cell offsets are relative to this
+const HBIN_START: usize = 0x1000;
+/// Data above this size is split into segments behind a `db` cell
+const BIG_DATA_SEGMENT: usize = 16344;
+/// `ri` lists only ever point at other lists one level down, anything deeper is a corrupt hive
+const MAX_LIST_DEPTH: u32 = 4;
+
+const KEY_COMP_NAME: u16 = 0x0020;
+const VALUE_COMP_NAME: u16 = 0x0001;
+
+fn read<const N: usize>(data: &[u8], offset: usize) -> anyhow::Result<[u8; N]> {
+    data.get(offset..offset + N)
+        .and_then(|b| b.try_into().ok())
+        .context("truncated hive")
+}
+
+fn u16_at(data: &[u8], offset: usize) -> anyhow::Result<u16> {
+    read(data, offset).map(u16::from_le_bytes)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> anyhow::Result<u32> {
+    read(data, offset).map(u32::from_le_bytes)
+}
+
+fn u64_at(data: &[u8], offset: usize) -> anyhow::Result<u64> {
+    read(data, offset).map(u64::from_le_bytes)
+}
+
+fn name(data: &[u8], compressed: bool) -> String {
+    if compressed {
+        // Latin-1
+        data.iter().map(|&b| char::from(b)).collect()
+    } else {
+        utf16_string(data)
+    }
+}
+
+/// A raw registry hive file (`regf`) loaded into memory, e.x. `SYSTEM`, `SOFTWARE`, `NTUSER.DAT` or `BCD`.
+///
+/// Transaction logs are not replayed, so a dirty hive is read as it was last flushed.
+pub struct Hive {
+    data: Vec<u8>,
+    root: u32,
+}
+
+impl Hive {
+    pub fn from_bytes(data: Vec<u8>) -> anyhow::Result<Self> {
+        ensure!(data.starts_with(b"regf"), "not a registry hive");
+        let root = u32_at(&data, 0x24)?;
+        let hive = Self { data, root };
+        hive.node(root).context("bad root key")?;
+        Ok(hive)
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Self::from_bytes(fs::read(path)?).with_context(|| format!("bad hive {}", path.display()))
+    }
+
+    #[must_use]
+    pub const fn root(&self) -> HiveKey<'_> {
+        HiveKey {
+            hive: self,
+            offset: self.root,
+        }
+    }
+
+    /// The data of the cell at `offset`, without its size
+    fn cell(&self, offset: u32) -> anyhow::Result<&[u8]> {
+        ensure!(offset != u32::MAX, "nc");
+        let start = HBIN_START + offset as usize;
+        let size = i32::from_le_bytes(read(&self.data, start)?).unsigned_abs() as usize;
+        ensure!(size >= 4, "bad cell size at {offset:#x}");
+        self.data
+            .get(start + 4..start + size)
+            .context("truncated hive")
+    }
+
+    fn node(&self, offset: u32) -> anyhow::Result<Node<'_>> {
+        let cell = self.cell(offset)?;
+        ensure!(cell.starts_with(b"nk"), "no key at {offset:#x}");
+
+        let flags = u16_at(cell, 0x02)?;
+        let name_len = u16_at(cell, 0x48)? as usize;
+        Ok(Node {
+            last_written: FileTime(u64_at(cell, 0x04)?),
+            subkey_count: u32_at(cell, 0x14)?,
+            subkeys: u32_at(cell, 0x1C)?,
+            value_count: u32_at(cell, 0x24)?,
+            values: u32_at(cell, 0x28)?,
+            name: cell.get(0x4C..0x4C + name_len).context("truncated hive")?,
+            compressed_name: flags & KEY_COMP_NAME != 0,
+        })
+    }
+
+    /// Offsets of the keys in the subkey list at `offset`, following `ri` index roots
+    fn subkey_offsets(&self, offset: u32, depth: u32, out: &mut Vec<u32>) -> anyhow::Result<()> {
+        ensure!(depth <= MAX_LIST_DEPTH, "subkey lists nested too deep");
+        let cell = self.cell(offset)?;
+        let count = u16_at(cell, 0x02)? as usize;
+
+        match cell.get(..2) {
+            // Offset and name hint/hash pairs
+            Some(b"lf" | b"lh") => {
+                for i in 0..count {
+                    out.push(u32_at(cell, 4 + i * 8)?);
+                }
+            }
+            Some(b"li") => {
+                for i in 0..count {
+                    out.push(u32_at(cell, 4 + i * 4)?);
+                }
+            }
+            Some(b"ri") => {
+                for i in 0..count {
+                    self.subkey_offsets(u32_at(cell, 4 + i * 4)?, depth + 1, out)?;
+                }
+            }
+            _ => bail!("bad subkey list at {offset:#x}"),
+        }
+
+        Ok(())
+    }
+
+    fn value(&self, offset: u32) -> anyhow::Result<(String, RegValue)> {
+        let cell = self.cell(offset)?;
+        ensure!(cell.starts_with(b"vk"), "no value at {offset:#x}");
+
+        let name_len = u16_at(cell, 0x02)? as usize;
+        let size = u32_at(cell, 0x04)?;
+        let data_offset = u32_at(cell, 0x08)?;
+        let ty = u32_at(cell, 0x0C)?;
+        let flags = u16_at(cell, 0x10)?;
+        let name = name(
+            cell.get(0x14..0x14 + name_len).context("truncated hive")?,
+            flags & VALUE_COMP_NAME != 0,
+        );
+
+        let len = (size & 0x7FFF_FFFF) as usize;
+        let data = if size & 0x8000_0000 != 0 {
+            // Small data lives in the offset field itself
+            data_offset
+                .to_le_bytes()
+                .get(..len.min(4))
+                .unwrap_or_default()
+                .to_vec()
+        } else if len > BIG_DATA_SEGMENT && self.cell(data_offset)?.starts_with(b"db") {
+            self.big_data(data_offset, len)?
+        } else {
+            self.cell(data_offset)?
+                .get(..len)
+                .context("truncated value data")?
+                .to_vec()
+        };
+
+        Ok((name, RegValue::from_raw(ty, &data)))
+    }
+
+    /// Joins the segments of a `db` record
+    fn big_data(&self, offset: u32, len: usize) -> anyhow::Result<Vec<u8>> {
+        let cell = self.cell(offset)?;
+        let segments = u16_at(cell, 0x02)? as usize;
+        let list = self.cell(u32_at(cell, 0x04)?)?;
+        ensure!(
+            len <= segments * BIG_DATA_SEGMENT,
+            "truncated big data at {offset:#x}"
+        );
+
+        let mut data = Vec::with_capacity(len);
+        for i in 0..segments {
+            let segment = self.cell(u32_at(list, i * 4)?)?;
+            let take = (len - data.len()).min(BIG_DATA_SEGMENT).min(segment.len());
+            data.extend_from_slice(segment.get(..take).unwrap_or_default());
+        }
+        ensure!(data.len() == len, "truncated big data at {offset:#x}");
+
+        Ok(data)
+    }
+}
+
+struct Node<'a> {
+    last_written: FileTime,
+    subkey_count: u32,
+    subkeys: u32,
+    value_count: u32,
+    values: u32,
+    name: &'a [u8],
+    compressed_name: bool,
+}
+
+/// A key inside a [`Hive`]
+#[derive(Clone, Copy)]
+pub struct HiveKey<'a> {
+    hive: &'a Hive,
+    offset: u32,
+}
+
+impl HiveKey<'_> {
+    pub fn name(&self) -> anyhow::Result<String> {
+        let node = self.hive.node(self.offset)?;
+        Ok(name(node.name, node.compressed_name))
+    }
+
+    fn children(&self) -> anyhow::Result<Vec<Self>> {
+        let node = self.hive.node(self.offset)?;
+        // Not preallocated from the count, a corrupt one would abort on allocation
+        let mut offsets = Vec::new();
+        if node.subkey_count != 0 {
+            self.hive.subkey_offsets(node.subkeys, 0, &mut offsets)?;
+        }
+
+        Ok(offsets
+            .into_iter()
+            .map(|offset| Self {
+                hive: self.hive,
+                offset,
+            })
+            .collect())
+    }
+
+    /// Like [`RegistryKey::open`], but the key borrows the hive rather than `self`
+    pub fn open_key(&self, path: &str) -> anyhow::Result<Self> {
+        path.split('\\')
+            .filter(|c| !c.is_empty())
+            .try_fold(*self, |key, component| key.subkey(component))
+    }
+
+    fn subkey(&self, name: &str) -> anyhow::Result<Self> {
+        for key in self.children()? {
+            if key.name()?.eq_ignore_ascii_case(name) {
+                return Ok(key);
+            }
+        }
+
+        bail!("nk")
+    }
+
+    fn value_offsets(&self) -> anyhow::Result<Vec<u32>> {
+        let node = self.hive.node(self.offset)?;
+        if node.value_count == 0 {
+            return Ok(Vec::new());
+        }
+
+        let list = self.hive.cell(node.values)?;
+        (0..node.value_count as usize)
+            .map(|i| u32_at(list, i * 4))
+            .collect()
+    }
+}
+
+impl RegistryKey for HiveKey<'_> {
+    fn open(&self, path: &str) -> anyhow::Result<Box<dyn RegistryKey + '_>> {
+        Ok(Box::new(self.open_key(path)?))
+    }
+
+    fn keys(&self) -> anyhow::Result<Vec<String>> {
+        self.children()?.iter().map(Self::name).collect()
+    }
+
+    fn values(&self) -> anyhow::Result<Vec<(String, RegValue)>> {
+        self.value_offsets()?
+            .into_iter()
+            .map(|offset| self.hive.value(offset))
+            .collect()
+    }
+
+    fn value(&self, name: &str) -> anyhow::Result<RegValue> {
+        for offset in self.value_offsets()? {
+            let (value_name, value) = self.hive.value(offset)?;
+            if value_name.eq_ignore_ascii_case(name) {
+                return Ok(value);
+            }
+        }
+
+        bail!("nv")
+    }
+
+    fn subkeys(&self) -> anyhow::Result<Subkeys<'_>> {
+        Ok(Box::new(self.children()?.into_iter().filter_map(|key| {
+            Some((key.name().ok()?, Box::new(key) as Box<dyn RegistryKey>))
+        })))
+    }
+
+    fn last_written(&self) -> anyhow::Result<FileTime> {
+        Ok(self.hive.node(self.offset)?.last_written)
+    }
+}
+
+/// `NTUSER.DAT` and `UsrClass.dat`, loaded under `HKEY_USERS`
+fn is_user_hive(file_name: &str) -> bool {
+    ["NTUSER.DAT", "USRCLASS.DAT"]
+        .iter()
+        .any(|hive| file_name.eq_ignore_ascii_case(hive))
+}
+
+/// Hive files mounted under the names they have in `HKEY_LOCAL_MACHINE`, so rules written against the live
+/// registry run unchanged
+#[derive(Default)]
+pub struct Hives {
+    mounts: Vec<(String, Hive)>,
+}
+
+impl Hives {
+    pub fn mount(&mut self, name: &str, hive: Hive) {
+        self.mounts.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+        self.mounts.push((name.to_owned(), hive));
+    }
+
+    /// Mounts a hive file under its `HKEY_LOCAL_MACHINE` name, which is the file name except for `BCD`. User
+    /// hives are rejected, they belong under `HKEY_USERS` which no check reads.
+    pub fn mount_file(&mut self, path: &Path) -> anyhow::Result<()> {
+        let file_name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .context("bad hive file name")?
+            .to_uppercase();
+        ensure!(
+            !is_user_hive(&file_name),
+            "{} is a user hive, only HKEY_LOCAL_MACHINE hives can be mounted",
+            path.display()
+        );
+        let name = if file_name == "BCD" {
+            "BCD00000000"
+        } else {
+            &file_name
+        };
+
+        self.mount(name, Hive::load(path)?);
+        Ok(())
+    }
+
+    /// Mounts a hive file, or every hive file in a directory that belongs in `HKEY_LOCAL_MACHINE` (e.x. a copy
+    /// of `System32\config`)
+    pub fn mount_path(&mut self, path: &Path) -> anyhow::Result<()> {
+        if !path.is_dir() {
+            return self.mount_file(path);
+        }
+
+        let mut mounted = false;
+        let mut user_hives = false;
+        for entry in fs::read_dir(path)? {
+            let path = entry?.path();
+            let file_name = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or_default();
+            let is_hive = ["SYSTEM", "SOFTWARE", "SAM", "SECURITY", "HARDWARE", "BCD"]
+                .iter()
+                .any(|hive| file_name.eq_ignore_ascii_case(hive));
+            user_hives |= is_user_hive(file_name);
+
+            if is_hive && path.is_file() {
+                self.mount_file(&path)?;
+                mounted = true;
+            }
+        }
+
+        ensure!(
+            mounted || !user_hives,
+            "{} only has user hives, only HKEY_LOCAL_MACHINE hives can be mounted",
+            path.display()
+        );
+        ensure!(mounted, "no hives in {}", path.display());
+        Ok(())
+    }
+
+    fn hive(&self, name: &str) -> Option<&Hive> {
+        self.mounts
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, hive)| hive)
+    }
+}
+
+impl RegistryKey for Hives {
+    fn open(&self, path: &str) -> anyhow::Result<Box<dyn RegistryKey + '_>> {
+        let path = path.trim_start_matches('\\');
+        let (mount, rest) = path.split_once('\\').unwrap_or((path, ""));
+        Ok(Box::new(
+            self.hive(mount).context("nk")?.root().open_key(rest)?,
+        ))
+    }
+
+    fn keys(&self) -> anyhow::Result<Vec<String>> {
+        Ok(self.mounts.iter().map(|(name, _)| name.clone()).collect())
+    }
+
+    fn values(&self) -> anyhow::Result<Vec<(String, RegValue)>> {
+        Ok(Vec::new())
+    }
+
+    fn value(&self, _name: &str) -> anyhow::Result<RegValue> {
+        bail!("nv")
+    }
+}
+
+const OFFLINE: &str = "not available from registry hives";
+
+/// Only provides the registry, everything else fails
+impl FactSource for Hives {
+    fn system_devices(&self) -> anyhow::Result<SystemDeviceFacts> {
+        bail!(OFFLINE)
+    }
+
+    fn os(&self) -> anyhow::Result<OsFacts> {
+        bail!(OFFLINE)
+    }
+
+    fn installed_apps(&self) -> anyhow::Result<InstalledAppsFacts> {
+        bail!(OFFLINE)
+    }
+
+    fn license(&self) -> anyhow::Result<LicenseFacts> {
+        bail!(OFFLINE)
+    }
+
+    fn wifi_adapters(&self) -> anyhow::Result<WifiFacts> {
+        bail!(OFFLINE)
+    }
+
+    fn displays(&self) -> anyhow::Result<DisplayFacts> {
+        bail!(OFFLINE)
+    }
+
+    fn battery(&self) -> anyhow::Result<BatteryFacts> {
+        bail!(OFFLINE)
+    }
+
+    fn sysinfo(&self) -> anyhow::Result<SysinfoFacts> {
+        bail!(OFFLINE)
+    }
+
+    fn usb_devices(&self) -> anyhow::Result<UsbFacts> {
+        bail!(OFFLINE)
+    }
+
+    fn registry(&self) -> anyhow::Result<Box<dyn RegistryKey + '_>> {
+        Ok(Box::new(self))
+    }
+
+    fn microsoft_account(&self) -> anyhow::Result<AccountFacts> {
+        bail!(OFFLINE)
+    }
+
+    fn graphics_cards(&self) -> anyhow::Result<GraphicsCardFacts> {
+        bail!(OFFLINE)
+    }
+
+    fn bluetooth_adapters(&self) -> anyhow::Result<BluetoothFacts> {
+        bail!(OFFLINE)
+    }
+
+    fn various_wmi(&self) -> anyhow::Result<VariousWmiFacts> {
+        bail!(OFFLINE)
+    }
+
+    fn printers(&self) -> anyhow::Result<PrinterFacts> {
+        bail!(OFFLINE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WRITTEN: u64 = 133_000_000_000_000_000;
+
+    /// Appends cells to a single hive bin and wraps it in a base block
+    struct Builder {
+        bin: Vec<u8>,
+    }
+
+    impl Builder {
+        fn new() -> Self {
+            // The hbin header, cell offsets start at the bin
+            Self { bin: vec![0; 0x20] }
+        }
+
+        fn cell(&mut self, data: &[u8]) -> u32 {
+            let offset = self.bin.len();
+            let size = (data.len() + 4).next_multiple_of(8);
+            self.bin
+                .extend_from_slice(&(-i32::try_from(size).unwrap()).to_le_bytes());
+            self.bin.extend_from_slice(data);
+            self.bin.resize(offset + size, 0);
+            u32::try_from(offset).unwrap()
+        }
+
+        fn list(&mut self, signature: [u8; 2], offsets: &[u32]) -> u32 {
+            let mut cell = signature.to_vec();
+            cell.extend_from_slice(&u16::try_from(offsets.len()).unwrap().to_le_bytes());
+            for offset in offsets {
+                cell.extend_from_slice(&offset.to_le_bytes());
+                if matches!(&signature, b"lf" | b"lh") {
+                    // Name hint or hash, not read
+                    cell.extend_from_slice(&[0; 4]);
+                }
+            }
+            self.cell(&cell)
+        }
+
+        /// A key named `name`, Latin-1 if `compressed` and UTF-16 otherwise
+        fn key(&mut self, name: &str, compressed: bool, subkeys: &[u32], values: &[u32]) -> u32 {
+            let list = match subkeys {
+                [] => u32::MAX,
+                _ => self.list(*b"lf", subkeys),
+            };
+            self.key_with_list(name, compressed, subkeys.len(), list, values)
+        }
+
+        fn key_with_list(
+            &mut self,
+            name: &str,
+            compressed: bool,
+            subkey_count: usize,
+            list: u32,
+            values: &[u32],
+        ) -> u32 {
+            let value_list = if values.is_empty() {
+                u32::MAX
+            } else {
+                let offsets = values
+                    .iter()
+                    .flat_map(|v| v.to_le_bytes())
+                    .collect::<Vec<_>>();
+                self.cell(&offsets)
+            };
+            let name = encode(name, compressed);
+
+            let mut cell = vec![0; 0x4C];
+            cell.splice(..2, *b"nk");
+            let flags = if compressed { KEY_COMP_NAME } else { 0 };
+            let fields: [(usize, &[u8]); 7] = [
+                (0x02, &flags.to_le_bytes()),
+                (0x04, &WRITTEN.to_le_bytes()),
+                (0x14, &u32::try_from(subkey_count).unwrap().to_le_bytes()),
+                (0x1C, &list.to_le_bytes()),
+                (0x24, &u32::try_from(values.len()).unwrap().to_le_bytes()),
+                (0x28, &value_list.to_le_bytes()),
+                (0x48, &u16::try_from(name.len()).unwrap().to_le_bytes()),
+            ];
+            for (at, bytes) in fields {
+                cell.splice(at..at + bytes.len(), bytes.iter().copied());
+            }
+            cell.extend_from_slice(&name);
+            self.cell(&cell)
+        }
+
+        /// A value with its data in the offset field, a data cell or a `db` record depending on its size
+        fn value(&mut self, name: &str, compressed: bool, ty: u32, data: &[u8]) -> u32 {
+            let (size, data_offset) = if data.len() <= 4 {
+                let mut resident = [0; 4];
+                resident
+                    .get_mut(..data.len())
+                    .unwrap()
+                    .copy_from_slice(data);
+                (data.len() | 0x8000_0000, u32::from_le_bytes(resident))
+            } else if data.len() > BIG_DATA_SEGMENT {
+                let segments = data
+                    .chunks(BIG_DATA_SEGMENT)
+                    .map(|segment| self.cell(segment))
+                    .collect::<Vec<_>>();
+                let list = self.cell(
+                    &segments
+                        .iter()
+                        .flat_map(|s| s.to_le_bytes())
+                        .collect::<Vec<_>>(),
+                );
+                let mut db = b"db".to_vec();
+                db.extend_from_slice(&u16::try_from(segments.len()).unwrap().to_le_bytes());
+                db.extend_from_slice(&list.to_le_bytes());
+                (data.len(), self.cell(&db))
+            } else {
+                (data.len(), self.cell(data))
+            };
+            let name = encode(name, compressed);
+
+            let mut cell = b"vk".to_vec();
+            cell.extend_from_slice(&u16::try_from(name.len()).unwrap().to_le_bytes());
+            cell.extend_from_slice(&u32::try_from(size).unwrap().to_le_bytes());
+            cell.extend_from_slice(&data_offset.to_le_bytes());
+            cell.extend_from_slice(&ty.to_le_bytes());
+            let flags = if compressed { VALUE_COMP_NAME } else { 0 };
+            cell.extend_from_slice(&flags.to_le_bytes());
+            cell.extend_from_slice(&[0; 2]);
+            cell.extend_from_slice(&name);
+            self.cell(&cell)
+        }
+
+        fn finish(mut self, root: u32) -> Vec<u8> {
+            let size = self.bin.len().next_multiple_of(0x1000);
+            self.bin.resize(size, 0);
+            self.bin.splice(..4, *b"hbin");
+
+            let mut data = vec![0; HBIN_START];
+            data.splice(..4, *b"regf");
+            data.splice(0x24..0x28, root.to_le_bytes());
+            data.extend_from_slice(&self.bin);
+            data
+        }
+    }
+
+    fn encode(name: &str, compressed: bool) -> Vec<u8> {
+        if compressed {
+            name.chars().map(|c| u8::try_from(c).unwrap()).collect()
+        } else {
+            name.encode_utf16().flat_map(u16::to_le_bytes).collect()
+        }
+    }
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16()
+            .chain([0])
+            .flat_map(u16::to_le_bytes)
+            .collect()
+    }
+
+    /// Reads every key and value below `key`, errors are fine but nothing may panic
+    fn walk(key: &dyn RegistryKey, depth: u32) {
+        if depth > 8 {
+            return;
+        }
+        _ = key.values();
+        _ = key.last_written();
+        if let Ok(subkeys) = key.subkeys() {
+            for (_, subkey) in subkeys {
+                walk(&*subkey, depth + 1);
+            }
+        }
+    }
+
+    /// A root with one key per kind of subkey list, the last behind an `ri` index root
+    fn subkey_lists() -> Vec<u8> {
+        let mut b = Builder::new();
+        let fast = b.key("Fast", true, &[], &[]);
+        let hash = b.key("Hash", true, &[], &[]);
+        let index = b.key("Index", true, &[], &[]);
+        let rooted = b.key("Rooted", true, &[], &[]);
+        let below_ri = b.list(*b"li", &[rooted]);
+        let lists = [
+            b.list(*b"lf", &[fast]),
+            b.list(*b"lh", &[hash]),
+            b.list(*b"li", &[index]),
+            b.list(*b"ri", &[below_ri]),
+        ];
+
+        let parents = lists
+            .iter()
+            .zip(["lf", "lh", "li", "ri"])
+            .map(|(&list, name)| b.key_with_list(name, true, 1, list, &[]))
+            .collect::<Vec<_>>();
+        let root = b.key("ROOT", true, &parents, &[]);
+        b.finish(root)
+    }
+
+    #[test]
+    fn keys() {
+        let hive = Hive::from_bytes(subkey_lists()).unwrap();
+        let root = hive.root();
+        assert_eq!(root.name().unwrap(), "ROOT");
+        assert_eq!(root.keys().unwrap(), ["lf", "lh", "li", "ri"]);
+        assert_eq!(root.open("lf").unwrap().keys().unwrap(), ["Fast"]);
+        assert_eq!(root.open("lh").unwrap().keys().unwrap(), ["Hash"]);
+        assert_eq!(root.open("li").unwrap().keys().unwrap(), ["Index"]);
+        assert_eq!(
+            root.open("RI\\rooted").unwrap().keys().unwrap(),
+            Vec::<String>::new()
+        );
+        root.open("ri\\Missing").map(drop).unwrap_err();
+        assert_eq!(root.last_written().unwrap(), FileTime(WRITTEN));
+
+        let mut b = Builder::new();
+        let latin = b.key("Gerät", true, &[], &[]);
+        let wide = b.key("Überwachung €", false, &[], &[]);
+        let root = b.key("ROOT", true, &[latin, wide], &[]);
+        let hive = Hive::from_bytes(b.finish(root)).unwrap();
+        assert_eq!(hive.root().keys().unwrap(), ["Gerät", "Überwachung €"]);
+        hive.root().open("Überwachung €").unwrap();
+    }
+
+    #[test]
+    fn values() {
+        let big = (0..40_000_u32)
+            .map(|i| i.to_le_bytes()[0])
+            .collect::<Vec<_>>();
+
+        let mut b = Builder::new();
+        let values = [
+            b.value("Current", true, 4, &2_u32.to_le_bytes()),
+            b.value("Short", true, 3, &[1, 2]),
+            b.value("Größe", false, 1, &utf16("VMware Virtual disk")),
+            b.value("Path", true, 2, &utf16("%SystemRoot%\\system32")),
+            b.value("Ids", true, 7, &utf16("PCI\\VEN_15AD\0PCI\\VEN_80EE\0")),
+            b.value("Stamp", true, 11, &WRITTEN.to_le_bytes()),
+            b.value("Big", true, 3, &big),
+        ];
+        let root = b.key("ROOT", true, &[], &values);
+        let hive = Hive::from_bytes(b.finish(root)).unwrap();
+        let root = hive.root();
+
+        assert_eq!(root.value("current").unwrap(), RegValue::Dword(2));
+        assert_eq!(root.value("Short").unwrap(), RegValue::Binary(vec![1, 2]));
+        assert_eq!(
+            root.value("Größe").unwrap(),
+            RegValue::String("VMware Virtual disk".to_owned())
+        );
+        assert_eq!(
+            root.value("Path").unwrap(),
+            RegValue::ExpandString("%SystemRoot%\\system32".to_owned())
+        );
+        assert_eq!(
+            root.value("Ids").unwrap(),
+            RegValue::MultiString(vec!["PCI\\VEN_15AD".to_owned(), "PCI\\VEN_80EE".to_owned()])
+        );
+        assert_eq!(root.value("Stamp").unwrap(), RegValue::Qword(WRITTEN));
+        assert_eq!(root.value("Big").unwrap(), RegValue::Binary(big));
+        root.value("Missing").unwrap_err();
+
+        let names = root
+            .values()
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            ["Current", "Short", "Größe", "Path", "Ids", "Stamp", "Big"]
+        );
+    }
+
+    #[test]
+    fn corrupt_hives() {
+        Hive::from_bytes(Vec::new()).map(drop).unwrap_err();
+        Hive::from_bytes(b"not a hive".to_vec())
+            .map(drop)
+            .unwrap_err();
+
+        let mut data = subkey_lists();
+        data.splice(0x24..0x28, 0xFFFF_0000_u32.to_le_bytes());
+        Hive::from_bytes(data).map(drop).unwrap_err();
+
+        // Lists and values that point past the end, at the wrong kind of cell or back at themselves
+        let mut b = Builder::new();
+        let past_end = b.key_with_list("PastEnd", true, 1, 0x00FF_0000, &[]);
+        let not_a_list = b.key("NotAList", true, &[], &[]);
+        let wrong_list = b.key_with_list("WrongList", true, 1, not_a_list, &[]);
+        // The next cell, so the index root lists itself
+        let ri = u32::try_from(b.bin.len()).unwrap();
+        b.list(*b"ri", &[ri]);
+        let looped = b.key_with_list("Looped", true, 1, ri, &[]);
+        let bad_value = b.cell(b"vk");
+        let values = b.key("Values", true, &[], &[bad_value, 0x00FF_0000]);
+        let root = b.key("ROOT", true, &[past_end, wrong_list, looped, values], &[]);
+        let hive = Hive::from_bytes(b.finish(root)).unwrap();
+        let root = hive.root();
+        for name in ["PastEnd", "WrongList", "Looped"] {
+            root.open(name).unwrap().keys().unwrap_err();
+        }
+        root.open("Values").unwrap().values().unwrap_err();
+        root.open("Values").unwrap().value("x").unwrap_err();
+
+        // Truncating or overwriting any byte of a hive gives errors, never a panic
+        let data = subkey_lists();
+        for len in 0..data.len() {
+            if let Ok(hive) = Hive::from_bytes(data.get(..len).unwrap().to_vec()) {
+                walk(&hive.root(), 0);
+            }
+        }
+        for at in HBIN_START..data.len() {
+            for byte in [0x00, 0x7F, 0xFF] {
+                let mut data = data.clone();
+                *data.get_mut(at).unwrap() = byte;
+                if let Ok(hive) = Hive::from_bytes(data) {
+                    walk(&hive.root(), 0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mount_file_rejects_user_hives() {
+        let dir = std::env::temp_dir().join(format!("vm-detect-hives-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["NTUSER.DAT", "UsrClass.dat"] {
+            fs::write(dir.join(name), subkey_lists()).unwrap();
+            Hives::default().mount_file(&dir.join(name)).unwrap_err();
+        }
+        Hives::default().mount_path(&dir).unwrap_err();
+
+        fs::write(dir.join("SYSTEM"), subkey_lists()).unwrap();
+        let mut hives = Hives::default();
+        hives.mount_path(&dir).unwrap();
+        assert_eq!(hives.keys().unwrap(), ["SYSTEM"]);
+        hives.open("SYSTEM\\ri\\Rooted").unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
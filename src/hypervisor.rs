@@ -0,0 +1,118 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Hypervisor {
+    VMware,
+    VirtualBox,
+    HyperV,
+    Qemu,
+    Parallels,
+    Xen,
+    /// Looks like a virtual machine, but nothing named the vendor
+    Unknown,
+}
+
+// Checked in order, so the more specific names go first
+const MARKERS: &[(Hypervisor, &[&str])] = &[
+    (
+        Hypervisor::VMware,
+        &["vmware", "vmw", "vid_0e0f", "ven_15ad", "vid_15ad"],
+    ),
+    (
+        Hypervisor::VirtualBox,
+        &[
+            "virtualbox",
+            "vbox",
+            "innotek",
+            "oracle",
+            "vid_80ee",
+            "ven_80ee",
+        ],
+    ),
+    (
+        Hypervisor::Parallels,
+        &["parallels", "prl_", "vid_203a", "ven_1ab8"],
+    ),
+    (
+        Hypervisor::Qemu,
+        &["qemu", "kvm", "bochs", "virtio", "vid_46f4", "ven_1af4"],
+    ),
+    (Hypervisor::Xen, &["xen"]),
+    (
+        Hypervisor::HyperV,
+        &[
+            "hyper-v",
+            "vmbus",
+            "virtual machine",
+            "microsoft hv",
+            "vrtual",
+        ],
+    ),
+];
+
+impl Hypervisor {
+    pub const ALL: [Self; 7] = [
+        Self::VMware,
+        Self::VirtualBox,
+        Self::HyperV,
+        Self::Qemu,
+        Self::Parallels,
+        Self::Xen,
+        Self::Unknown,
+    ];
+
+    /// Guesses the vendor named by a string seen on the system, e.x. a device description
+    #[must_use]
+    pub fn from_text(text: &str) -> Option<Self> {
+        let text = text.to_lowercase();
+        MARKERS
+            .iter()
+            .find(|(_, markers)| markers.iter().any(|marker| text.contains(marker)))
+            .map(|(vendor, _)| *vendor)
+    }
+
+    /// Vendor owning a USB vendor id
+    #[must_use]
+    pub const fn from_usb_vendor(vendor_id: u16) -> Option<Self> {
+        match vendor_id {
+            0x0E0F | 0x15AD => Some(Self::VMware),
+            0x80EE => Some(Self::VirtualBox),
+            0x203A => Some(Self::Parallels),
+            0x46F4 => Some(Self::Qemu),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::VMware => "VMware",
+            Self::VirtualBox => "VirtualBox",
+            Self::HyperV => "Hyper-V",
+            Self::Qemu => "QEMU/KVM",
+            Self::Parallels => "Parallels",
+            Self::Xen => "Xen",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for Hypervisor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// How much evidence pointed at one vendor
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
+pub struct VendorTally {
+    pub vendor: Hypervisor,
+    pub findings: usize,
+    /// Sum of the penalties attributed to this vendor, as a positive number
+    pub weight: i64,
+}
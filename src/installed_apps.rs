@@ -1,11 +1,11 @@
-use std::{
-    ffi::OsStr,
-    fs,
-    path::{Path, PathBuf},
-    ptr::null_mut,
-};
+use std::{ffi::OsStr, path::PathBuf};
+#[cfg(windows)]
+use std::{fs, path::Path, ptr::null_mut};
 
+#[cfg(windows)]
 use anyhow::{Context, bail};
+use serde::{Deserialize, Serialize};
+#[cfg(windows)]
 use windows::{
     Win32::{
         Foundation::MAX_PATH,
@@ -15,11 +15,88 @@ use windows::{
     },
     core::{GUID, Interface},
 };
+#[cfg(windows)]
 use windows_core::PCWSTR;
 
-use crate::{debug_println, flags::Flags};
+use crate::{
+    debug_println,
+    flags::Flags,
+    profile::{bucket, ensure_ascending},
+};
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct InstalledAppsFacts {
+    /// `.lnk` and `.url` files in the start menu, `None` if it was empty or unreadable
+    pub shortcuts: Option<Vec<InstalledShortcut>>,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
+pub struct InstalledShortcut {
+    pub path: PathBuf,
+    pub url: Option<UrlShortcutType>,
+    pub lnk: Option<LnkShortcutType>,
+}
+
+/// Bucket bounds are inclusive upper bounds, anything above the last one lands in the top bucket
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InstalledAppsThresholds {
+    pub programs: ProgramBuckets,
+    pub steam_games: SteamGameBuckets,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProgramBuckets {
+    pub none: u32,
+    pub few: u32,
+    pub normal: u32,
+}
+
+impl Default for ProgramBuckets {
+    fn default() -> Self {
+        Self {
+            none: 2,
+            few: 5,
+            normal: 11,
+        }
+    }
+}
 
-pub fn score_installed_apps(flags: &mut Flags) -> anyhow::Result<()> {
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SteamGameBuckets {
+    pub none: u32,
+    pub few: u32,
+    pub normal: u32,
+}
+
+impl Default for SteamGameBuckets {
+    fn default() -> Self {
+        Self {
+            none: 0,
+            few: 2,
+            normal: 6,
+        }
+    }
+}
+
+impl InstalledAppsThresholds {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let programs = &self.programs;
+        ensure_ascending("programs", &[programs.none, programs.few, programs.normal])?;
+        let games = &self.steam_games;
+        ensure_ascending("steam games", &[games.none, games.few, games.normal])
+    }
+}
+
+#[cfg(windows)]
+pub fn collect_installed_apps() -> anyhow::Result<InstalledAppsFacts> {
     let programs_dir = dirs::data_dir()
         .context("ndd")?
         .join("Microsoft")
@@ -29,35 +106,64 @@ pub fn score_installed_apps(flags: &mut Flags) -> anyhow::Result<()> {
 
     let mut installed = Vec::with_capacity(10);
     if recurse_dir(&programs_dir, &mut installed).is_err() || installed.is_empty() {
-        flags.large_penalty();
-        return Ok(());
+        return Ok(InstalledAppsFacts::default());
     }
 
+    let shortcuts = installed
+        .into_iter()
+        .filter_map(|p| {
+            // lnk or url
+            let ext = p
+                .extension()
+                .and_then(OsStr::to_str)
+                .map(str::to_lowercase)?;
+
+            let url = if ext == "url" {
+                validate_url(&p).ok()
+            } else if ext == "lnk" {
+                None
+            } else {
+                return None;
+            };
+
+            Some(InstalledShortcut {
+                lnk: validate_lnk(&p).ok(),
+                url,
+                path: p,
+            })
+        })
+        .collect();
+
+    Ok(InstalledAppsFacts {
+        shortcuts: Some(shortcuts),
+    })
+}
+
+pub fn score_installed_apps(
+    facts: &InstalledAppsFacts,
+    thresholds: &InstalledAppsThresholds,
+    flags: &mut Flags,
+) {
+    let Some(shortcuts) = &facts.shortcuts else {
+        flags.large_penalty(
+            "apps.no_start_menu",
+            "start menu programs are empty or unreadable",
+        );
+        return;
+    };
+
     let mut found_steam_exe = false;
     let mut steam_games = 0u32;
 
     let mut valid_programs = 0u32;
 
-    for p in installed {
-        // lnk or url
-        let Some(ext) = p.extension().and_then(OsStr::to_str).map(str::to_lowercase) else {
-            continue;
-        };
-
-        if ext == "url" {
-            if matches!(validate_url(&p), Ok(UrlShortcutType::Steam(_))) {
-                steam_games += 1;
-            }
-        } else if ext != "lnk" {
-            continue;
+    for shortcut in shortcuts {
+        if matches!(shortcut.url, Some(UrlShortcutType::Steam(_))) {
+            steam_games += 1;
         }
 
-        let Ok(shortcut) = validate_lnk(&p) else {
-            continue;
-        };
-
         // TODO maybe make use of the other types eventually
-        let LnkShortcutType::ExistingExecutable(exe_path) = shortcut else {
+        let Some(LnkShortcutType::ExistingExecutable(exe_path)) = &shortcut.lnk else {
             continue;
         };
 
@@ -80,24 +186,53 @@ pub fn score_installed_apps(flags: &mut Flags) -> anyhow::Result<()> {
     );
 
     if found_steam_exe {
-        match steam_games {
-            0 => flags.large_penalty(),
-            1..3 => flags.small_penalty(),
-            3..=6 => {}
-            _ => flags.medium_bonus(),
+        let games = &thresholds.steam_games;
+        match bucket(&steam_games, &[games.none, games.few, games.normal]) {
+            0 => {
+                flags.large_penalty(
+                    "apps.steam_no_games",
+                    "steam is installed without any games",
+                );
+            }
+            1 => {
+                flags
+                    .small_penalty("apps.steam_few_games", "steam has few games")
+                    .evidence(steam_games);
+            }
+            2 => {}
+            _ => {
+                flags
+                    .medium_bonus("apps.steam_many_games", "steam has many games")
+                    .evidence(steam_games);
+            }
         }
     }
 
-    match valid_programs {
-        0..=2 => flags.large_penalty(),
-        3..=5 => flags.medium_penalty(),
-        6..=11 => {}
-        _ => flags.medium_bonus(),
+    let programs = &thresholds.programs;
+    match bucket(
+        &valid_programs,
+        &[programs.none, programs.few, programs.normal],
+    ) {
+        0 => {
+            flags
+                .large_penalty("apps.programs_none", "almost no installed programs")
+                .evidence(valid_programs);
+        }
+        1 => {
+            flags
+                .medium_penalty("apps.programs_few", "few installed programs")
+                .evidence(valid_programs);
+        }
+        2 => {}
+        _ => {
+            flags
+                .medium_bonus("apps.programs_many", "many installed programs")
+                .evidence(valid_programs);
+        }
     }
-
-    Ok(())
 }
 
+#[cfg(windows)]
 fn recurse_dir(dir: &Path, ret: &mut Vec<PathBuf>) -> anyhow::Result<()> {
     for entry in dir.read_dir()? {
         let entry = entry?;
@@ -112,13 +247,15 @@ fn recurse_dir(dir: &Path, ret: &mut Vec<PathBuf>) -> anyhow::Result<()> {
     Ok(())
 }
 
-#[allow(dead_code)]
-enum UrlShortcutType {
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
+pub enum UrlShortcutType {
     Steam(String),
     Web(String),
     Other(String),
 }
 
+#[cfg(windows)]
 fn validate_url(path: &Path) -> anyhow::Result<UrlShortcutType> {
     let s = fs::read_to_string(path)?;
 
@@ -151,6 +288,7 @@ fn validate_url(path: &Path) -> anyhow::Result<UrlShortcutType> {
     }
 }
 
+#[cfg(windows)]
 const CLSID_SHELL_LINK: GUID = GUID::from_values(
     0x00021401,
     0x0000,
@@ -158,13 +296,15 @@ const CLSID_SHELL_LINK: GUID = GUID::from_values(
     [0xC0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46],
 );
 
-#[allow(dead_code)]
-enum LnkShortcutType {
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
+pub enum LnkShortcutType {
     ExistingExecutable(PathBuf),
     NonExistingExecutable(PathBuf),
     SystemApplication(PathBuf),
 }
 
+#[cfg(windows)]
 fn validate_lnk(path: &Path) -> anyhow::Result<LnkShortcutType> {
     // Done in main
     // let ret = unsafe { CoInitialize(None) };
@@ -0,0 +1,342 @@
+use std::fmt::Write;
+
+use anyhow::ensure;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    debug_println,
+    flags::Flags,
+    os::registry_install_date,
+    registry::current_control_set,
+    registry_key::{RegValue, RegistryKey},
+    registry_plan::Plan,
+    win_time::FileTime,
+};
+
+/// Hardware and driver keys, with how many levels of subkeys are read under each
+const ROOTS: &[(&str, usize)] = &[("Enum", 3), ("Control\\Class", 2), ("Services", 1)];
+const BIOS: &str = "HARDWARE\\DESCRIPTION\\System\\BIOS";
+/// Where the install date is read from
+const CURRENT_VERSION: &str = "SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion";
+const MAX_KEYS: usize = 20_000;
+
+/// Histogram buckets, by the inclusive upper bound of how long after install a key was written
+const BUCKETS: &[(&str, TimeDelta)] = &[
+    ("1h", TimeDelta::hours(1)),
+    ("1d", TimeDelta::days(1)),
+    ("1w", TimeDelta::weeks(1)),
+    ("30d", TimeDelta::days(30)),
+];
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyTimestampThresholds {
+    /// Keys written this many days after install changed after setup
+    pub settle_days: i64,
+    /// Fewer keys with a timestamp than this is too little to judge
+    pub min_keys: usize,
+    /// Penalize when at most this percent of the keys changed after setup
+    pub frozen_percent: usize,
+    /// Bonus when at least this percent of the keys changed after setup...
+    pub changed_percent: usize,
+    /// ...and the last change is at least this many days after install
+    pub changed_days: i64,
+}
+
+impl Default for KeyTimestampThresholds {
+    fn default() -> Self {
+        Self {
+            settle_days: 7,
+            min_keys: 50,
+            frozen_percent: 2,
+            changed_percent: 10,
+            changed_days: 90,
+        }
+    }
+}
+
+impl KeyTimestampThresholds {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        ensure!(self.min_keys > 0, "min_keys must be above 0");
+        ensure!(
+            self.frozen_percent < self.changed_percent,
+            "frozen_percent must be below changed_percent"
+        );
+        ensure!(
+            self.settle_days <= self.changed_days,
+            "settle_days must not be above changed_days"
+        );
+        Ok(())
+    }
+}
+
+/// Adds what the check reads to the registry preload, the install date, shutdown time and the key timestamps
+pub fn plan_key_timestamps(plan: &mut Plan, control_set: &str) {
+    plan.at(CURRENT_VERSION).read_values();
+    let control_set_plan = plan.at(&format!("SYSTEM\\{control_set}"));
+    control_set_plan.at("Control\\Windows").read_values();
+    for (path, depth) in ROOTS {
+        let mut level = control_set_plan.at(path).read_times();
+        for _ in 0..*depth {
+            level = level.every().read_times();
+        }
+    }
+    plan.at(BIOS).read_times();
+}
+
+/// Last write times of the keys under `key`, down to `depth` levels of subkeys
+fn collect(key: &dyn RegistryKey, depth: usize, out: &mut Vec<DateTime<Utc>>) {
+    if out.len() >= MAX_KEYS {
+        return;
+    }
+    if let Some(written) = key.last_written().ok().and_then(FileTime::to_datetime) {
+        out.push(written);
+    }
+    if depth == 0 {
+        return;
+    }
+
+    for (_, sub_key) in key.subkeys().into_iter().flatten() {
+        if out.len() >= MAX_KEYS {
+            return;
+        }
+        collect(&*sub_key, depth - 1, out);
+    }
+}
+
+/// When the machine was last shut down, from `Control\Windows`
+fn shutdown_time(control_set: &dyn RegistryKey) -> Option<DateTime<Utc>> {
+    let key = control_set.open("Control\\Windows").ok()?;
+    let RegValue::Binary(data) = key.value("ShutdownTime").ok()? else {
+        return None;
+    };
+
+    FileTime::from_le_bytes(&data)?.to_datetime()
+}
+
+fn histogram(install: DateTime<Utc>, written: &[DateTime<Utc>]) -> String {
+    let mut counts = vec![0; BUCKETS.len() + 2];
+    for &time in written {
+        let since = time - install;
+        let index = if since < TimeDelta::zero() {
+            0
+        } else {
+            BUCKETS
+                .iter()
+                .position(|(_, bound)| since <= *bound)
+                .map_or(BUCKETS.len() + 1, |i| i + 1)
+        };
+        if let Some(count) = counts.get_mut(index) {
+            *count += 1;
+        }
+    }
+
+    let names = std::iter::once("before")
+        .chain(BUCKETS.iter().map(|(name, _)| *name))
+        .chain(["later"]);
+    let mut out = String::new();
+    for (name, count) in names.zip(counts) {
+        let separator = if out.is_empty() { "" } else { ", " };
+        let _ = write!(out, "{separator}{name}: {count}");
+    }
+    out
+}
+
+pub fn score_key_timestamps(
+    local_machine: &dyn RegistryKey,
+    thresholds: &KeyTimestampThresholds,
+    flags: &mut Flags,
+) -> anyhow::Result<()> {
+    let install = registry_install_date(local_machine)?;
+    let control_set =
+        local_machine.open(&format!("SYSTEM\\{}", current_control_set(local_machine)))?;
+
+    let mut written = Vec::new();
+    for (path, depth) in ROOTS {
+        if let Ok(key) = control_set.open(path) {
+            collect(&*key, *depth, &mut written);
+        }
+    }
+    if let Ok(bios) = local_machine.open(BIOS) {
+        collect(&*bios, 0, &mut written);
+    }
+    // Stripped exports and hives without timestamps have nothing to judge, which is not an error
+    if written.len() < thresholds.min_keys {
+        debug_println!("only {} key timestamps", written.len());
+        return Ok(());
+    }
+
+    let histogram = histogram(install, &written);
+    debug_println!("key writes since install: {histogram}");
+
+    let newest = written.iter().max().copied().unwrap_or(install);
+    let last_used = shutdown_time(&*control_set).map_or(newest, |shutdown| shutdown.max(newest));
+    let settled = install + TimeDelta::days(thresholds.settle_days);
+    // Too young to tell a machine that never changed from one that hasn't had the time to
+    if last_used <= settled {
+        return Ok(());
+    }
+
+    let changed = written.iter().filter(|&&time| time > settled).count();
+    let percent = changed * 100 / written.len();
+    if changed == 0 {
+        flags
+            .large_penalty(
+                "key_timestamps.frozen",
+                "no hardware key changed after setup",
+            )
+            .evidence(histogram);
+    } else if percent <= thresholds.frozen_percent {
+        flags
+            .medium_penalty(
+                "key_timestamps.mostly_frozen",
+                "almost no hardware key changed after setup",
+            )
+            .evidence(histogram);
+    } else if percent >= thresholds.changed_percent
+        && newest - install >= TimeDelta::days(thresholds.changed_days)
+    {
+        flags
+            .small_bonus(
+                "key_timestamps.changed_for_months",
+                "hardware keys kept changing for months after setup",
+            )
+            .evidence(histogram);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry_key::MemoryKey;
+
+    const INSTALL: &str = "2024-01-01T00:00:00Z";
+
+    fn utc(text: &str) -> DateTime<Utc> {
+        text.parse().unwrap()
+    }
+
+    /// A registry installed at [`INSTALL`] with a `Services` key written at each of `written`
+    fn registry(written: &[DateTime<Utc>]) -> MemoryKey {
+        let mut local_machine = MemoryKey::default();
+        local_machine.subkey_mut(CURRENT_VERSION).set_value(
+            "InstallTime",
+            RegValue::Qword(FileTime::from_datetime(utc(INSTALL)).unwrap().0),
+        );
+        let services = local_machine.subkey_mut("SYSTEM\\ControlSet001\\Services");
+        for (index, &time) in written.iter().enumerate() {
+            services
+                .subkey_mut(&format!("Service{index:03}"))
+                .last_written = FileTime::from_datetime(time);
+        }
+        local_machine
+    }
+
+    /// Finding ids for `after_setup` of 100 keys written a year after install, the rest an hour after it
+    fn score(after_setup: usize) -> Vec<String> {
+        let written = (0..100)
+            .map(|index| {
+                let since = if index < after_setup {
+                    TimeDelta::days(365)
+                } else {
+                    TimeDelta::hours(1)
+                };
+                utc(INSTALL) + since
+            })
+            .collect::<Vec<_>>();
+        let mut flags = Flags::new("key-timestamps");
+        score_key_timestamps(
+            &registry(&written),
+            &KeyTimestampThresholds::default(),
+            &mut flags,
+        )
+        .unwrap();
+        flags
+            .findings()
+            .iter()
+            .map(|f| f.rule_id.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn histogram_buckets() {
+        let install = utc(INSTALL);
+        let written = [
+            install - TimeDelta::seconds(1),
+            install,
+            install + TimeDelta::hours(1),
+            install + TimeDelta::hours(2),
+            install + TimeDelta::days(1),
+            install + TimeDelta::days(2),
+            install + TimeDelta::days(30),
+            install + TimeDelta::days(31),
+        ];
+        assert_eq!(
+            histogram(install, &written),
+            "before: 1, 1h: 2, 1d: 2, 1w: 1, 30d: 1, later: 1"
+        );
+        assert_eq!(
+            histogram(install, &[]),
+            "before: 0, 1h: 0, 1d: 0, 1w: 0, 30d: 0, later: 0"
+        );
+    }
+
+    #[test]
+    fn thresholds() {
+        // The shutdown time isn't recorded, so the newest key tells how long the machine was used
+        assert_eq!(score(0), Vec::<String>::new());
+        assert_eq!(score(1), ["key_timestamps.mostly_frozen"]);
+        assert_eq!(score(2), ["key_timestamps.mostly_frozen"]);
+        assert_eq!(score(3), Vec::<String>::new());
+        assert_eq!(score(9), Vec::<String>::new());
+        assert_eq!(score(10), ["key_timestamps.changed_for_months"]);
+    }
+
+    #[test]
+    fn frozen_after_use() {
+        let mut local_machine = registry(&[utc(INSTALL) + TimeDelta::hours(1); 100]);
+        local_machine
+            .subkey_mut("SYSTEM\\ControlSet001\\Control\\Windows")
+            .set_value(
+                "ShutdownTime",
+                RegValue::Binary(
+                    FileTime::from_datetime(utc("2025-01-01T00:00:00Z"))
+                        .unwrap()
+                        .0
+                        .to_le_bytes()
+                        .to_vec(),
+                ),
+            );
+
+        let mut flags = Flags::new("key-timestamps");
+        score_key_timestamps(
+            &local_machine,
+            &KeyTimestampThresholds::default(),
+            &mut flags,
+        )
+        .unwrap();
+        let ids = flags
+            .findings()
+            .iter()
+            .map(|f| &*f.rule_id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["key_timestamps.frozen"]);
+    }
+
+    #[test]
+    fn too_few_keys() {
+        let local_machine = registry(&[utc(INSTALL) + TimeDelta::hours(1); 10]);
+        let mut flags = Flags::new("key-timestamps");
+        score_key_timestamps(
+            &local_machine,
+            &KeyTimestampThresholds::default(),
+            &mut flags,
+        )
+        .unwrap();
+        assert!(flags.findings().is_empty());
+    }
+}
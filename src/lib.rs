@@ -0,0 +1,53 @@
+#![feature(stmt_expr_attributes)]
+
+mod activated;
+mod auto_logon;
+mod battery;
+mod bluetooth_adapters;
+pub mod checks;
+pub mod cim_datetime;
+mod detect;
+mod displays;
+pub mod facts;
+pub mod flags;
+mod graphics_card;
+pub mod hive;
+pub mod hypervisor;
+mod installed_apps;
+mod key_timestamps;
+pub mod license_keys;
+mod microsoft_account;
+mod os;
+pub mod output;
+mod printers;
+pub mod product_key;
+pub mod profile;
+pub mod reg_export;
+mod registry;
+mod registry_key;
+mod registry_macros;
+mod registry_plan;
+pub mod rules_lint;
+mod scheduler;
+pub mod snapshot;
+mod sysinfo;
+mod system_devices;
+mod usb_devices;
+mod util;
+mod various_wmi;
+mod verdict;
+mod wifi_adapters;
+pub mod win_time;
+
+#[cfg(windows)]
+pub use detect::detect;
+pub use detect::{CheckReport, DetectOptions, DetectionReport, detect_checks, detect_with};
+pub use verdict::{Verdict, VerdictThresholds};
+
+// TODO check across many (real) systems
+// TODO check across virtual box, hyperv, (and maybe even UTM?)
+// TODO check on laptop
+
+// TODO use obfstr or use build step to do all strings
+
+// TODO get rid of unused windows crate features
@@ -1,211 +1,305 @@
-#![feature(stmt_expr_attributes)]
-
-use windows::Win32::System::Com::CoInitialize;
-use wmi::COMLibrary;
-
-use crate::{
-    activated::{ActivationType, get_windows_license_type},
-    auto_logon::is_auto_logon_enabled,
-    battery::get_battery,
-    bluetooth_adapters::score_bluetooth_adapters,
-    displays::score_displays,
-    flags::Flags,
-    graphics_card::score_graphics_cards,
-    installed_apps::score_installed_apps,
-    microsoft_account::has_microsoft_account,
-    os::score_os,
-    printers::score_printers,
-    registry::score_registry,
-    sysinfo::score_sysinfo,
-    system_devices::score_system_devices,
-    usb_devices::score_usb_devices,
-    various_wmi::score_various_wmi,
-    wifi_adapters::score_wifi_adapters,
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
 };
 
-mod activated;
-mod auto_logon;
-mod battery;
-mod bluetooth_adapters;
-mod displays;
-mod flags;
-mod graphics_card;
-mod installed_apps;
-mod microsoft_account;
-mod os;
-mod printers;
-mod registry;
-mod registry_macros;
-mod sysinfo;
-mod system_devices;
-mod usb_devices;
-mod util;
-mod various_wmi;
-mod wifi_adapters;
-
-// TODO check across many (real) systems
-// TODO check across virtual box, hyperv, (and maybe even UTM?)
-// TODO check on laptop
-
-// TODO use obfstr or use build step to do all strings
-
-// TODO get rid of unused windows crate features
-// TODO implement tests running many times and getting the same score
-fn execute() -> Flags {
-    let mut flags = Flags::new();
-    let mut enable_com_features = true;
-
-    // This is very finicky
-    let ret = COMLibrary::new();
-    if ret.is_err() {
-        debug_println!("WARNING: Failed to init COM: {ret:?}");
-
-        if unsafe { CoInitialize(None) }.is_err() {
-            debug_println!("WARNING: Backup COM init failed, removing COM features");
-            enable_com_features = false;
-        }
+use anyhow::{Context, bail};
+use vm_detect::{
+    DetectOptions, DetectionReport, checks,
+    facts::{RegistryKey, current_control_set},
+    hive::Hives,
+    output::{self, Format},
+    profile::{ControlSets, KeyDatabase, RegistryRules, ScoringProfile},
+    reg_export::RegExport,
+    rules_lint::{self, Severity},
+    snapshot::Snapshot,
+};
+
+/// Checks that only read the registry, the default for `hives`
+const REGISTRY_CHECKS: [&str; 4] = ["registry", "auto-logon", "key-timestamps", "product-key"];
+/// The default for `reg`, exports also cover the PCI devices the system devices check reads. Exports are often
+/// of a few subtrees, so a check is left out when the export doesn't have the key it reads or any of the values in it.
+const REG_EXPORT_CHECKS: [(&str, &str, &[&str]); 4] = [
+    ("registry", "", &[]),
+    (
+        "auto-logon",
+        "SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion\\Winlogon",
+        &["AutoAdminLogon"],
+    ),
+    (
+        "system-devices",
+        "SYSTEM\\{CurrentControlSet}\\Enum\\PCI",
+        &[],
+    ),
+    (
+        "product-key",
+        "SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion",
+        &["DigitalProductId4", "DigitalProductId"],
+    ),
+];
+/// Where `rules lint` looks for fixtures when none are given
+const FIXTURES: &str = "reference/fixtures";
+
+const USAGE: &str = "usage: vm-detect [options] [capture <snapshot.json> | replay <snapshot.json> | hives <hive or dir>... | reg <file.reg>... | rules lint [fixture dir]...]
+
+options:
+  --profile <profile.toml>  load weights and thresholds from a scoring profile
+  --rules <rules.toml>      run the registry rules in this TOML or JSON file instead of the built-in ones
+  --all-control-sets        run the registry rules on every control set, not just the current one
+  --keys <keys.toml>        match the license against the pirated keys in this TOML or JSON file
+  --only <ids>              only run these checks (comma separated, repeatable)
+  --skip <ids>              don't run these checks (comma separated, repeatable)
+  --format <format>         text (default), json or markdown
+  --threshold <score>       scores below this are a virtual machine
+  --no-com                  skip the checks that need COM (WMI, shell links)
+  --timeout-ms <ms>         time budget for the whole run, checks still going are reported as timed out
+  --check-timeout-ms <ms>   deadline of every check, instead of the default for its cost
+  --verbose                 list every finding and the score of every check in text output
+  --help                    print this and the check ids";
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Default)]
+struct Cli {
+    options: DetectOptions,
+    format: Format,
+    verbose: bool,
+    help: bool,
+    /// The `--rules` file, loaded into the profile unless the rules are being linted
+    rules: Option<PathBuf>,
+    command: Vec<String>,
+}
+
+impl Cli {
+    fn is_lint(&self) -> bool {
+        self.command
+            .get(..2)
+            .is_some_and(|c| c == ["rules", "lint"])
     }
+}
 
-    // VERY SLOW CHECK: Takes 150-400ms
-    let system_devices_t = std::thread::spawn(|| {
-        let mut f = Flags::new();
-        if inspect!("system devices", score_system_devices(&mut f)).is_err() {
-            f.large_penalty();
-        }
-        f
-    });
-
-    // SLOW CHECK: Takes 60-150ms
-    // BUT: CANNOT be threaded, because it uses COM
-    #[rustfmt::skip]
-    let os_t = std::thread::spawn(move || {
-        let mut f = Flags::new();
-        if enable_com_features 
-            && inspect!("os", score_os(&mut f)).is_err() {
-                f.large_penalty();
+fn check_ids(value: &str) -> anyhow::Result<Vec<String>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| {
+            if checks::by_id(id).is_none() {
+                bail!("unknown check {id}, see --help for the list");
+            }
+            Ok(id.to_owned())
+        })
+        .collect()
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> anyhow::Result<Cli> {
+    let mut cli = Cli::default();
+    let mut threshold = None;
+    let mut all_control_sets = false;
+    let mut keys = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().with_context(|| format!("{arg} needs a value"));
+        match arg.as_str() {
+            "--profile" => {
+                cli.options.profile = ScoringProfile::load(&PathBuf::from(value()?))?;
+            }
+            "--rules" => cli.rules = Some(PathBuf::from(value()?)),
+            "--all-control-sets" => all_control_sets = true,
+            "--keys" => keys = Some(PathBuf::from(value()?)),
+            "--only" => cli.options.only.extend(check_ids(&value()?)?),
+            "--skip" => cli.options.skip.extend(check_ids(&value()?)?),
+            "--format" => cli.format = value()?.parse()?,
+            "--threshold" => {
+                threshold = Some(value()?.parse::<i64>().context("bad --threshold")?);
             }
-        f
-    });
-
-    // SLOW CHECK: Takes ~40-400ms
-    #[rustfmt::skip]
-    let installed_apps_t = std::thread::spawn(move || {
-        let mut f = Flags::new();
-        if enable_com_features
-            && inspect!("installed apps", score_installed_apps(&mut f)).is_err() {
-                    f.large_penalty();
+            "--no-com" => cli.options.enable_com = false,
+            "--timeout-ms" => {
+                let ms = value()?.parse().context("bad --timeout-ms")?;
+                cli.options.budget = Some(Duration::from_millis(ms));
             }
-        f
-    });
-
-    // VERY SLOW CHECK: Takes ~300ms
-    let license_type_t = std::thread::spawn(|| {
-        let mut f = Flags::new();
-        match inspect!("license type", get_windows_license_type()).unwrap_or_default() {
-            ActivationType::LikelyGenuine => f.medium_bonus(),
-            ActivationType::Pirated => f.small_penalty(),
-            ActivationType::Unlicensed => f.medium_penalty(),
+            "--check-timeout-ms" => {
+                let ms = value()?.parse().context("bad --check-timeout-ms")?;
+                cli.options.check_timeout = Some(Duration::from_millis(ms));
+            }
+            "--verbose" | "-v" => cli.verbose = true,
+            "--help" | "-h" => cli.help = true,
+            _ if arg.starts_with('-') => bail!("unknown option {arg}"),
+            _ => cli.command.push(arg),
         }
-        f
-    });
-
-    if inspect!("wifi adapters", score_wifi_adapters(&mut flags)).is_err() {
-        flags.medium_penalty();
     }
 
-    if inspect!("displays", score_displays(&mut flags)).is_err() {
-        flags.large_penalty();
+    // Applied last so they win over the profile regardless of the order of the options
+    if let Some(rules) = &cli.rules
+        && !cli.is_lint()
+    {
+        cli.options.profile.registry = RegistryRules::load(rules)?;
     }
-
-    if inspect!("battery info", get_battery()).unwrap_or_default() {
-        flags.extreme_bonus();
+    if let Some(keys) = keys {
+        cli.options.profile.license_keys = KeyDatabase::load(&keys)?;
     }
-
-    if inspect!("sysinfo", score_sysinfo(&mut flags)).is_err() {
-        flags.large_penalty();
+    if all_control_sets {
+        cli.options.profile.registry.control_sets = ControlSets::All;
     }
-
-    if inspect!("usb devices", score_usb_devices(&mut flags)).is_err() {
-        flags.large_penalty();
+    if let Some(threshold) = threshold {
+        let verdict = &mut cli.options.profile.verdict;
+        verdict.virtual_machine_below = threshold;
+        verdict.suspicious_below = verdict.suspicious_below.max(threshold);
     }
 
-    score_registry(&mut flags);
+    Ok(cli)
+}
 
-    // SLOW CHECK: Takes ~66ms
-    if enable_com_features {
-        if inspect!("micorosft account", has_microsoft_account()).unwrap_or_default() {
-            flags.large_bonus();
+fn print_help() {
+    println!("{USAGE}\n\nchecks:");
+    for check in checks::CHECKS {
+        let com = if check.needs_com() {
+            " (needs COM)"
         } else {
-            flags.small_penalty();
-        }
+            ""
+        };
+        println!("  {:<18}{}{com}", check.id(), check.name());
     }
+}
 
-    if enable_com_features {
-        // this can be spoofed, and either way laptops can have discrete graphics cards
-        if inspect!("graphics card", score_graphics_cards(&mut flags)).is_err() {
-            flags.medium_penalty();
-        }
-    }
+fn print_report(cli: &Cli, report: &DetectionReport) -> anyhow::Result<i32> {
+    let verbose = cli.verbose || cfg!(debug_assertions);
+    print!("{}", output::render(report, cli.format, verbose)?);
+    Ok(report.verdict.exit_code())
+}
 
-    if inspect!("bluetooth", score_bluetooth_adapters(&mut flags)).is_err() {
-        flags.large_penalty();
+/// Lints the rules and runs them against the fixtures, the exit code is 1 if anything is wrong
+fn lint(cli: &Cli, fixture_dirs: &[&str]) -> anyhow::Result<i32> {
+    let (rules, mut problems) = rules_lint::lint_rules(cli.rules.as_deref())?;
+    let fixture_dirs = if fixture_dirs.is_empty() {
+        &[FIXTURES][..]
+    } else {
+        fixture_dirs
+    };
+    for dir in fixture_dirs {
+        problems.extend(rules_lint::run_fixtures(&rules, Path::new(dir))?);
     }
 
-    // SLOW CHECK: Takes ~53ms
-    #[rustfmt::skip]
-    if enable_com_features
-         && inspect!("various wmi", score_various_wmi(&mut flags)).is_err() {
-                flags.large_penalty();
-        }
-
-    if inspect!("auto logon", is_auto_logon_enabled()).unwrap_or_default() {
-        flags.medium_penalty();
+    for problem in &problems {
+        println!("{problem}");
     }
+    let count = |severity| problems.iter().filter(|p| p.severity == severity).count();
+    let errors = count(Severity::Error);
+    println!(
+        "{} rules, {errors} errors, {} warnings",
+        rules.rules.len(),
+        count(Severity::Warning)
+    );
+
+    Ok(i32::from(errors > 0))
+}
 
-    if inspect!("printers", score_printers(&mut flags)).is_err() {
-        flags.medium_penalty();
+fn main() -> anyhow::Result<()> {
+    let cli = parse_args(std::env::args().skip(1))?;
+    if cli.help {
+        print_help();
+        return Ok(());
     }
-
-    match system_devices_t.join() {
-        Ok(mut f) => flags.merge(&mut f),
-        Err(why) => {
-            debug_println!("failed to join system devices thread: {why:?}");
-        }
+    if cli.is_lint() {
+        let dirs = cli
+            .command
+            .iter()
+            .skip(2)
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        std::process::exit(lint(&cli, &dirs)?);
     }
 
-    match os_t.join() {
-        Ok(mut f) => flags.merge(&mut f),
-        Err(why) => {
-            debug_println!("failed to join os thread: {why:?}");
+    let options = &cli.options;
+    let report = match cli
+        .command
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        #[cfg(windows)]
+        [] => vm_detect::detect(options),
+        #[cfg(windows)]
+        ["capture", out] => {
+            let (report, snapshot) = vm_detect::snapshot::capture(options);
+            snapshot.save(Path::new(out))?;
+            report
         }
-    }
-
-    match installed_apps_t.join() {
-        Ok(mut f) => flags.merge(&mut f),
-        Err(why) => {
-            debug_println!("failed to join installed apps thread: {why:?}");
+        ["replay", snapshot] => {
+            let snapshot = Snapshot::load(Path::new(snapshot))?;
+            let options = DetectOptions {
+                enable_com: options.enable_com && snapshot.com_enabled,
+                ..options.clone()
+            };
+            vm_detect::detect_with(Arc::new(snapshot), &options)
         }
-    }
+        ["hives", paths @ ..] if !paths.is_empty() => {
+            let mut hives = Hives::default();
+            for path in paths {
+                hives.mount_path(Path::new(path))?;
+            }
 
-    match license_type_t.join() {
-        Ok(mut f) => flags.merge(&mut f),
-        Err(why) => {
-            debug_println!("failed to join license type thread: {why:?}");
+            let mut options = options.clone();
+            if options.only.is_empty() {
+                options.only = REGISTRY_CHECKS.map(str::to_owned).to_vec();
+            }
+            vm_detect::detect_with(Arc::new(hives), &options)
         }
-    }
-
-    flags
-}
-
-fn main() {
-    let start = std::time::Instant::now();
-    let flags = execute();
-
-    debug_println!("penalties: {:?}", flags.penalties());
-    debug_println!("bonuses: {:?}", flags.bonuses());
+        ["reg", paths @ ..] if !paths.is_empty() => {
+            let mut export = RegExport::default();
+            for path in paths {
+                export.import_file(Path::new(path))?;
+            }
+            for warning in &export.warnings {
+                eprintln!("skipped {warning}");
+            }
 
-    // TODO decide value to choose if finally detected
-    println!("score: {}", flags.score());
+            let snapshot = export.snapshot();
+            let mut options = DetectOptions {
+                enable_com: false,
+                ..options.clone()
+            };
+            if options.only.is_empty() {
+                options.only = REG_EXPORT_CHECKS
+                    .iter()
+                    .filter(|(id, path, values)| {
+                        let path = path.replace(
+                            "{CurrentControlSet}",
+                            &current_control_set(&snapshot.registry),
+                        );
+                        let exported = path.is_empty()
+                            || snapshot.registry.open(&path).is_ok_and(|key| {
+                                values.is_empty()
+                                    || values.iter().any(|value| key.value(value).is_ok())
+                            });
+                        if !exported {
+                            if values.is_empty() {
+                                eprintln!("skipped {id}, the export has no {path}");
+                            } else {
+                                eprintln!(
+                                    "skipped {id}, the export has no {} under {path}",
+                                    values.join(" or ")
+                                );
+                            }
+                        }
+                        exported
+                    })
+                    .map(|(id, ..)| (*id).to_owned())
+                    .collect();
+            }
+            vm_detect::detect_with(Arc::new(snapshot), &options)
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            if cfg!(not(windows)) {
+                eprintln!("live detection and capture are only supported on windows");
+            }
+            std::process::exit(2);
+        }
+    };
 
-    println!("TOTAL EXECUTION TIME: {}ms", start.elapsed().as_millis());
+    let exit_code = print_report(&cli, &report)?;
+    std::process::exit(exit_code);
 }
@@ -1,10 +1,32 @@
+use serde::{Deserialize, Serialize};
+#[cfg(windows)]
 use wmi::{COMLibrary, WMIConnection};
 
-pub fn has_microsoft_account() -> anyhow::Result<bool> {
+use crate::flags::Flags;
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct AccountFacts {
+    /// Number of non local accounts
+    pub microsoft_accounts: usize,
+}
+
+#[cfg(windows)]
+pub fn collect_microsoft_account() -> anyhow::Result<AccountFacts> {
     let com_con = unsafe { COMLibrary::assume_initialized() };
     let wmi_con = WMIConnection::new(com_con)?;
 
     let accounts = wmi_con
         .raw_query::<()>("SELECT LocalAccount FROM Win32_Account WHERE LocalAccount = FALSE")?;
-    Ok(!accounts.is_empty())
+    Ok(AccountFacts {
+        microsoft_accounts: accounts.len(),
+    })
+}
+
+pub fn score_microsoft_account(facts: &AccountFacts, flags: &mut Flags) {
+    if facts.microsoft_accounts > 0 {
+        flags.large_bonus("account.microsoft", "signed in with a microsoft account");
+    } else {
+        flags.small_penalty("account.local_only", "only local accounts exist");
+    }
 }
@@ -1,24 +1,106 @@
-use anyhow::Context;
+use anyhow::{Context, bail};
 use chrono::{DateTime, FixedOffset, Utc};
-use serde::Deserialize;
-use windows::Win32::{
-    Foundation::{FILETIME, SYSTEMTIME},
-    System::{
-        SystemInformation::{
-            OS_PRODUCT_TYPE, PRODUCT_PROFESSIONAL, PRODUCT_PROFESSIONAL_E, PRODUCT_PROFESSIONAL_N,
-            PRODUCT_PROFESSIONAL_WMC,
-        },
-        Time::SystemTimeToFileTime,
-    },
-};
+use serde::{Deserialize, Serialize};
+#[cfg(windows)]
 use windows_registry::{Key, USERS};
+#[cfg(windows)]
 use wmi::{COMLibrary, WMIConnection};
 
-use crate::{debug_println, flags::Flags};
+#[cfg(windows)]
+use crate::win_time::SystemTime;
+use crate::{
+    cim_datetime::parse_timestamp,
+    debug_println,
+    flags::Flags,
+    profile::{bucket, ensure_ascending},
+    registry_key::{RegValue, RegistryKey},
+    win_time::FileTime,
+};
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OsFacts {
+    /// Earliest first logon time of any user, from the registry
+    pub registry_first_logon: DateTime<Utc>,
+    pub operating_system: Win32OperatingSystem,
+    /// When these facts were gathered, install ages are relative to this
+    pub collected_at: DateTime<Utc>,
+}
+
+#[cfg(windows)]
+pub fn collect_os() -> anyhow::Result<OsFacts> {
+    let registry_first_logon = get_registry_days_since_installation()?;
+
+    let com_con = unsafe { COMLibrary::assume_initialized() };
+    let wmi_con = WMIConnection::new(com_con)?;
+
+    let results = wmi_con.raw_query::<Win32OperatingSystem>(
+        "SELECT Caption, Name, InstallDate, SerialNumber, OsType, OperatingSystemSku FROM Win32_OperatingSystem",
+    )?;
+
+    let operating_system = results.into_iter().next().context("nf")?;
+
+    Ok(OsFacts {
+        registry_first_logon,
+        operating_system,
+        collected_at: Utc::now(),
+    })
+}
+
+/// Bucket bounds are inclusive upper bounds, anything above the last one lands in the top bucket
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OsThresholds {
+    /// Days the registry and WMI install dates may differ by
+    pub install_date_tolerance_days: i64,
+    pub install_age_days: InstallAgeBuckets,
+}
+
+impl Default for OsThresholds {
+    fn default() -> Self {
+        Self {
+            install_date_tolerance_days: 2,
+            install_age_days: InstallAgeBuckets::default(),
+        }
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InstallAgeBuckets {
+    pub today: i64,
+    pub this_week: i64,
+    pub recent: i64,
+}
+
+impl Default for InstallAgeBuckets {
+    fn default() -> Self {
+        Self {
+            today: 0,
+            this_week: 6,
+            recent: 60,
+        }
+    }
+}
+
+impl OsThresholds {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let age = &self.install_age_days;
+        ensure_ascending("install age", &[age.today, age.this_week, age.recent])
+    }
+}
 
-pub fn score_os(flags: &mut Flags) -> anyhow::Result<()> {
-    let registry_date = get_registry_days_since_installation()?.date_naive();
-    let wmi_date = get_wmi_os_stats_and_score(flags)?.to_utc().date_naive();
+pub fn score_os(
+    facts: &OsFacts,
+    thresholds: &OsThresholds,
+    flags: &mut Flags,
+) -> anyhow::Result<()> {
+    let registry_date = facts.registry_first_logon.date_naive();
+    let wmi_date = score_wmi_os_stats(&facts.operating_system, flags)?
+        .to_utc()
+        .date_naive();
 
     let installations_diff = wmi_date
         .signed_duration_since(registry_date)
@@ -26,28 +108,53 @@ pub fn score_os(flags: &mut Flags) -> anyhow::Result<()> {
         .num_days();
 
     debug_println!("the installations differ by {installations_diff} days");
-    if installations_diff > 2 {
-        flags.large_penalty();
+    if installations_diff > thresholds.install_date_tolerance_days {
+        flags
+            .large_penalty(
+                "os.install_date_mismatch",
+                "registry and WMI install dates disagree",
+            )
+            .evidence(format!("{installations_diff} days"));
     }
 
-    let days_since_installation = registry_date
-        .min(wmi_date)
-        .signed_duration_since(Utc::now().date_naive())
+    let days_since_installation = facts
+        .collected_at
+        .date_naive()
+        .signed_duration_since(registry_date.min(wmi_date))
         .num_days();
 
     debug_println!("days since installation: {days_since_installation}");
+    // An install date after the facts were collected is clock skew, not an age
+    if days_since_installation < 0 {
+        return Ok(());
+    }
 
-    match days_since_installation {
-        0 => flags.extreme_penalty(),
-        1..=6 => flags.large_penalty(),
+    let age = &thresholds.install_age_days;
+    match bucket(
+        &days_since_installation,
+        &[age.today, age.this_week, age.recent],
+    ) {
+        0 => {
+            flags.extreme_penalty("os.installed_today", "windows was installed today");
+        }
+        1 => {
+            flags
+                .large_penalty("os.installed_this_week", "windows was installed this week")
+                .evidence(days_since_installation);
+        }
         // Okay...
-        7..=60 => {}
-        _ => flags.small_bonus(),
+        2 => {}
+        _ => {
+            flags
+                .small_bonus("os.installed_long_ago", "windows was installed a while ago")
+                .evidence(days_since_installation);
+        }
     }
 
     Ok(())
 }
 
+#[cfg(windows)]
 fn get_registry_days_since_installation() -> anyhow::Result<DateTime<Utc>> {
     USERS
         .keys()?
@@ -56,115 +163,70 @@ fn get_registry_days_since_installation() -> anyhow::Result<DateTime<Utc>> {
         .context("nm")
 }
 
+#[cfg(windows)]
 fn try_get_registry_logon_stats(name: &str, root: &Key) -> anyhow::Result<DateTime<Utc>> {
     let user_root = root.open(name)?;
     let key =
         user_root.open("Software\\Microsoft\\Windows\\CurrentVersion\\Explorer\\LogonStats")?;
 
-    let mut flt = None;
-
-    if let Ok(first_logon_time) = key.get_value("FirstLogonTime") {
-        let wide = first_logon_time.as_wide();
-        let time = parse_registry_system_time(wide.try_into()?)?;
-        flt = Some(time);
-    }
-
-    if let Ok(first_logon_time_on_current_installation) =
+    // FirstLogonTimeOnCurrentInstallation is more useful, so prefer it
+    let flt = if let Ok(first_logon_time_on_current_installation) =
         key.get_value("FirstLogonTimeOnCurrentInstallation")
     {
         let wide = first_logon_time_on_current_installation.as_wide();
-        let time = parse_registry_system_time(wide.try_into()?)?;
-        // This is more useful
-        flt = Some(time);
-    }
+        Some(SystemTime::from_words(wide.try_into()?).to_datetime()?)
+    } else if let Ok(first_logon_time) = key.get_value("FirstLogonTime") {
+        let wide = first_logon_time.as_wide();
+        Some(SystemTime::from_words(wide.try_into()?).to_datetime()?)
+    } else {
+        None
+    };
 
     flt.context("ltf")
 }
 
-fn parse_registry_system_time(bytes: [u16; 8]) -> anyhow::Result<DateTime<Utc>> {
-    let system_time = SYSTEMTIME {
-        wYear: bytes[0],
-        wMonth: bytes[1],
-        wDayOfWeek: bytes[2],
-        wDay: bytes[3],
-        wHour: bytes[4],
-        wMinute: bytes[5],
-        wSecond: bytes[6],
-        wMilliseconds: bytes[7],
-    };
-
-    let mut file_time: FILETIME = unsafe { std::mem::zeroed() };
-    unsafe {
-        SystemTimeToFileTime(&raw const system_time, &raw mut file_time)?;
+/// When windows was installed according to `InstallTime`, or the `InstallDate` WMI reports in older installs.
+/// Feature updates reset both.
+pub fn registry_install_date(local_machine: &dyn RegistryKey) -> anyhow::Result<DateTime<Utc>> {
+    let key = local_machine.open("SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion")?;
+    if let Ok(RegValue::Qword(install_time)) = key.value("InstallTime")
+        && let Some(install_time) = FileTime(install_time).to_datetime()
+    {
+        return Ok(install_time);
     }
+    let RegValue::Dword(install_date) = key.value("InstallDate")? else {
+        bail!("nd");
+    };
 
-    const WINDOWS_TICK: u64 = 10_000_000;
-    const SEC_TO_UNIX_EPOCH: u64 = 11_644_473_600;
-
-    let (high, low) = (
-        file_time.dwHighDateTime as u64,
-        file_time.dwLowDateTime as u64,
-    );
-
-    let windows_ticks = (high << 32) | low;
-    let unix_time = (windows_ticks / WINDOWS_TICK) - SEC_TO_UNIX_EPOCH;
-
-    DateTime::from_timestamp(unix_time as i64, 0).context("bdt")
+    DateTime::from_timestamp(i64::from(install_date), 0).context("bdt")
 }
 
-#[allow(dead_code)]
 #[cfg_attr(debug_assertions, derive(Debug))]
-#[derive(Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
-struct Win32OperatingSystem {
-    caption: String,       // Microsoft Windows 11 Pro
-    name: String,          // Microsoft Windows 11 Pro|C:\\WINDOWS|\\Device\\Harddisk0\\Partition3
-    install_date: String,  // 20240704035336.000000-240
-    serial_number: String, // TODO 00330-80000-00000-AA359
-    os_type: u16, // https://learn.microsoft.com/en-us/windows/win32/cimwin32prov/win32-operatingsystem?redirectedfrom=MSDN#examples
-    operating_system_sku: u32,
+pub struct Win32OperatingSystem {
+    pub caption: String,       // Microsoft Windows 11 Pro
+    pub name: String, // Microsoft Windows 11 Pro|C:\\WINDOWS|\\Device\\Harddisk0\\Partition3
+    pub install_date: String, // 20240704035336.000000-240
+    pub serial_number: String, // TODO 00330-80000-00000-AA359
+    pub os_type: u16, // https://learn.microsoft.com/en-us/windows/win32/cimwin32prov/win32-operatingsystem?redirectedfrom=MSDN#examples
+    pub operating_system_sku: u32,
 }
 
-const PROS: &[OS_PRODUCT_TYPE] = &[
-    PRODUCT_PROFESSIONAL,
-    PRODUCT_PROFESSIONAL_E,
-    PRODUCT_PROFESSIONAL_N,
-    PRODUCT_PROFESSIONAL_WMC,
-];
-
-fn get_wmi_os_stats_and_score(flags: &mut Flags) -> anyhow::Result<DateTime<FixedOffset>> {
-    let com_con = unsafe { COMLibrary::assume_initialized() };
-    let wmi_con = WMIConnection::new(com_con)?;
-
-    let results = wmi_con.raw_query::<Win32OperatingSystem>(
-        "SELECT Caption, Name, InstallDate, SerialNumber, OsType, OperatingSystemSku FROM Win32_OperatingSystem",
-    )?;
-
-    let os = results.first().context("nf")?;
+// PRODUCT_PROFESSIONAL, PRODUCT_PROFESSIONAL_E, PRODUCT_PROFESSIONAL_N, PRODUCT_PROFESSIONAL_WMC
+const PROS: &[u32] = &[48, 69, 49, 103];
 
+fn score_wmi_os_stats(
+    os: &Win32OperatingSystem,
+    flags: &mut Flags,
+) -> anyhow::Result<DateTime<FixedOffset>> {
     debug_println!("operating system sku: {}", os.operating_system_sku);
 
-    if PROS
-        .iter()
-        .map(|sku| sku.0)
-        .any(|sku| sku == os.operating_system_sku)
-    {
-        flags.small_penalty();
+    if PROS.contains(&os.operating_system_sku) {
+        flags
+            .small_penalty("os.professional", "running a professional edition")
+            .evidence(&os.caption);
     }
 
-    parse_wmi_install_date(&os.install_date)
-}
-
-// 20240704035336.000000-240
-fn parse_wmi_install_date(date_str: &str) -> anyhow::Result<DateTime<FixedOffset>> {
-    let (datetime_part, tz_part) = date_str.rsplit_once('-').context("ns")?;
-
-    let tz_minutes: i32 = tz_part.parse()?;
-    let tz_hours = tz_minutes / 60;
-    let tz_mins = tz_minutes % 60;
-    let tz_formatted = format!("-{tz_hours:02}:{tz_mins:02}");
-
-    let formatted_date = format!("{datetime_part}{tz_formatted}");
-
-    DateTime::parse_from_str(&formatted_date, "%Y%m%d%H%M%S%.f%z").map_err(Into::into)
+    parse_timestamp(&os.install_date)
 }
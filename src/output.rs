@@ -0,0 +1,187 @@
+use std::{fmt::Write, str::FromStr};
+
+use anyhow::bail;
+use serde::Serialize;
+
+use crate::{
+    detect::DetectionReport,
+    flags::Finding,
+    hypervisor::{Hypervisor, VendorTally},
+    verdict::Verdict,
+};
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+    Markdown,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "text" => Self::Text,
+            "json" => Self::Json,
+            "markdown" | "md" => Self::Markdown,
+            _ => bail!("unknown format {s}, expected text, json or markdown"),
+        })
+    }
+}
+
+/// Renders `report` in `format`. Text only lists the findings when `verbose` is set, the other formats always do.
+pub fn render(report: &DetectionReport, format: Format, verbose: bool) -> anyhow::Result<String> {
+    Ok(match format {
+        Format::Text => text(report, verbose),
+        Format::Json => serde_json::to_string_pretty(&JsonReport::new(report))?,
+        Format::Markdown => markdown(report),
+    })
+}
+
+fn text(report: &DetectionReport, verbose: bool) -> String {
+    let mut out = String::new();
+    if verbose {
+        for finding in report.findings() {
+            let _ = writeln!(out, "{}: {finding}", finding.check);
+        }
+        for check in &report.checks {
+            let _ = writeln!(
+                out,
+                "{}: score {} ({}ms)",
+                check.name,
+                check.score,
+                check.elapsed.as_millis()
+            );
+        }
+    }
+
+    let _ = writeln!(out, "score: {}", report.score);
+    let _ = writeln!(out, "verdict: {}", report.verdict);
+    if let Some(hypervisor) = report.hypervisor {
+        let _ = writeln!(out, "hypervisor: {hypervisor}");
+    }
+    for tally in &report.vendors {
+        let _ = writeln!(
+            out,
+            "  {}: {} findings, weight {}",
+            tally.vendor, tally.findings, tally.weight
+        );
+    }
+
+    let _ = writeln!(
+        out,
+        "TOTAL EXECUTION TIME: {}ms",
+        report.elapsed.as_millis()
+    );
+    out
+}
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    score: i64,
+    verdict: Verdict,
+    hypervisor: Option<Hypervisor>,
+    vendors: &'a [VendorTally],
+    elapsed_ms: u128,
+    com_enabled: bool,
+    checks: Vec<JsonCheck<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonCheck<'a> {
+    name: &'static str,
+    score: i64,
+    elapsed_ms: u128,
+    error: Option<&'a str>,
+    timed_out: bool,
+    findings: &'a [Finding],
+}
+
+impl<'a> JsonReport<'a> {
+    fn new(report: &'a DetectionReport) -> Self {
+        Self {
+            score: report.score,
+            verdict: report.verdict,
+            hypervisor: report.hypervisor,
+            vendors: &report.vendors,
+            elapsed_ms: report.elapsed.as_millis(),
+            com_enabled: report.com_enabled,
+            checks: report
+                .checks
+                .iter()
+                .map(|check| JsonCheck {
+                    name: check.name,
+                    score: check.score,
+                    elapsed_ms: check.elapsed.as_millis(),
+                    error: check.error.as_deref(),
+                    timed_out: check.timed_out,
+                    findings: check.flags.findings(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Makes `s` safe to put in a table cell
+fn cell(s: &str) -> String {
+    s.replace('|', "\\|").replace('\n', " ")
+}
+
+fn markdown(report: &DetectionReport) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# VM detection report\n");
+    let _ = writeln!(out, "- **Score:** {}", report.score);
+    let _ = writeln!(out, "- **Verdict:** {}", report.verdict);
+    if let Some(hypervisor) = report.hypervisor {
+        let _ = writeln!(out, "- **Hypervisor:** {hypervisor}");
+    }
+    let _ = writeln!(out, "- **Time:** {}ms", report.elapsed.as_millis());
+
+    if !report.vendors.is_empty() {
+        let _ = writeln!(out, "\n## Vendors\n");
+        let _ = writeln!(out, "| Vendor | Findings | Weight |\n|---|---|---|");
+        for tally in &report.vendors {
+            let _ = writeln!(
+                out,
+                "| {} | {} | {} |",
+                tally.vendor, tally.findings, tally.weight
+            );
+        }
+    }
+
+    let _ = writeln!(out, "\n## Checks\n");
+    let _ = writeln!(out, "| Check | Score | Time | Error |\n|---|---|---|---|");
+    for check in &report.checks {
+        let _ = writeln!(
+            out,
+            "| {} | {} | {}ms | {} |",
+            check.name,
+            check.score,
+            check.elapsed.as_millis(),
+            cell(check.error.as_deref().unwrap_or_default())
+        );
+    }
+
+    let _ = writeln!(out, "\n## Findings\n");
+    let _ = writeln!(
+        out,
+        "| Check | Kind | Level | Rule | Reason | Evidence |\n|---|---|---|---|---|---|"
+    );
+    for finding in report.findings() {
+        let _ = writeln!(
+            out,
+            "| {} | {} | {} | `{}` | {} | {} |",
+            finding.check,
+            finding.kind.name(),
+            finding.level,
+            finding.rule_id,
+            cell(&finding.reason),
+            cell(finding.evidence.as_deref().unwrap_or_default())
+        );
+    }
+
+    out
+}
@@ -1,33 +1,56 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+#[cfg(windows)]
 use wmi::{COMLibrary, WMIConnection};
 
 use crate::flags::Flags;
 
-#[derive(Deserialize)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct PrinterFacts {
+    pub printers: Vec<Win32Printer>,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
-struct Win32Printer {
-    name: String,        // != "Microsoft Print to PDF"
-    printer_state: u32,  // 13 = Not Available, 24 = Server_Unknown
-    printer_status: u16, // 1 = Other, 2 = Unknown
+pub struct Win32Printer {
+    pub name: String,        // != "Microsoft Print to PDF"
+    pub printer_state: u32,  // 13 = Not Available, 24 = Server_Unknown
+    pub printer_status: u16, // 1 = Other, 2 = Unknown
 }
 
-pub fn score_printers(flags: &mut Flags) -> anyhow::Result<()> {
+#[cfg(windows)]
+pub fn collect_printers() -> anyhow::Result<PrinterFacts> {
     let com_con = unsafe { COMLibrary::assume_initialized() };
     let wmi_con = WMIConnection::new(com_con)?;
 
-    let mut printers = wmi_con
+    let printers = wmi_con
         .raw_query::<Win32Printer>("SELECT Name, PrinterState, PrinterStatus FROM Win32_Printer")
         .unwrap_or_default();
 
-    printers.retain(is_printer_valid);
+    Ok(PrinterFacts { printers })
+}
 
-    match printers.len() {
-        0 => {}
-        1 => flags.medium_bonus(),
-        _ => flags.large_bonus(),
-    }
+pub fn score_printers(facts: &PrinterFacts, flags: &mut Flags) {
+    let printers = facts
+        .printers
+        .iter()
+        .filter(|printer| is_printer_valid(printer))
+        .collect::<Vec<_>>();
 
-    Ok(())
+    match printers.as_slice() {
+        [] => {}
+        [printer] => {
+            flags
+                .medium_bonus("printers.one", "has a real printer")
+                .evidence(&printer.name);
+        }
+        _ => {
+            flags
+                .large_bonus("printers.multiple", "has multiple real printers")
+                .evidence(printers.len());
+        }
+    }
 }
 
 fn is_printer_valid(printer: &Win32Printer) -> bool {
@@ -0,0 +1,389 @@
+//! The installed product key, decoded from the `DigitalProductId` blobs under `CurrentVersion`
+use anyhow::{Context, ensure};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    flags::Flags,
+    license_keys::{Channel, KeyDatabase},
+    registry_key::{RegValue, RegistryKey, utf16_string},
+};
+
+const CURRENT_VERSION: &str = "SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion";
+
+/// The 24 letters and digits keys are written in, `N` is encoded separately since windows 8
+const KEY_CHARS: &[u8; 24] = b"BCDFGHJKMPQRTVWXY2346789";
+/// Length of the encoded key in both blobs
+const KEY_BYTES: usize = 15;
+/// Set in the last key byte when the key has an `N`
+const WINDOWS8_FLAG: u8 = 0x08;
+
+/// `DigitalProductId`: the product id as ASCII at 8 and the key at 52
+const PRODUCT_ID: (usize, usize) = (8, 24);
+const KEY_OFFSET: usize = 52;
+
+/// `DigitalProductId4`: UTF-16 edition at 280 and key type at 1016, the key at 808
+const DPID4_EDITION: (usize, usize) = (280, 520);
+const DPID4_KEY_OFFSET: usize = 808;
+const DPID4_KEY_TYPE: (usize, usize) = (1016, 128);
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DigitalProductId {
+    /// e.x. `00330-80000-00000-AA478`
+    pub product_id: Option<String>,
+    /// None when the blob holds no key, which is what removing it from the registry leaves
+    pub key: Option<String>,
+    /// Edition the key is for, e.x. `Professional`
+    pub edition: Option<String>,
+    /// Channel as the key type names it, e.x. `Retail`, `OEM:DM` or `Volume:GVLK`
+    pub key_type: Option<String>,
+}
+
+impl DigitalProductId {
+    /// Parses the 164 byte `DigitalProductId`
+    pub fn parse(blob: &[u8]) -> anyhow::Result<Self> {
+        let key = key_at(blob, KEY_OFFSET).context("dpid too short")?;
+        Ok(Self {
+            product_id: ascii_at(blob, PRODUCT_ID),
+            key,
+            ..Self::default()
+        })
+    }
+
+    /// Parses the 1272 byte `DigitalProductId4`
+    pub fn parse4(blob: &[u8]) -> anyhow::Result<Self> {
+        let key = key_at(blob, DPID4_KEY_OFFSET).context("dpid4 too short")?;
+        Ok(Self {
+            product_id: None,
+            key,
+            edition: utf16_at(blob, DPID4_EDITION),
+            key_type: utf16_at(blob, DPID4_KEY_TYPE),
+        })
+    }
+
+    /// The channel of the key type
+    #[must_use]
+    pub fn channel(&self) -> Option<Channel> {
+        key_type_channel(self.key_type.as_deref()?)
+    }
+
+    /// The last group of the key, what `slmgr` and the licensing API show
+    #[must_use]
+    pub fn partial_key(&self) -> Option<&str> {
+        self.key.as_deref()?.rsplit('-').next()
+    }
+
+    /// Fills in what `self` is missing from `other`
+    fn or(self, other: Self) -> Self {
+        Self {
+            product_id: self.product_id.or(other.product_id),
+            key: self.key.or(other.key),
+            edition: self.edition.or(other.edition),
+            key_type: self.key_type.or(other.key_type),
+        }
+    }
+}
+
+/// The channel of a `DigitalProductId4` key type, the test builds of evaluation editions are `Retail:TB:Eval`
+#[must_use]
+pub fn key_type_channel(key_type: &str) -> Option<Channel> {
+    if key_type.ends_with(":Eval") {
+        return Some(Channel::Eval);
+    }
+    key_type.parse().ok()
+}
+
+/// Decodes a key from the base 24 number in `bytes`, little endian with the last character in the lowest digit.
+///
+/// Since windows 8 the flag bit marks a key with an `N`, whose position is stored in the first digit instead.
+#[must_use]
+pub fn decode_key(mut bytes: [u8; KEY_BYTES]) -> String {
+    let last = bytes.len() - 1;
+    let windows8 = bytes
+        .get(last)
+        .is_some_and(|&byte| byte & WINDOWS8_FLAG != 0);
+    if let Some(byte) = bytes.get_mut(last) {
+        *byte &= !WINDOWS8_FLAG;
+    }
+
+    let mut digits = [0u8; 25];
+    for digit in digits.iter_mut().rev() {
+        let mut remainder = 0u32;
+        for byte in bytes.iter_mut().rev() {
+            let value = (remainder << 8) | u32::from(*byte);
+            // The remainder is below 24, so the quotient fits a byte
+            *byte = u8::try_from(value / 24).unwrap_or_default();
+            remainder = value % 24;
+        }
+        *digit = u8::try_from(remainder).unwrap_or_default();
+    }
+
+    let mut chars: Vec<char> = digits
+        .iter()
+        .filter_map(|&digit| KEY_CHARS.get(usize::from(digit)).copied().map(char::from))
+        .collect();
+    if windows8 {
+        let position = usize::from(digits[0]).min(chars.len() - 1);
+        chars.remove(0);
+        chars.insert(position, 'N');
+    }
+
+    chars
+        .chunks(5)
+        .map(|group| group.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// The key at `offset`, None if its bytes are all zero
+fn key_at(blob: &[u8], offset: usize) -> anyhow::Result<Option<String>> {
+    let bytes: [u8; KEY_BYTES] = blob
+        .get(offset..offset + KEY_BYTES)
+        .and_then(|bytes| bytes.try_into().ok())
+        .context("nk")?;
+    Ok(bytes
+        .iter()
+        .any(|&byte| byte != 0)
+        .then(|| decode_key(bytes)))
+}
+
+/// NUL terminated ASCII in the `(offset, length)` field, None if empty
+fn ascii_at(blob: &[u8], (offset, length): (usize, usize)) -> Option<String> {
+    let field = blob.get(offset..offset + length)?;
+    let text = field.split(|&byte| byte == 0).next()?;
+    (!text.is_empty()).then(|| String::from_utf8_lossy(text).into_owned())
+}
+
+/// NUL terminated UTF-16 in the `(offset, length)` field, None if empty
+fn utf16_at(blob: &[u8], (offset, length): (usize, usize)) -> Option<String> {
+    let text = utf16_string(blob.get(offset..offset + length)?);
+    let text = text.split('\0').next()?;
+    (!text.is_empty()).then(|| text.to_owned())
+}
+
+/// Both blobs combined, `DigitalProductId4` first since it is the one windows 8 and later keep up to date
+pub fn read_digital_product_id(
+    local_machine: &dyn RegistryKey,
+) -> anyhow::Result<DigitalProductId> {
+    let key = local_machine.open(CURRENT_VERSION)?;
+    let blob = |name| match key.value(name) {
+        Ok(RegValue::Binary(data)) => Some(data),
+        _ => None,
+    };
+
+    let dpid4 = blob("DigitalProductId4").map(|data| DigitalProductId::parse4(&data));
+    let dpid = blob("DigitalProductId").map(|data| DigitalProductId::parse(&data));
+    ensure!(dpid4.is_some() || dpid.is_some(), "ndpid");
+
+    let mut id = DigitalProductId::default();
+    for parsed in [dpid4, dpid].into_iter().flatten() {
+        id = id.or(parsed?);
+    }
+    Ok(id)
+}
+
+pub fn score_product_key(
+    local_machine: &dyn RegistryKey,
+    keys: &KeyDatabase,
+    flags: &mut Flags,
+) -> anyhow::Result<()> {
+    let id = read_digital_product_id(local_machine)?;
+    let channel = id.channel();
+
+    let mut evidence = id.key.clone().unwrap_or_default();
+    for field in [&id.key_type, &id.edition].into_iter().flatten() {
+        if !evidence.is_empty() {
+            evidence.push_str(", ");
+        }
+        evidence.push_str(field);
+    }
+
+    // `slmgr /cpky` zeroes the key, KMS activators run it to hide the key they installed
+    let Some(key) = id.key.as_deref() else {
+        let finding = flags.small_penalty(
+            "product_key.cleared",
+            "the product key was removed from the registry",
+        );
+        if !evidence.is_empty() {
+            finding.evidence(evidence);
+        }
+        return Ok(());
+    };
+
+    // A known key is shared by every machine it is installed on, whatever the channel
+    if let Some(record) = keys.find_key(key) {
+        let evidence = format!("{evidence}, installed by {}", record.activator_names());
+        match record.channel {
+            Channel::VolumeGvlk => flags.small_penalty(
+                "product_key.gvlk",
+                "a KMS client key is installed, activated by a KMS server",
+            ),
+            Channel::Eval => {
+                flags.medium_penalty("product_key.eval", "an evaluation edition is installed")
+            }
+            _ => flags.small_penalty(
+                "product_key.generic",
+                "a generic key that comes with no license is installed",
+            ),
+        }
+        .evidence(evidence);
+        return Ok(());
+    }
+
+    match channel {
+        Some(Channel::OemDm) => {
+            flags
+                .medium_bonus(
+                    "product_key.oem_dm",
+                    "the key came with the firmware of an OEM machine",
+                )
+                .evidence(evidence);
+        }
+        Some(Channel::Retail) => {
+            flags
+                .small_bonus("product_key.retail", "a bought retail key is installed")
+                .evidence(evidence);
+        }
+        Some(Channel::Eval) => {
+            flags
+                .medium_penalty("product_key.eval", "an evaluation edition is installed")
+                .evidence(evidence);
+        }
+        Some(Channel::VolumeGvlk) => {
+            flags
+                .small_penalty(
+                    "product_key.gvlk",
+                    "a KMS client key is installed, activated by a KMS server",
+                )
+                .evidence(evidence);
+        }
+        Some(Channel::OemNonSlp | Channel::VolumeMak) | None => {}
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry_key::MemoryKey;
+
+    /// Windows 7 Professional KMS client key, from before the `N` encoding
+    const WINDOWS7_KEY: [u8; KEY_BYTES] = [
+        0xc3, 0xaa, 0xbf, 0xa6, 0x5b, 0xba, 0x18, 0xb8, 0x87, 0x8e, 0x89, 0xd2, 0x4e, 0xd8, 0x00,
+    ];
+    /// Windows 10 Pro KMS client key, `N` fifth
+    const WINDOWS10_KEY: [u8; KEY_BYTES] = [
+        0xef, 0x0c, 0x10, 0x00, 0x00, 0x00, 0x34, 0x3d, 0xc5, 0x39, 0x4e, 0xbd, 0x6e, 0x2f, 0x09,
+    ];
+    /// Windows 10 Enterprise KMS client key, `N` first
+    const LEADING_N_KEY: [u8; KEY_BYTES] = [
+        0xda, 0x0c, 0x30, 0x00, 0x00, 0x00, 0x18, 0x63, 0x67, 0xe0, 0x15, 0x65, 0xbe, 0x19, 0x08,
+    ];
+
+    fn write_utf16(blob: &mut [u8], offset: usize, text: &str) {
+        let bytes: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        blob.get_mut(offset..offset + bytes.len())
+            .unwrap()
+            .copy_from_slice(&bytes);
+    }
+
+    fn dpid(key: [u8; KEY_BYTES]) -> Vec<u8> {
+        let mut blob = vec![0u8; 164];
+        blob.get_mut(8..31)
+            .unwrap()
+            .copy_from_slice(b"00371-OEM-8992671-00524");
+        blob.get_mut(KEY_OFFSET..KEY_OFFSET + KEY_BYTES)
+            .unwrap()
+            .copy_from_slice(&key);
+        blob
+    }
+
+    fn dpid4(key: [u8; KEY_BYTES], edition: &str, key_type: &str) -> Vec<u8> {
+        let mut blob = vec![0u8; 1272];
+        write_utf16(&mut blob, 280, edition);
+        blob.get_mut(808..808 + KEY_BYTES)
+            .unwrap()
+            .copy_from_slice(&key);
+        write_utf16(&mut blob, 1016, key_type);
+        blob
+    }
+
+    fn score(values: &[(&str, Vec<u8>)]) -> Flags {
+        let mut local_machine = MemoryKey::default();
+        let key = local_machine.subkey_mut(CURRENT_VERSION);
+        for (name, blob) in values {
+            key.set_value(name, RegValue::Binary(blob.clone()));
+        }
+        let mut flags = Flags::new("product-key");
+        score_product_key(&local_machine, &KeyDatabase::default(), &mut flags).unwrap();
+        flags
+    }
+
+    fn rule_ids(flags: &Flags) -> Vec<&str> {
+        flags.findings().iter().map(|f| &*f.rule_id).collect()
+    }
+
+    #[test]
+    fn decodes_keys_without_n() {
+        assert_eq!(decode_key(WINDOWS7_KEY), "FJ82H-XT6CR-J8D7P-XQJJ2-GPDD4");
+    }
+
+    #[test]
+    fn decodes_keys_with_n() {
+        assert_eq!(decode_key(WINDOWS10_KEY), "W269N-WFGWX-YVC9B-4J6C9-T83GX");
+        assert_eq!(decode_key(LEADING_N_KEY), "NPPR9-FWDCX-D2C8J-H872K-2YT43");
+    }
+
+    #[test]
+    fn parses_dpid() {
+        let id = DigitalProductId::parse(&dpid(WINDOWS7_KEY)).unwrap();
+        assert_eq!(id.product_id.as_deref(), Some("00371-OEM-8992671-00524"));
+        assert_eq!(id.key.as_deref(), Some("FJ82H-XT6CR-J8D7P-XQJJ2-GPDD4"));
+        assert_eq!(id.partial_key(), Some("GPDD4"));
+
+        DigitalProductId::parse(&[0; KEY_OFFSET]).unwrap_err();
+    }
+
+    #[test]
+    fn parses_dpid4_offsets() {
+        let id =
+            DigitalProductId::parse4(&dpid4(WINDOWS10_KEY, "Professional", "Volume:GVLK")).unwrap();
+        assert_eq!(id.edition.as_deref(), Some("Professional"));
+        assert_eq!(id.key.as_deref(), Some("W269N-WFGWX-YVC9B-4J6C9-T83GX"));
+        assert_eq!(id.key_type.as_deref(), Some("Volume:GVLK"));
+        assert_eq!(id.channel(), Some(Channel::VolumeGvlk));
+
+        let eval =
+            DigitalProductId::parse4(&dpid4(LEADING_N_KEY, "EnterpriseEval", "Retail:TB:Eval"))
+                .unwrap();
+        assert_eq!(eval.channel(), Some(Channel::Eval));
+    }
+
+    #[test]
+    fn zeroed_key_is_a_finding() {
+        let flags = score(&[
+            ("DigitalProductId", dpid([0; KEY_BYTES])),
+            (
+                "DigitalProductId4",
+                dpid4([0; KEY_BYTES], "Professional", "Retail"),
+            ),
+        ]);
+        assert_eq!(rule_ids(&flags), ["product_key.cleared"]);
+    }
+
+    #[test]
+    fn scores_the_key_type() {
+        let flags = score(&[(
+            "DigitalProductId4",
+            dpid4(WINDOWS10_KEY, "Professional", "OEM:DM"),
+        )]);
+        assert_eq!(rule_ids(&flags), ["product_key.gvlk"]);
+
+        let flags = score(&[(
+            "DigitalProductId4",
+            dpid4(WINDOWS7_KEY, "Professional", "OEM:DM"),
+        )]);
+        assert_eq!(rule_ids(&flags), ["product_key.oem_dm"]);
+    }
+}
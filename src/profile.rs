@@ -0,0 +1,406 @@
+use std::{collections::BTreeMap, fmt, fs, path::Path};
+
+use anyhow::{Context, bail, ensure};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    checks::CHECKS,
+    flags::{Finding, FindingKind, Level},
+};
+pub use crate::{
+    installed_apps::{InstalledAppsThresholds, ProgramBuckets, SteamGameBuckets},
+    key_timestamps::KeyTimestampThresholds,
+    license_keys::{Activator, Channel, KeyDatabase, KeyRecord},
+    os::{InstallAgeBuckets, OsThresholds},
+    registry::{ControlSets, RegistryRules},
+    registry_macros::{
+        BytePattern, Check as RegistryCheck, Comparison, Pattern, RegistryRule, TraversalLimits,
+    },
+    scheduler::TimeoutPenalty,
+    sysinfo::{DiskSizeBuckets, DiskUsageBuckets, RamBuckets, SysinfoThresholds, UptimeBuckets},
+    verdict::VerdictThresholds,
+};
+
+/// Every rule id the checks report, except the registry rules (`registry.<rule>.<what>`, the rules come from a
+/// file) and the `<check name>.error` and `<check name>.timed_out` penalties
+const RULE_IDS: &[&str] = &[
+    "account.local_only",
+    "account.microsoft",
+    "apps.no_start_menu",
+    "apps.programs_few",
+    "apps.programs_many",
+    "apps.programs_none",
+    "apps.steam_few_games",
+    "apps.steam_many_games",
+    "apps.steam_no_games",
+    "auto_logon.enabled",
+    "battery.present",
+    "bluetooth.none",
+    "bluetooth.present",
+    "devices.pci_microsoft",
+    "devices.pci_ps2",
+    "devices.pci_vbox_hardware_id",
+    "devices.pci_vm_description",
+    "devices.pci_vm_manufacturer",
+    "devices.pci_vmci",
+    "devices.ps2",
+    "devices.vm_name",
+    "displays.connection_other",
+    "displays.connector_dvi",
+    "displays.connector_modern",
+    "displays.connector_other",
+    "displays.connector_unknown",
+    "displays.connector_unusual",
+    "displays.connector_vga",
+    "displays.dolby_vision",
+    "displays.internal",
+    "displays.luminance_consistent",
+    "displays.luminance_inconsistent",
+    "displays.multiple",
+    "displays.no_full_frame_luminance",
+    "displays.no_luminance",
+    "displays.no_name",
+    "displays.no_physical_size",
+    "displays.no_resolution",
+    "displays.none",
+    "displays.resolution_below_fhd",
+    "displays.resolution_low",
+    "displays.resolution_tiny",
+    "displays.unreadable",
+    "displays.usage_head_mounted",
+    "displays.usage_other",
+    "displays.usage_special_purpose",
+    "displays.virtual",
+    "displays.wireless",
+    "displays.zero_luminance",
+    "graphics.no_dac_type",
+    "graphics.none",
+    "graphics.vm3d_driver",
+    "graphics.vm3d_inf",
+    "graphics.vmware",
+    "key_timestamps.changed_for_months",
+    "key_timestamps.frozen",
+    "key_timestamps.mostly_frozen",
+    "license.evaluation",
+    "license.evaluation_expired",
+    "license.genuine",
+    "license.kms38",
+    "license.kms_host",
+    "license.pirated",
+    "license.unlicensed",
+    "os.install_date_mismatch",
+    "os.installed_long_ago",
+    "os.installed_this_week",
+    "os.installed_today",
+    "os.professional",
+    "printers.multiple",
+    "printers.one",
+    "product_key.cleared",
+    "product_key.eval",
+    "product_key.generic",
+    "product_key.gvlk",
+    "product_key.oem_dm",
+    "product_key.retail",
+    "sysinfo.disk_barely_used",
+    "sysinfo.disk_huge",
+    "sysinfo.disk_large",
+    "sysinfo.disk_small",
+    "sysinfo.disk_tiny",
+    "sysinfo.disk_unused",
+    "sysinfo.disk_used",
+    "sysinfo.disk_well_used",
+    "sysinfo.integrated_display",
+    "sysinfo.no_c_drive",
+    "sysinfo.ram_below_average",
+    "sysinfo.ram_high",
+    "sysinfo.ram_low",
+    "sysinfo.ram_odd",
+    "sysinfo.ram_tiny",
+    "sysinfo.ram_unknown",
+    "sysinfo.ram_very_high",
+    "sysinfo.single_processor",
+    "sysinfo.two_processors",
+    "sysinfo.uptime_minute",
+    "sysinfo.uptime_minutes",
+    "usb.focusrite_driver",
+    "usb.intel_bluetooth",
+    "usb.keychron",
+    "usb.many_devices",
+    "usb.no_devices",
+    "usb.nuphy",
+    "usb.real_vendor",
+    "usb.vm_interface",
+    "usb.vm_product",
+    "usb.vm_vendor",
+    "usb.vmware_instance",
+    "wifi.none",
+    "wifi.present",
+    "wifi.vm_description",
+    "wifi.zero_guid",
+    "wmi.bios_vm_string",
+    "wmi.card_440bx",
+    "wmi.card_reference_platform",
+    "wmi.chassis_no_enclosure",
+    "wmi.computer_system_vm_string",
+    "wmi.power_on_password_disabled",
+    "wmi.user_device_vm_string",
+];
+
+/// Weights, thresholds, registry rules and license keys used for scoring. The default is the built-in profile, a
+/// profile file only has to contain the values it changes.
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScoringProfile {
+    pub levels: LevelValues,
+    /// Weight of a rule by rule id, replacing the value of the level it fired with. Unknown ids are rejected, a
+    /// misspelled one would never apply.
+    pub rules: BTreeMap<String, i64>,
+    pub verdict: VerdictThresholds,
+    pub sysinfo: SysinfoThresholds,
+    pub installed_apps: InstalledAppsThresholds,
+    pub os: OsThresholds,
+    pub key_timestamps: KeyTimestampThresholds,
+    pub timeouts: TimeoutPenalty,
+    /// Loaded from a separate rules file, see [`RegistryRules::load`]
+    #[serde(skip)]
+    pub registry: RegistryRules,
+    /// Loaded from a separate keys file, see [`KeyDatabase::load`]
+    #[serde(skip)]
+    pub license_keys: KeyDatabase,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LevelValues {
+    pub tiny: i64,
+    pub small: i64,
+    pub medium: i64,
+    pub large: i64,
+    pub extreme: i64,
+    pub end_all: i64,
+}
+
+impl Default for LevelValues {
+    fn default() -> Self {
+        Self {
+            tiny: Level::Tiny.value(),
+            small: Level::Small.value(),
+            medium: Level::Medium.value(),
+            large: Level::Large.value(),
+            extreme: Level::Extreme.value(),
+            end_all: Level::EndAll.value(),
+        }
+    }
+}
+
+impl LevelValues {
+    #[must_use]
+    pub const fn value(&self, level: Level) -> i64 {
+        match level {
+            Level::Tiny => self.tiny,
+            Level::Small => self.small,
+            Level::Medium => self.medium,
+            Level::Large => self.large,
+            Level::Extreme => self.extreme,
+            Level::EndAll => self.end_all,
+        }
+    }
+}
+
+impl ScoringProfile {
+    pub fn from_toml(toml: &str) -> anyhow::Result<Self> {
+        let profile: Self = toml::from_str(toml)?;
+        profile.validate()?;
+        Ok(profile)
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let toml = fs::read_to_string(path)?;
+        Self::from_toml(&toml).with_context(|| format!("bad profile {}", path.display()))
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for id in self.rules.keys() {
+            ensure!(is_rule_id(id), "unknown rule id {id}");
+        }
+        self.verdict.validate()?;
+        self.sysinfo.validate()?;
+        self.installed_apps.validate()?;
+        self.os.validate()?;
+        self.key_timestamps.validate()
+    }
+
+    /// Signed score contribution of `finding` under this profile, negative for penalties
+    #[must_use]
+    pub fn finding_value(&self, finding: &Finding) -> i64 {
+        let weight = self
+            .rules
+            .get(finding.rule_id.as_ref())
+            .copied()
+            .unwrap_or_else(|| self.levels.value(finding.level));
+
+        match finding.kind {
+            FindingKind::Penalty => -weight,
+            FindingKind::Bonus => weight,
+        }
+    }
+
+    #[must_use]
+    pub fn score<'a>(&self, findings: impl IntoIterator<Item = &'a Finding>) -> i64 {
+        findings.into_iter().map(|f| self.finding_value(f)).sum()
+    }
+}
+
+fn is_rule_id(id: &str) -> bool {
+    if RULE_IDS.contains(&id) || id.starts_with("registry.") {
+        return true;
+    }
+    id.rsplit_once('.').is_some_and(|(check, rule)| {
+        ["error", "timed_out"].contains(&rule) && CHECKS.iter().any(|c| c.name() == check)
+    })
+}
+
+/// Index of the first bucket whose inclusive upper bound fits `value`, or `upper_bounds.len()` if none do
+pub fn bucket<T: PartialOrd>(value: &T, upper_bounds: &[T]) -> usize {
+    upper_bounds
+        .iter()
+        .position(|bound| value <= bound)
+        .unwrap_or(upper_bounds.len())
+}
+
+pub fn ensure_ascending<T: PartialOrd + fmt::Display>(
+    name: &str,
+    upper_bounds: &[T],
+) -> anyhow::Result<()> {
+    for pair in upper_bounds.windows(2) {
+        if let [a, b] = pair
+            && a >= b
+        {
+            bail!("{name} buckets must be ascending, {a} is not below {b}");
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::Utc;
+
+    use super::*;
+    use crate::{
+        DetectOptions,
+        facts::{
+            AccountFacts, BatteryFacts, BluetoothFacts, DiskSpaceReport, DisplayFacts,
+            GraphicsCardFacts, InstalledAppsFacts, LicenseFacts, OsFacts, PrinterFacts,
+            SysinfoFacts, SystemDeviceFacts, UsbFacts, VariousWmiFacts, WifiFacts,
+            Win32OperatingSystem,
+        },
+        reg_export::RegExport,
+        snapshot::Snapshot,
+    };
+
+    /// A small, mostly empty machine, which trips many of the checks' "none" and "tiny" rules
+    fn bare_machine() -> Snapshot {
+        let now = Utc::now();
+        Snapshot {
+            system_devices: Ok(SystemDeviceFacts {
+                device_names: vec![
+                    "VMware VMCI Bus Device".to_owned(),
+                    "PS/2 Keyboard".to_owned(),
+                ],
+            }),
+            os: Ok(OsFacts {
+                registry_first_logon: now,
+                operating_system: Win32OperatingSystem {
+                    caption: "Microsoft Windows 11 Pro".to_owned(),
+                    name: "Microsoft Windows 11 Pro|C:\\WINDOWS".to_owned(),
+                    install_date: now.format("%Y%m%d%H%M%S.000000+000").to_string(),
+                    serial_number: "00330-80000-00000-AA359".to_owned(),
+                    os_type: 18,
+                    operating_system_sku: 48,
+                },
+                collected_at: now,
+            }),
+            installed_apps: Ok(InstalledAppsFacts::default()),
+            license: Ok(LicenseFacts::default()),
+            wifi_adapters: Ok(WifiFacts::default()),
+            displays: Ok(DisplayFacts::default()),
+            battery: Ok(BatteryFacts::default()),
+            sysinfo: Ok(SysinfoFacts {
+                memory_in_kilos: Some(2 * 1024 * 1024),
+                processors: 1,
+                tick_count_ms: 30_000,
+                integrated_display_size: None,
+                c_drive_error: Some("no C: drive".to_owned()),
+                disk_space: Some(DiskSpaceReport {
+                    total_space_gig: 40,
+                    free_space_gig: 39,
+                }),
+            }),
+            usb_devices: Ok(UsbFacts::default()),
+            microsoft_account: Ok(AccountFacts::default()),
+            graphics_cards: Ok(GraphicsCardFacts::default()),
+            bluetooth_adapters: Ok(BluetoothFacts::default()),
+            various_wmi: Ok(VariousWmiFacts::default()),
+            printers: Ok(PrinterFacts::default()),
+            ..Snapshot::empty()
+        }
+    }
+
+    #[test]
+    fn rule_ids() {
+        ScoringProfile::from_toml(
+            r#"
+            [rules]
+            "sysinfo.ram_odd" = 5
+            "registry.vbox_guest_additions.Version" = 0
+            "license type.error" = 0
+            "os.timed_out" = 10
+            "#,
+        )
+        .unwrap();
+
+        for id in ["sysinfo.ram_od", "os", "license.error", "os.timed_out.x"] {
+            let toml = format!("[rules]\n\"{id}\" = 5");
+            let err = ScoringProfile::from_toml(&toml).unwrap_err();
+            assert_eq!(err.to_string(), format!("unknown rule id {id}"));
+        }
+    }
+
+    #[test]
+    fn rule_ids_cover_the_checks() {
+        let mut snapshots = vec![bare_machine()];
+        let exports = fs::read_dir("reference/fixtures")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .chain(["reference/registry.txt".into()]);
+        for path in exports {
+            let mut export = RegExport::default();
+            export.import_file(&path).unwrap();
+            snapshots.push(export.snapshot());
+        }
+
+        let mut reported = Vec::new();
+        for snapshot in snapshots {
+            let report = crate::detect_with(Arc::new(snapshot), &DetectOptions::default());
+            reported.extend(
+                report
+                    .checks
+                    .iter()
+                    .flat_map(|check| check.flags.findings())
+                    .map(|finding| finding.rule_id.to_string())
+                    .filter(|id| !id.starts_with("registry.")),
+            );
+        }
+
+        assert!(reported.len() > 20, "only reported {reported:?}");
+        for id in reported {
+            assert!(is_rule_id(&id), "{id} is reported but not in RULE_IDS");
+        }
+    }
+}
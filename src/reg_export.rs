@@ -0,0 +1,391 @@
+use std::{fs, path::Path};
+
+use anyhow::Context;
+
+use crate::{
+    facts::{MemoryKey, RegValue, SystemDeviceFacts},
+    registry_key::utf16_string,
+    snapshot::Snapshot,
+};
+
+/// Short names regedit accepts for the root keys
+const ROOT_ALIASES: [(&str, &str); 5] = [
+    ("HKLM", "HKEY_LOCAL_MACHINE"),
+    ("HKCU", "HKEY_CURRENT_USER"),
+    ("HKCR", "HKEY_CLASSES_ROOT"),
+    ("HKU", "HKEY_USERS"),
+    ("HKCC", "HKEY_CURRENT_CONFIG"),
+];
+
+/// Keys and values read from `.reg` exports (the `REGEDIT5` text format regedit writes).
+///
+/// Files are applied in order like an import, so later files win and `[-key]` / `"value"=-` delete. Lines that
+/// can't be parsed are skipped and listed in `warnings`.
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Default)]
+pub struct RegExport {
+    /// Holds the root keys, `HKEY_LOCAL_MACHINE`, `HKEY_USERS`...
+    pub root: MemoryKey,
+    /// `source:line: problem` for every skipped line
+    pub warnings: Vec<String>,
+}
+
+impl RegExport {
+    /// Reads a `.reg` file, which regedit writes as UTF-16 with a BOM but is often saved as UTF-8 after editing
+    pub fn import_file(&mut self, path: &Path) -> anyhow::Result<()> {
+        let data = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let text = data.strip_prefix(&[0xFF, 0xFE]).map_or_else(
+            || {
+                let utf8 = data.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(&data);
+                String::from_utf8_lossy(utf8).into_owned()
+            },
+            utf16_string,
+        );
+
+        self.import(&path.display().to_string(), &text);
+        Ok(())
+    }
+
+    /// Applies the keys and values in `text`, `source` is only used in warnings
+    pub fn import(&mut self, source: &str, text: &str) {
+        let mut lines = text.lines().enumerate();
+        // None outside of a key, or after a deleted one
+        let mut current = None::<String>;
+
+        while let Some((index, line)) = lines.next() {
+            let line = line.trim_start();
+            let mut warn = |problem: &str| {
+                self.warnings
+                    .push(format!("{source}:{}: {problem}", index + 1));
+            };
+
+            if line.trim_end().is_empty()
+                || line.starts_with(';')
+                || line.starts_with("//")
+                || line.starts_with("Windows Registry Editor Version")
+                || line.starts_with("REGEDIT4")
+            {
+                continue;
+            }
+
+            if let Some(key) = line.strip_prefix('[') {
+                current = None;
+                let Some(key) = key.trim_end().strip_suffix(']') else {
+                    warn("key isn't closed with ]");
+                    continue;
+                };
+
+                if let Some(key) = key.strip_prefix('-') {
+                    self.root.remove_subkey(&full_path(key));
+                } else {
+                    let path = full_path(key);
+                    self.root.subkey_mut(&path);
+                    current = Some(path);
+                }
+                continue;
+            }
+
+            let Some(path) = &current else {
+                warn("value outside of a key");
+                continue;
+            };
+
+            let Some((name, data)) = value_name(line) else {
+                warn("expected \"name\"= or @=");
+                continue;
+            };
+
+            // Long hex data is wrapped, each line but the last ending with a backslash
+            let mut data = data.trim_end().to_owned();
+            while data.starts_with("hex")
+                && let Some(wrapped) = data.strip_suffix('\\')
+            {
+                data = wrapped.to_owned();
+                match lines.next() {
+                    Some((_, next)) => data.push_str(next.trim()),
+                    None => break,
+                }
+            }
+
+            let key = self.root.subkey_mut(path);
+            match parse_data(&data) {
+                Ok(Some(value)) => key.set_value(&name, value),
+                Ok(None) => key.remove_value(&name),
+                Err(problem) => warn(problem),
+            }
+        }
+    }
+
+    /// `HKEY_LOCAL_MACHINE` as a snapshot that only has the registry.
+    ///
+    /// Exports don't have the device names the system devices check otherwise reads, it gets an empty list so
+    /// it can still look at the PCI devices in the registry.
+    #[must_use]
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            com_enabled: false,
+            registry: self
+                .root
+                .subkey("HKEY_LOCAL_MACHINE")
+                .cloned()
+                .unwrap_or_default(),
+            system_devices: Ok(SystemDeviceFacts::default()),
+            ..Snapshot::empty()
+        }
+    }
+}
+
+/// Spells out the root key if it is abbreviated
+fn full_path(path: &str) -> String {
+    let path = path.trim_matches('\\');
+    let (root, rest) = path.split_once('\\').unwrap_or((path, ""));
+    let root = ROOT_ALIASES
+        .iter()
+        .find(|(alias, _)| alias.eq_ignore_ascii_case(root))
+        .map_or(root, |(_, full)| full);
+
+    if rest.is_empty() {
+        root.to_owned()
+    } else {
+        format!("{root}\\{rest}")
+    }
+}
+
+/// Reads a quoted string at the start of `s`, returning it unescaped along with what follows the closing quote
+fn quoted(s: &str) -> Option<(String, &str)> {
+    let rest = s.strip_prefix('"')?;
+    let mut out = String::new();
+    let mut chars = rest.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            // Only \\ and \" are ever written
+            '\\' => out.push(chars.next()?.1),
+            '"' => return Some((out, rest.get(i + 1..)?)),
+            c => out.push(c),
+        }
+    }
+    None
+}
+
+/// Splits a value line into its name (empty for the default value `@`) and its data
+fn value_name(line: &str) -> Option<(String, &str)> {
+    let (name, rest) = match line.strip_prefix('@') {
+        Some(rest) => (String::new(), rest),
+        None => quoted(line)?,
+    };
+    let data = rest.trim_start().strip_prefix('=')?.trim_start();
+    Some((name, data))
+}
+
+/// Decodes the data after the `=`, `None` means the value is deleted
+fn parse_data(data: &str) -> Result<Option<RegValue>, &'static str> {
+    if data == "-" {
+        return Ok(None);
+    }
+
+    if data.starts_with('"') {
+        let (s, rest) = quoted(data).ok_or("string isn't closed")?;
+        if !rest.trim().is_empty() {
+            return Err("unexpected text after the string");
+        }
+        return Ok(Some(RegValue::String(s)));
+    }
+
+    if let Some(hex) = data.strip_prefix("dword:") {
+        let dword = u32::from_str_radix(hex.trim(), 16)
+            .ok()
+            .ok_or("bad dword")?;
+        return Ok(Some(RegValue::Dword(dword)));
+    }
+
+    let (ty, bytes) = if let Some(bytes) = data.strip_prefix("hex:") {
+        (3, bytes)
+    } else if let Some(rest) = data.strip_prefix("hex(") {
+        let (ty, bytes) = rest.split_once("):").ok_or("bad hex(type):")?;
+        let ty = u32::from_str_radix(ty, 16).ok().ok_or("bad hex(type):")?;
+        (ty, bytes)
+    } else {
+        return Err("unknown value type");
+    };
+
+    let bytes = bytes
+        .split(',')
+        .map(str::trim)
+        .filter(|b| !b.is_empty())
+        .map(|b| u8::from_str_radix(b, 16))
+        .collect::<Result<Vec<_>, _>>()
+        .ok()
+        .ok_or("bad hex byte")?;
+    Ok(Some(RegValue::from_raw(ty, &bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::facts::RegistryKey;
+
+    const KEY: &str = "HKEY_LOCAL_MACHINE\\SOFTWARE\\Test";
+
+    fn import(text: &str) -> RegExport {
+        let mut export = RegExport::default();
+        export.import("test.reg", text);
+        export
+    }
+
+    fn value(export: &RegExport, name: &str) -> anyhow::Result<RegValue> {
+        export.root.open(KEY)?.value(name)
+    }
+
+    #[test]
+    fn value_types() {
+        let export = import(
+            r#"Windows Registry Editor Version 5.00
+
+; Comments and blank lines are skipped
+[HKEY_LOCAL_MACHINE\SOFTWARE\Test]
+@="default"
+"Escaped"="C:\\Program Files\\\"VMware\""
+"Dword"=dword:0000001f
+"Binary"=hex:56,4d,77,61,72,65
+"Expand"=hex(2):25,00,41,00,25,00,00,00
+"Multi"=hex(7):61,00,00,00,62,00,00,00,00,00
+"Qword"=hex(b):01,00,00,00,00,00,00,00
+"Wrapped"=hex:01,02,\
+  03,04,\
+  05
+"#,
+        );
+        assert!(export.warnings.is_empty());
+        assert_eq!(
+            value(&export, "").unwrap(),
+            RegValue::String("default".to_owned())
+        );
+        assert_eq!(
+            value(&export, "Escaped").unwrap(),
+            RegValue::String("C:\\Program Files\\\"VMware\"".to_owned())
+        );
+        assert_eq!(value(&export, "Dword").unwrap(), RegValue::Dword(0x1F));
+        assert_eq!(
+            value(&export, "Binary").unwrap(),
+            RegValue::Binary(b"VMware".to_vec())
+        );
+        assert_eq!(
+            value(&export, "Expand").unwrap(),
+            RegValue::ExpandString("%A%".to_owned())
+        );
+        assert_eq!(
+            value(&export, "Multi").unwrap(),
+            RegValue::MultiString(vec!["a".to_owned(), "b".to_owned()])
+        );
+        assert_eq!(value(&export, "Qword").unwrap(), RegValue::Qword(1));
+        assert_eq!(
+            value(&export, "Wrapped").unwrap(),
+            RegValue::Binary(vec![1, 2, 3, 4, 5])
+        );
+    }
+
+    #[test]
+    fn deletions() {
+        let export = import(
+            r#"[HKLM\SOFTWARE\Test]
+"Kept"=dword:1
+"Deleted"=dword:2
+
+[HKLM\SOFTWARE\Test\Gone]
+"Value"=dword:3
+
+[HKEY_LOCAL_MACHINE\SOFTWARE\Test]
+"Deleted"=-
+
+[-HKLM\SOFTWARE\Test\Gone]
+"#,
+        );
+        assert!(export.warnings.is_empty());
+        assert_eq!(value(&export, "Kept").unwrap(), RegValue::Dword(1));
+        value(&export, "Deleted").unwrap_err();
+        export
+            .root
+            .open(&format!("{KEY}\\Gone"))
+            .map(drop)
+            .unwrap_err();
+        // HKLM is the same key as HKEY_LOCAL_MACHINE, and what the snapshot is made of
+        assert_eq!(export.root.keys().unwrap(), ["HKEY_LOCAL_MACHINE"]);
+        let snapshot = export.snapshot();
+        assert_eq!(
+            snapshot
+                .registry
+                .open("SOFTWARE\\Test")
+                .unwrap()
+                .value("Kept")
+                .unwrap(),
+            RegValue::Dword(1)
+        );
+    }
+
+    #[test]
+    fn warnings() {
+        let export = import(
+            r#""Orphan"=dword:1
+[HKLM\SOFTWARE\Test
+[HKLM\SOFTWARE\Test]
+not a value
+"Unclosed"="text
+"Trailing"="text" extra
+"Dword"=dword:xyz
+"Unknown"=sz:text
+"Type"=hex(zz):00
+"Byte"=hex:0g
+"Good"=dword:1
+
+[-HKLM\SOFTWARE\Deleted]
+"Lost"=dword:1
+"#,
+        );
+        assert_eq!(
+            export.warnings,
+            [
+                "test.reg:1: value outside of a key",
+                "test.reg:2: key isn't closed with ]",
+                "test.reg:4: expected \"name\"= or @=",
+                "test.reg:5: string isn't closed",
+                "test.reg:6: unexpected text after the string",
+                "test.reg:7: bad dword",
+                "test.reg:8: unknown value type",
+                "test.reg:9: bad hex(type):",
+                "test.reg:10: bad hex byte",
+                "test.reg:14: value outside of a key",
+            ]
+        );
+        assert_eq!(value(&export, "Good").unwrap(), RegValue::Dword(1));
+    }
+
+    #[test]
+    fn utf16_files() {
+        let text = "Windows Registry Editor Version 5.00\r\n\r\n[HKEY_LOCAL_MACHINE\\SOFTWARE\\Test]\r\n\"Name\"=\"Gerät\"\r\n";
+        let dir = std::env::temp_dir().join(format!("vm-detect-reg-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let utf16 = dir.join("utf16.reg");
+        let data = [0xFF, 0xFE]
+            .into_iter()
+            .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+            .collect::<Vec<_>>();
+        fs::write(&utf16, data).unwrap();
+        let utf8 = dir.join("utf8.reg");
+        fs::write(&utf8, [&[0xEF, 0xBB, 0xBF], text.as_bytes()].concat()).unwrap();
+
+        for path in [utf16, utf8] {
+            let mut export = RegExport::default();
+            export.import_file(&path).unwrap();
+            assert!(export.warnings.is_empty());
+            assert_eq!(
+                value(&export, "Name").unwrap(),
+                RegValue::String("Gerät".to_owned())
+            );
+        }
+        RegExport::default()
+            .import_file(&dir.join("missing.reg"))
+            .unwrap_err();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
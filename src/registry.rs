@@ -1,22 +1,398 @@
+use std::{borrow::Cow, fs, path::Path};
+
+use anyhow::{Context, bail, ensure};
+use serde::Deserialize;
+
 use crate::{
     any_value_contains, contains, eq,
-    flags::{Flags, Level},
-    key_contains, recurse, recurse_into,
-    registry_macros::execute_checks,
+    flags::{Finding, FindingKind, Flags, Level},
+    key_contains,
+    key_timestamps::plan_key_timestamps,
+    recurse, recurse_into,
+    registry_key::{RegValue, RegistryKey},
+    registry_macros::{Check, RegistryRule, Traversal, TraversalLimits, execute_checks},
+    registry_plan::{Plan, Preload},
     rule, starts_with,
+    system_devices::plan_registry_pci,
+    value_exists,
 };
-use windows_registry::LOCAL_MACHINE;
 
-pub fn score_registry(flags: &mut Flags) {
-    let rules = vec![
-        rule!("HARDWARE\\DESCRIPTION\\System\\BIOS" => {
+/// Written in rule paths instead of `ControlSet001`, resolved from `SYSTEM\\Select`
+pub const CURRENT_CONTROL_SET: &str = "{CurrentControlSet}";
+
+/// Which control sets the rules written against `{CurrentControlSet}` run on
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlSets {
+    /// The one `SYSTEM\\Select` marks as current
+    #[default]
+    Current,
+    /// Every `ControlSetNNN`, a finding from several of them is only counted once and lists them all
+    All,
+}
+
+/// The rules the registry check runs, the built-in set unless loaded from a rules file
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone)]
+pub struct RegistryRules {
+    pub rules: Vec<RegistryRule>,
+    pub control_sets: ControlSets,
+    /// Budget of every rule
+    pub limits: TraversalLimits,
+}
+
+impl Default for RegistryRules {
+    fn default() -> Self {
+        Self {
+            rules: builtin_rules(),
+            control_sets: ControlSets::Current,
+            limits: TraversalLimits::default(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RulesFile {
+    /// Keep the built-in rules, a rule in the file replaces the built-in one with the same id
+    #[serde(default)]
+    pub include_builtin: bool,
+    #[serde(default)]
+    pub control_sets: ControlSets,
+    #[serde(default)]
+    pub traversal: TraversalLimits,
+    #[serde(default)]
+    pub rules: Vec<RegistryRule>,
+}
+
+impl RulesFile {
+    /// Parses a rules file without validating the rules, JSON if it has a `.json` extension and TOML otherwise
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let is_json = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+
+        Ok(if is_json {
+            serde_json::from_str(&text)?
+        } else {
+            toml::from_str(&text)?
+        })
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        ensure!(
+            self.include_builtin || !self.rules.is_empty(),
+            "no rules, add some or set include_builtin"
+        );
+        for (index, rule) in self.rules.iter().enumerate() {
+            let context = || format!("rule {} ({})", index + 1, rule.id);
+            rule.validate().with_context(context)?;
+            if let Some(first) = self.rules.iter().take(index).position(|r| r.id == rule.id) {
+                bail!("{}: id is already used by rule {}", context(), first + 1);
+            }
+        }
+        Ok(())
+    }
+
+    /// The rules of the file, after the built-in ones it keeps
+    pub fn into_rules(self) -> RegistryRules {
+        let mut rules = if self.include_builtin {
+            builtin_rules()
+        } else {
+            Vec::new()
+        };
+        for rule in self.rules {
+            match rules.iter_mut().find(|r| r.id == rule.id) {
+                Some(builtin) => *builtin = rule,
+                None => rules.push(rule),
+            }
+        }
+
+        RegistryRules {
+            rules,
+            control_sets: self.control_sets,
+            limits: self.traversal,
+        }
+    }
+}
+
+impl RegistryRules {
+    pub fn from_toml(toml: &str) -> anyhow::Result<Self> {
+        Self::from_file(toml::from_str(toml)?)
+    }
+
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        Self::from_file(serde_json::from_str(json)?)
+    }
+
+    /// Loads a rules file, JSON if it has a `.json` extension and TOML otherwise
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        RulesFile::read(path)
+            .and_then(Self::from_file)
+            .with_context(|| format!("bad rules file {}", path.display()))
+    }
+
+    fn from_file(file: RulesFile) -> anyhow::Result<Self> {
+        file.validate()?;
+        Ok(file.into_rules())
+    }
+}
+
+/// The control set `SYSTEM\\Select` marks as current.
+///
+/// Without `Select` (exports and older snapshots) it is `ControlSet001`, or the `CurrentControlSet` link if that is
+/// all there is.
+pub fn current_control_set(local_machine: &dyn RegistryKey) -> String {
+    let selected = local_machine
+        .open("SYSTEM\\Select")
+        .ok()
+        .and_then(|select| match select.value("Current").ok()? {
+            RegValue::Dword(current) => Some(format!("ControlSet{current:03}")),
+            _ => None,
+        });
+
+    selected
+        .into_iter()
+        .chain(["ControlSet001".to_owned(), "CurrentControlSet".to_owned()])
+        .find(|set| local_machine.open(&format!("SYSTEM\\{set}")).is_ok())
+        .unwrap_or_else(|| "ControlSet001".to_owned())
+}
+
+/// Every `ControlSetNNN` under `SYSTEM`, or just the current one if there are none
+fn all_control_sets(local_machine: &dyn RegistryKey) -> Vec<String> {
+    let sets = local_machine
+        .open("SYSTEM")
+        .and_then(|system| system.keys())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|name| {
+            name.get(..10)
+                .is_some_and(|prefix| prefix.eq_ignore_ascii_case("ControlSet"))
+                && name.get(10..).is_some_and(|number| {
+                    number.len() == 3 && number.bytes().all(|b| b.is_ascii_digit())
+                })
+        })
+        .collect::<Vec<_>>();
+
+    if sets.is_empty() {
+        vec![current_control_set(local_machine)]
+    } else {
+        sets
+    }
+}
+
+/// A finding of a rule, merged with the others that have the same id
+struct Match<'a> {
+    finding: Finding,
+    /// Control sets it was seen in
+    sets: Vec<&'a str>,
+    /// Findings with the same id but different evidence that were dropped
+    more: usize,
+}
+
+impl<'a> Match<'a> {
+    fn add(matches: &mut Vec<Self>, finding: &Finding, set: Option<&'a str>) {
+        let Some(existing) = matches
+            .iter_mut()
+            .find(|m| m.finding.rule_id == finding.rule_id && m.finding.kind == finding.kind)
+        else {
+            matches.push(Self {
+                finding: finding.clone(),
+                sets: set.into_iter().collect(),
+                more: 0,
+            });
+            return;
+        };
+
+        if existing.finding.evidence != finding.evidence {
+            existing.more += 1;
+        } else if let Some(set) = set
+            && !existing.sets.contains(&set)
+        {
+            existing.sets.push(set);
+        }
+    }
+
+    /// The finding with the control sets (if asked for) and the number of dropped matches added to its evidence
+    fn into_finding(self, show_sets: bool) -> Finding {
+        let mut finding = self.finding;
+        let mut notes = Vec::new();
+        if show_sets && !self.sets.is_empty() {
+            notes.push(format!("in {}", self.sets.join(", ")));
+        }
+        if self.more > 0 {
+            notes.push(format!("+{} more", self.more));
+        }
+
+        if !notes.is_empty() {
+            let notes = notes.join(" ");
+            finding.evidence = Some(
+                finding
+                    .evidence
+                    .map_or_else(|| notes.clone(), |evidence| format!("{evidence} {notes}")),
+            );
+        }
+        finding
+    }
+}
+
+/// The keys a rule starts at, one per control set if its path has `{CurrentControlSet}`
+fn rule_roots<'a>(
+    rule: &RegistryRule,
+    control_sets: &'a [String],
+) -> Vec<(Option<&'a str>, String)> {
+    if rule.path.contains(CURRENT_CONTROL_SET) {
+        control_sets
+            .iter()
+            .map(|set| {
+                (
+                    Some(set.as_str()),
+                    rule.path.replace(CURRENT_CONTROL_SET, set),
+                )
+            })
+            .collect()
+    } else {
+        vec![(None, rule.path.to_string())]
+    }
+}
+
+/// Readers of the preload besides the rules, each gets the budget of a rule
+const OTHER_READERS: usize = 2;
+
+/// Copies what the rules, the PCI devices and the key timestamps read out of `source`, the root of
+/// `HKEY_LOCAL_MACHINE`, in a single pass. Every key is opened and read once however many of them look at it.
+pub fn preload_registry(source: &dyn RegistryKey, rules: &RegistryRules) -> Preload {
+    let control_set = current_control_set(source);
+    let control_sets = match rules.control_sets {
+        ControlSets::Current => vec![control_set.clone()],
+        ControlSets::All => all_control_sets(source),
+    };
+
+    let mut plan = Plan::default();
+    // What `current_control_set` reads, so it resolves the same on the copy
+    plan.at("SYSTEM\\Select").read_values();
+    for rule in &rules.rules {
+        for (_, path) in rule_roots(rule, &control_sets) {
+            plan.at(&path).add_checks(&rule.checks);
+        }
+    }
+    plan_registry_pci(&mut plan, &control_set);
+    plan_key_timestamps(&mut plan, &control_set);
+
+    let max_keys = rules
+        .limits
+        .max_keys
+        .saturating_mul(rules.rules.len() + OTHER_READERS);
+    Preload::load(source, &plan, max_keys)
+}
+
+/// `checks` without the ones that look for a key or value to be missing
+fn without_key_missing(checks: &[Check]) -> Vec<Check> {
+    checks
+        .iter()
+        .filter(|check| !matches!(check, Check::KeyMissing { .. }))
+        .map(|check| match check {
+            Check::RecurseKeys { checks } => Check::RecurseKeys {
+                checks: without_key_missing(checks),
+            },
+            Check::RecursePattern { pattern, checks } => Check::RecursePattern {
+                pattern: pattern.clone(),
+                checks: without_key_missing(checks),
+            },
+            check => check.clone(),
+        })
+        .collect()
+}
+
+/// Runs every rule against `preload`, see [`preload_registry`].
+///
+/// Findings of a rule with the same id are reported once, and a rule reports at most `max_matches` findings. A rule
+/// that runs out of traversal budget gets a `registry.<id>.traversal_budget` penalty on top. When the preload itself
+/// ran out of budget, a key that wasn't copied can't be told from one that doesn't exist, so the checks for missing
+/// keys are skipped.
+pub fn score_registry(preload: &Preload, rules: &RegistryRules, flags: &mut Flags) {
+    let local_machine = &preload.local_machine;
+    let control_sets = match rules.control_sets {
+        ControlSets::Current => vec![current_control_set(local_machine)],
+        ControlSets::All => all_control_sets(local_machine),
+    };
+    if preload.truncated {
+        flags
+            .tiny_penalty(
+                "registry.traversal_budget",
+                "registry rules ran out of traversal budget, some keys were not read",
+            )
+            .evidence(format!(
+                "{} keys read, checks for missing keys skipped",
+                preload.max_keys
+            ));
+    }
+
+    for rule in &rules.rules {
+        let checks = if preload.truncated {
+            Cow::Owned(without_key_missing(&rule.checks))
+        } else {
+            Cow::Borrowed(&rule.checks)
+        };
+        let mut traversal = Traversal::new(rules.limits);
+        let mut matches = Vec::new();
+        for (set, path) in rule_roots(rule, &control_sets) {
+            let Ok(root) = local_machine.open(&path) else {
+                continue;
+            };
+
+            let mut set_flags = flags.empty_like();
+            execute_checks(&mut set_flags, &rule.id, &*root, &checks, &mut traversal);
+            for finding in set_flags.findings() {
+                Match::add(&mut matches, finding, set);
+            }
+        }
+        let mut bonuses = 0;
+        let show_sets = rules.control_sets == ControlSets::All;
+        flags.extend(
+            matches
+                .into_iter()
+                .filter(|m| {
+                    if m.finding.kind == FindingKind::Bonus {
+                        bonuses += 1;
+                        bonuses <= rule.max_bonuses
+                    } else {
+                        true
+                    }
+                })
+                .take(rule.max_matches)
+                .map(|m| m.into_finding(show_sets)),
+        );
+
+        if traversal.overflowed() {
+            let limits = traversal.limits();
+            flags
+                .tiny_penalty(
+                    format!("registry.{}.traversal_budget", rule.id),
+                    "registry rule ran out of traversal budget, some keys were skipped",
+                )
+                .evidence(format!(
+                    "{} keys opened, at most {} keys and depth {}",
+                    traversal.keys(),
+                    limits.max_keys,
+                    limits.max_depth
+                ));
+        }
+    }
+}
+
+pub fn builtin_rules() -> Vec<RegistryRule> {
+    vec![
+        rule!("bios", "HARDWARE\\DESCRIPTION\\System\\BIOS" => {
             eq!("BIOSVendor", "VMware, Inc." => EndAll),
             starts_with!("BIOSVersion", "VMW" => Large),
         }),
-        rule!("SYSTEM\\ControlSet001\\Control\\SecureBoot\\Servicing\\DeviceAttributes" => {
+        rule!("secure_boot_attributes", "SYSTEM\\{CurrentControlSet}\\Control\\SecureBoot\\Servicing\\DeviceAttributes" => {
             eq!("FirmwareManufacturer", "VMware, Inc." => Large),
         }),
-        rule!("BCD00000000\\Objects" => {
+        rule!("bcd_objects", "BCD00000000\\Objects" => {
             recurse!(
                 recurse_into!("Elements" => {
                     recurse!(
@@ -25,13 +401,13 @@ pub fn score_registry(flags: &mut Flags) {
                 })
             ),
         }),
-        rule!("SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\Component Based Servicing\\DriverOperations\\1" => {
+        rule!("driver_operations", "SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\Component Based Servicing\\DriverOperations\\1" => {
             recurse!(
                 contains!("Identity", "dual_vmxnet3" => Large),
                 contains!("Inf", "vmxnet3.inf" => Large),
             ),
         }),
-        rule!("SYSTEM\\ControlSet001\\Control\\Class" => {
+        rule!("device_class", "SYSTEM\\{CurrentControlSet}\\Control\\Class" => {
             recurse!(
                 recurse!(
                     eq!("HardwareInformation.ChipType", "VMware" => Large),
@@ -39,14 +415,14 @@ pub fn score_registry(flags: &mut Flags) {
                 )
             ),
         }),
-        rule!("SYSTEM\\ControlSet001\\Services" => {
+        rule!("services", "SYSTEM\\{CurrentControlSet}\\Services" => {
             recurse!(
                 recurse!(
                     contains!("Name", "VirtualBox" => Large),
                 )
             )
         }),
-        rule!("SYSTEM\\ControlSet001\\Control\\DeviceClasses" => {
+        rule!("device_classes", "SYSTEM\\{CurrentControlSet}\\Control\\DeviceClasses" => {
             recurse!(
                 key_contains!("Ven_VMware_&Prod_VMware_Virtual_S" | "CDRom" => Large),
                 recurse!(
@@ -54,7 +430,7 @@ pub fn score_registry(flags: &mut Flags) {
                 )
             ),
         }),
-        rule!("SYSTEM\\ControlSet001\\Control\\DeviceContainers" => {
+        rule!("device_containers", "SYSTEM\\{CurrentControlSet}\\Control\\DeviceContainers" => {
             recurse!(
                 recurse_into!("BaseContainers" => {
                     recurse!(
@@ -71,7 +447,7 @@ pub fn score_registry(flags: &mut Flags) {
                 })
             ),
         }),
-        rule!("SYSTEM\\ControlSet001\\Control\\Video" => {
+        rule!("video", "SYSTEM\\{CurrentControlSet}\\Control\\Video" => {
             recurse!(
                 recurse!(
                     contains!("Service", "VBox" => Large),
@@ -84,7 +460,7 @@ pub fn score_registry(flags: &mut Flags) {
                 )
             ),
         }),
-        rule!("SYSTEM\\ControlSet001\\Enum\\PCI" => {
+        rule!("enum_pci", "SYSTEM\\{CurrentControlSet}\\Enum\\PCI" => {
             recurse!(
                 recurse!(
                     contains!("DeviceDesc", "vmwarebusdevicedesc" | "VMware VMCI" => Large),
@@ -92,26 +468,25 @@ pub fn score_registry(flags: &mut Flags) {
                 )
             ),
         }),
-        rule!("SYSTEM\\ControlSet001\\Enum\\SCSI" => {
+        // Enum\SCSI\<device>\<instance>
+        rule!("enum_scsi", "SYSTEM\\{CurrentControlSet}\\Enum\\SCSI" => {
+            key_contains!(
+                "Ven_NECVMWar" |
+                "Prod_VMware_SATA_CD01" |
+                "Prod_VMware_Virtual_S" => Large
+            ),
             recurse!(
-                key_contains!(
-                    "Ven_NECVMWar" |
-                    "Prod_VMware_SATA_CD01" |
-                    "Prod_VMware_Virtual_S" => Large
-                ),
                 recurse!(
-                    recurse!(
-                        contains!("FriendlyName", "NECVMWar" | "VMware" | "VBOX" => Large),
-                        contains!("HardwareID", "VMware" | "VBOX" => Large),
-                        contains!("DeviceDesc", "Microsoft PS/2" => Medium)
-                    )
+                    contains!("FriendlyName", "NECVMWar" | "VMware" | "VBOX" => Large),
+                    contains!("HardwareID", "VMware" | "VBOX" => Large),
+                    contains!("DeviceDesc", "Microsoft PS/2" => Medium)
                 )
             ),
         }),
-        rule!("SYSTEM\\DriverDatabase\\DriverPackages" => {
+        rule!("driver_packages", "SYSTEM\\DriverDatabase\\DriverPackages" => {
             recurse!(
                 recurse_into!("Strings" => {
-                    contains!("loc.vmwarebusdevicedesc" => Large),
+                    value_exists!("loc.vmwarebusdevicedesc" => Large),
                 }),
                 recurse_into!("Descriptors\\PCI" => {
                     recurse!(
@@ -121,7 +496,7 @@ pub fn score_registry(flags: &mut Flags) {
                 })
             ),
         }),
-        rule!("SYSTEM\\HardwareConfig" => {
+        rule!("hardware_config", "SYSTEM\\HardwareConfig" => {
             recurse!(
                 eq!("BIOSVendor", "VMware, Inc." => Large),
                 recurse_into!("ComputerIds" => {
@@ -129,11 +504,148 @@ pub fn score_registry(flags: &mut Flags) {
                 })
             ),
         }),
-    ];
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{key_missing, registry_key::MemoryKey};
+
+    fn rules(max_keys: usize) -> RegistryRules {
+        RegistryRules {
+            rules: vec![rule!("test", "SOFTWARE\\Vendor" => {
+                recurse!(contains!("Name", "vmware" => Large)),
+                key_missing!("Tools" => bonus Small),
+            })],
+            control_sets: ControlSets::Current,
+            limits: TraversalLimits {
+                max_keys,
+                ..TraversalLimits::default()
+            },
+        }
+    }
+
+    fn score(local_machine: &MemoryKey, rules: &RegistryRules) -> (Preload, Vec<String>) {
+        let preload = preload_registry(local_machine, rules);
+        let mut flags = Flags::new("registry");
+        score_registry(&preload, rules, &mut flags);
+        let ids = flags
+            .findings()
+            .iter()
+            .map(|f| f.rule_id.to_string())
+            .collect();
+        (preload, ids)
+    }
+
+    #[test]
+    fn truncated_preload_skips_missing_key_checks() {
+        let mut local_machine = MemoryKey::default();
+        for index in 0..20 {
+            local_machine
+                .subkey_mut(&format!("SOFTWARE\\Vendor\\Device{index:02}"))
+                .set_value("Name", RegValue::String("Device".to_owned()));
+        }
+        local_machine.subkey_mut("SOFTWARE\\Vendor\\Tools");
+
+        let (preload, ids) = score(&local_machine, &rules(1000));
+        assert!(!preload.truncated);
+        assert!(ids.is_empty(), "{ids:?}");
+
+        // Tools sorts after every device, so it isn't copied
+        let (preload, ids) = score(&local_machine, &rules(5));
+        assert!(preload.truncated);
+        assert!(
+            preload
+                .local_machine
+                .open("SOFTWARE\\Vendor\\Tools")
+                .is_err()
+        );
+        assert_eq!(
+            ids,
+            [
+                "registry.traversal_budget",
+                "registry.test.traversal_budget"
+            ]
+        );
+    }
 
-    for rule in rules {
-        if let Ok(root) = LOCAL_MACHINE.open(rule.path) {
-            execute_checks(flags, &root, &rule.checks);
+    /// A `SYSTEM` with a `vmci` service in each of `sets`
+    fn control_sets(sets: &[&str]) -> MemoryKey {
+        let mut local_machine = MemoryKey::default();
+        for set in sets {
+            local_machine
+                .subkey_mut(&format!("SYSTEM\\{set}\\Services\\vmci"))
+                .set_value("DisplayName", RegValue::String("VMware VMCI".to_owned()));
         }
+        local_machine
+    }
+
+    fn vmci_rules(control_sets: ControlSets) -> RegistryRules {
+        RegistryRules {
+            rules: vec![
+                rule!("vmci", "SYSTEM\\{CurrentControlSet}\\Services\\vmci" => {
+                    contains!("DisplayName", "VMware" => Large),
+                }),
+            ],
+            control_sets,
+            limits: TraversalLimits::default(),
+        }
+    }
+
+    #[test]
+    fn current_control_set_from_select() {
+        let mut local_machine = control_sets(&["ControlSet001", "ControlSet002"]);
+        local_machine
+            .subkey_mut("SYSTEM\\Select")
+            .set_value("Current", RegValue::Dword(2));
+        assert_eq!(current_control_set(&local_machine), "ControlSet002");
+
+        // A Select pointing at a set that isn't there falls back to the first one
+        local_machine
+            .subkey_mut("SYSTEM\\Select")
+            .set_value("Current", RegValue::Dword(3));
+        assert_eq!(current_control_set(&local_machine), "ControlSet001");
+    }
+
+    #[test]
+    fn current_control_set_link() {
+        // Exports of the live registry only have the link
+        let local_machine = control_sets(&["CurrentControlSet"]);
+        assert_eq!(current_control_set(&local_machine), "CurrentControlSet");
+        assert_eq!(all_control_sets(&local_machine), ["CurrentControlSet"]);
+
+        let (_, ids) = score(&local_machine, &vmci_rules(ControlSets::Current));
+        assert_eq!(ids, ["registry.vmci.DisplayName"]);
+    }
+
+    #[test]
+    fn all_control_sets_report_once() {
+        let mut local_machine = control_sets(&["ControlSet001", "ControlSet002"]);
+        local_machine
+            .subkey_mut("SYSTEM\\Select")
+            .set_value("Current", RegValue::Dword(1));
+        local_machine.subkey_mut("SYSTEM\\ControlSetBackup");
+        assert_eq!(
+            all_control_sets(&local_machine),
+            ["ControlSet001", "ControlSet002"]
+        );
+
+        let evidence = |control_sets| {
+            let rules = vmci_rules(control_sets);
+            let preload = preload_registry(&local_machine, &rules);
+            let mut flags = Flags::new("registry");
+            score_registry(&preload, &rules, &mut flags);
+            flags
+                .findings()
+                .iter()
+                .map(|f| f.evidence.clone().unwrap_or_default())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(evidence(ControlSets::Current), ["VMware VMCI"]);
+        assert_eq!(
+            evidence(ControlSets::All),
+            ["VMware VMCI in ControlSet001, ControlSet002"]
+        );
     }
 }
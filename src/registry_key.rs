@@ -0,0 +1,357 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::win_time::FileTime;
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RegValue {
+    String(String),
+    ExpandString(String),
+    MultiString(Vec<String>),
+    Dword(u32),
+    Qword(u64),
+    Binary(Vec<u8>),
+    Other { ty: u32, data: Vec<u8> },
+}
+
+impl RegValue {
+    /// Decodes raw value data of registry type `ty` (`REG_SZ` = 1, `REG_DWORD` = 4, ...), as stored in hives and
+    /// `.reg` exports. Data that doesn't fit its type is kept as [`RegValue::Other`].
+    #[must_use]
+    pub fn from_raw(ty: u32, data: &[u8]) -> Self {
+        let other = || Self::Other {
+            ty,
+            data: data.to_vec(),
+        };
+
+        match ty {
+            1 => Self::String(utf16_string(data)),
+            2 => Self::ExpandString(utf16_string(data)),
+            3 => Self::Binary(data.to_vec()),
+            4 => data
+                .try_into()
+                .map_or_else(|_| other(), |b| Self::Dword(u32::from_le_bytes(b))),
+            5 => data
+                .try_into()
+                .map_or_else(|_| other(), |b| Self::Dword(u32::from_be_bytes(b))),
+            7 => {
+                let mut strings = utf16_string(data)
+                    .split('\0')
+                    .map(str::to_owned)
+                    .collect::<Vec<_>>();
+                // The list ends with an empty string
+                while strings.last().is_some_and(String::is_empty) {
+                    strings.pop();
+                }
+                Self::MultiString(strings)
+            }
+            11 => data
+                .try_into()
+                .map_or_else(|_| other(), |b| Self::Qword(u64::from_le_bytes(b))),
+            _ => other(),
+        }
+    }
+
+    /// The value as a string, only for `REG_SZ` and `REG_EXPAND_SZ`
+    #[must_use]
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) | Self::ExpandString(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+/// Decodes UTF-16LE, dropping the trailing NULs strings are usually stored with
+#[must_use]
+pub fn utf16_string(data: &[u8]) -> String {
+    let (pairs, _) = data.as_chunks::<2>();
+    let units = pairs
+        .iter()
+        .map(|pair| u16::from_le_bytes(*pair))
+        .collect::<Vec<_>>();
+    String::from_utf16_lossy(&units)
+        .trim_end_matches('\0')
+        .to_owned()
+}
+
+/// Subkeys along with their names, see [`RegistryKey::subkeys`]
+pub type Subkeys<'a> = Box<dyn Iterator<Item = (String, Box<dyn RegistryKey + 'a>)> + 'a>;
+
+/// A registry key that rules can be evaluated against, either the live registry or a copy of it
+pub trait RegistryKey {
+    /// Opens a subkey, `path` may contain multiple `\\` separated components
+    fn open(&self, path: &str) -> anyhow::Result<Box<dyn RegistryKey + '_>>;
+    fn keys(&self) -> anyhow::Result<Vec<String>>;
+    fn values(&self) -> anyhow::Result<Vec<(String, RegValue)>>;
+    fn value(&self, name: &str) -> anyhow::Result<RegValue>;
+
+    /// Every subkey along with its name, opened as it is iterated. Sources that can do better than opening each name
+    /// should.
+    fn subkeys(&self) -> anyhow::Result<Subkeys<'_>> {
+        Ok(Box::new(self.keys()?.into_iter().filter_map(|name| {
+            let key = self.open(&name).ok()?;
+            Some((name, key))
+        })))
+    }
+
+    /// When the key was last written. Not every source keeps it.
+    fn last_written(&self) -> anyhow::Result<FileTime> {
+        bail!("nt")
+    }
+
+    fn get_string(&self, name: &str) -> anyhow::Result<String> {
+        match self.value(name)? {
+            RegValue::String(s) | RegValue::ExpandString(s) => Ok(s),
+            _ => bail!("ns"),
+        }
+    }
+
+    fn get_multi_string(&self, name: &str) -> anyhow::Result<Vec<String>> {
+        match self.value(name)? {
+            RegValue::MultiString(v) => Ok(v),
+            _ => bail!("nms"),
+        }
+    }
+}
+
+impl<T: RegistryKey + ?Sized> RegistryKey for &T {
+    fn open(&self, path: &str) -> anyhow::Result<Box<dyn RegistryKey + '_>> {
+        (**self).open(path)
+    }
+
+    fn keys(&self) -> anyhow::Result<Vec<String>> {
+        (**self).keys()
+    }
+
+    fn values(&self) -> anyhow::Result<Vec<(String, RegValue)>> {
+        (**self).values()
+    }
+
+    fn value(&self, name: &str) -> anyhow::Result<RegValue> {
+        (**self).value(name)
+    }
+
+    fn subkeys(&self) -> anyhow::Result<Subkeys<'_>> {
+        (**self).subkeys()
+    }
+
+    fn last_written(&self) -> anyhow::Result<FileTime> {
+        (**self).last_written()
+    }
+
+    fn get_string(&self, name: &str) -> anyhow::Result<String> {
+        (**self).get_string(name)
+    }
+
+    fn get_multi_string(&self, name: &str) -> anyhow::Result<Vec<String>> {
+        (**self).get_multi_string(name)
+    }
+}
+
+/// An in-memory registry tree, names are matched case-insensitively like the real registry
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct MemoryKey {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub keys: BTreeMap<String, Self>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub values: BTreeMap<String, RegValue>,
+    /// When the source had it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_written: Option<FileTime>,
+}
+
+impl MemoryKey {
+    #[must_use]
+    pub fn subkey(&self, name: &str) -> Option<&Self> {
+        self.keys
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v)
+    }
+
+    /// Gets or creates the subkey at `path`, creating any missing keys along the way
+    pub fn subkey_mut(&mut self, path: &str) -> &mut Self {
+        path.split('\\')
+            .filter(|c| !c.is_empty())
+            .fold(self, |key, component| {
+                let existing = key
+                    .keys
+                    .keys()
+                    .find(|k| k.eq_ignore_ascii_case(component))
+                    .cloned();
+
+                key.keys
+                    .entry(existing.unwrap_or_else(|| component.to_owned()))
+                    .or_default()
+            })
+    }
+
+    pub fn set_value(&mut self, name: &str, value: RegValue) {
+        let existing = self
+            .values
+            .keys()
+            .find(|k| k.eq_ignore_ascii_case(name))
+            .cloned();
+
+        self.values
+            .insert(existing.unwrap_or_else(|| name.to_owned()), value);
+    }
+
+    /// Removes the subkey at `path` along with everything under it
+    pub fn remove_subkey(&mut self, path: &str) {
+        let path = path.trim_matches('\\');
+        let (parent, name) = path.rsplit_once('\\').unwrap_or(("", path));
+        let parent =
+            parent
+                .split('\\')
+                .filter(|c| !c.is_empty())
+                .try_fold(self, |key, component| {
+                    key.keys
+                        .iter_mut()
+                        .find(|(k, _)| k.eq_ignore_ascii_case(component))
+                        .map(|(_, v)| v)
+                });
+
+        if let Some(parent) = parent {
+            parent.keys.retain(|k, _| !k.eq_ignore_ascii_case(name));
+        }
+    }
+
+    pub fn remove_value(&mut self, name: &str) {
+        self.values.retain(|k, _| !k.eq_ignore_ascii_case(name));
+    }
+}
+
+impl RegistryKey for MemoryKey {
+    fn open(&self, path: &str) -> anyhow::Result<Box<dyn RegistryKey + '_>> {
+        let key = path
+            .split('\\')
+            .filter(|c| !c.is_empty())
+            .try_fold(self, |key, component| key.subkey(component))
+            .context("nk")?;
+
+        Ok(Box::new(key))
+    }
+
+    fn keys(&self) -> anyhow::Result<Vec<String>> {
+        Ok(self.keys.keys().cloned().collect())
+    }
+
+    fn values(&self) -> anyhow::Result<Vec<(String, RegValue)>> {
+        Ok(self
+            .values
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect())
+    }
+
+    fn value(&self, name: &str) -> anyhow::Result<RegValue> {
+        self.values
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.clone())
+            .context("nv")
+    }
+
+    fn subkeys(&self) -> anyhow::Result<Subkeys<'_>> {
+        Ok(Box::new(self.keys.iter().map(|(name, key)| {
+            (name.clone(), Box::new(key) as Box<dyn RegistryKey>)
+        })))
+    }
+
+    fn last_written(&self) -> anyhow::Result<FileTime> {
+        self.last_written.context("nt")
+    }
+}
+
+#[cfg(windows)]
+impl From<windows_registry::Value> for RegValue {
+    fn from(value: windows_registry::Value) -> Self {
+        use windows_registry::Type;
+
+        let data = value.to_vec();
+        let other = |ty| Self::Other {
+            ty,
+            data: data.clone(),
+        };
+
+        match value.ty() {
+            Type::String => value.try_into().map_or_else(|_| other(1), Self::String),
+            Type::ExpandString => value
+                .try_into()
+                .map_or_else(|_| other(2), Self::ExpandString),
+            Type::MultiString => value
+                .try_into()
+                .map_or_else(|_| other(7), Self::MultiString),
+            Type::U32 => value.try_into().map_or_else(|_| other(4), Self::Dword),
+            Type::U64 => value.try_into().map_or_else(|_| other(11), Self::Qword),
+            Type::Bytes => Self::Binary(data),
+            Type::Other(ty) => other(ty),
+        }
+    }
+}
+
+#[cfg(windows)]
+impl RegistryKey for windows_registry::Key {
+    fn open(&self, path: &str) -> anyhow::Result<Box<dyn RegistryKey + '_>> {
+        Ok(Box::new(Self::open(self, path)?))
+    }
+
+    fn keys(&self) -> anyhow::Result<Vec<String>> {
+        Ok(Self::keys(self)?.collect())
+    }
+
+    fn values(&self) -> anyhow::Result<Vec<(String, RegValue)>> {
+        Ok(Self::values(self)?
+            .map(|(name, value)| (name, value.into()))
+            .collect())
+    }
+
+    fn value(&self, name: &str) -> anyhow::Result<RegValue> {
+        Ok(self.get_value(name)?.into())
+    }
+
+    fn last_written(&self) -> anyhow::Result<FileTime> {
+        use windows::Win32::{
+            Foundation::{ERROR_SUCCESS, FILETIME},
+            System::Registry::{HKEY, RegQueryInfoKeyW},
+        };
+
+        let mut file_time = FILETIME::default();
+        let result = unsafe {
+            RegQueryInfoKeyW(
+                HKEY(self.as_raw()),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(&raw mut file_time),
+            )
+        };
+        anyhow::ensure!(result == ERROR_SUCCESS, "nt");
+
+        Ok(FileTime::from_parts(
+            file_time.dwLowDateTime,
+            file_time.dwHighDateTime,
+        ))
+    }
+
+    fn get_string(&self, name: &str) -> anyhow::Result<String> {
+        Ok(Self::get_string(self, name)?)
+    }
+
+    fn get_multi_string(&self, name: &str) -> anyhow::Result<Vec<String>> {
+        Ok(Self::get_multi_string(self, name)?)
+    }
+}
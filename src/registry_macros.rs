@@ -1,100 +1,705 @@
 #![allow(dead_code)]
 
-use crate::flags::{Flags, Level};
+use std::{borrow::Cow, fmt};
 
+use anyhow::{Context, bail, ensure};
+use regex_lite::{Regex, RegexBuilder};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+use crate::{
+    flags::{Finding, FindingKind, Flags, Level},
+    registry_key::{RegValue, RegistryKey},
+};
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct RegistryRule {
-    pub path: &'static str,
+    /// Stable identifier, used to build the rule ids of the findings this produces
+    pub id: Cow<'static, str>,
+    /// Key under `HKEY_LOCAL_MACHINE` the checks start at
+    pub path: Cow<'static, str>,
+    /// Most findings the rule reports, after the ones with the same id are merged
+    #[serde(default = "default_max_matches")]
+    pub max_matches: usize,
+    /// Most bonuses the rule awards, so a recursive match can't add one for every key it visits
+    #[serde(default = "default_max_bonuses")]
+    pub max_bonuses: usize,
     pub checks: Vec<Check>,
 }
+
+pub const DEFAULT_MAX_MATCHES: usize = 10;
+pub const DEFAULT_MAX_BONUSES: usize = 1;
+
+const fn default_max_matches() -> usize {
+    DEFAULT_MAX_MATCHES
+}
+
+const fn default_max_bonuses() -> usize {
+    DEFAULT_MAX_BONUSES
+}
+
+/// How far a single rule may walk the registry
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TraversalLimits {
+    /// Most recursive checks nested in each other that are followed
+    pub max_depth: usize,
+    /// Most keys opened by recursive checks
+    pub max_keys: usize,
+}
+
+impl Default for TraversalLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: 8,
+            max_keys: 50_000,
+        }
+    }
+}
+
+/// What is left of a rule's traversal budget
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct Traversal {
+    limits: TraversalLimits,
+    depth: usize,
+    keys: usize,
+    overflowed: bool,
+}
+
+impl Traversal {
+    #[must_use]
+    pub const fn new(limits: TraversalLimits) -> Self {
+        Self {
+            limits,
+            depth: 0,
+            keys: 0,
+            overflowed: false,
+        }
+    }
+
+    /// Whether some keys were skipped because the budget ran out
+    #[must_use]
+    pub const fn overflowed(&self) -> bool {
+        self.overflowed
+    }
+
+    /// Whether every key the budget allows has been opened
+    #[must_use]
+    pub const fn exhausted(&self) -> bool {
+        self.keys >= self.limits.max_keys
+    }
+
+    #[must_use]
+    pub const fn keys(&self) -> usize {
+        self.keys
+    }
+
+    #[must_use]
+    pub const fn limits(&self) -> TraversalLimits {
+        self.limits
+    }
+
+    /// Runs `checks` on the key `open` returns if the budget allows it
+    fn descend<'k>(
+        &mut self,
+        flags: &mut Flags,
+        rule_id: &str,
+        open: impl FnOnce() -> anyhow::Result<Box<dyn RegistryKey + 'k>>,
+        checks: &[Check],
+    ) {
+        if self.depth >= self.limits.max_depth || self.exhausted() {
+            self.overflowed = true;
+            return;
+        }
+
+        self.keys += 1;
+        if let Ok(sub_key) = open() {
+            self.depth += 1;
+            execute_checks(flags, rule_id, &*sub_key, checks, self);
+            self.depth -= 1;
+        }
+    }
+}
+
+/// What to look for in a key. `key` is the name of the value to read, matching is case-insensitive.
+///
+/// A match is a `penalty`, or a `bonus` for evidence of real hardware, every check but the recursive ones sets
+/// exactly one of them.
+///
+/// The string matchers look at `REG_SZ` and `REG_EXPAND_SZ` values and at every element of `REG_MULTI_SZ` ones.
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
 pub enum Check {
     StringStartsWith {
-        key: &'static str,
-        values: Vec<&'static str>,
-        penalty: Level,
+        key: Cow<'static, str>,
+        values: Vec<Cow<'static, str>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        penalty: Option<Level>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bonus: Option<Level>,
     },
     StringEquals {
-        key: &'static str,
-        values: Vec<&'static str>,
-        penalty: Level,
+        key: Cow<'static, str>,
+        values: Vec<Cow<'static, str>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        penalty: Option<Level>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bonus: Option<Level>,
     },
     StringContains {
-        key: &'static str,
-        values: Vec<&'static str>,
-        penalty: Level,
+        key: Cow<'static, str>,
+        values: Vec<Cow<'static, str>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        penalty: Option<Level>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bonus: Option<Level>,
+    },
+    StringMatches {
+        key: Cow<'static, str>,
+        pattern: Pattern,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        penalty: Option<Level>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bonus: Option<Level>,
+    },
+    /// Compares a `REG_DWORD` or `REG_QWORD` value against `value`
+    Number {
+        key: Cow<'static, str>,
+        op: Comparison,
+        value: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        penalty: Option<Level>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bonus: Option<Level>,
+    },
+    /// Searches `REG_BINARY` (or other raw) data for any of `values`
+    BinaryContains {
+        key: Cow<'static, str>,
+        values: Vec<BytePattern>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        penalty: Option<Level>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bonus: Option<Level>,
     },
     KeyNameContains {
-        values: Vec<&'static str>,
-        penalty: Level,
+        values: Vec<Cow<'static, str>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        penalty: Option<Level>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bonus: Option<Level>,
     },
     ValueContains {
-        values: Vec<&'static str>,
-        penalty: Level,
+        values: Vec<Cow<'static, str>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        penalty: Option<Level>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bonus: Option<Level>,
     },
-    RecurseKeys {
-        checks: Vec<Check>,
+    /// Fires if the subkey at `path` exists, and has the value `value` if one is given. An empty `path` is the
+    /// key itself.
+    KeyExists {
+        #[serde(default)]
+        path: Cow<'static, str>,
+        #[serde(default)]
+        value: Option<Cow<'static, str>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        penalty: Option<Level>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bonus: Option<Level>,
     },
+    /// The opposite of `KeyExists`, for keys every real machine has
+    KeyMissing {
+        #[serde(default)]
+        path: Cow<'static, str>,
+        #[serde(default)]
+        value: Option<Cow<'static, str>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        penalty: Option<Level>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bonus: Option<Level>,
+    },
+    /// Runs `checks` on every subkey
+    RecurseKeys { checks: Vec<Self> },
+    /// Runs `checks` on the subkey at `pattern`, if it exists
     RecursePattern {
-        pattern: &'static str,
-        checks: Vec<Check>,
+        pattern: Cow<'static, str>,
+        checks: Vec<Self>,
     },
 }
 
+/// A case-insensitive regex that keeps its source, so rules can be written back out
+#[derive(Clone)]
+pub struct Pattern {
+    source: Cow<'static, str>,
+    regex: Regex,
+}
+
+impl Pattern {
+    pub fn new(source: impl Into<Cow<'static, str>>) -> anyhow::Result<Self> {
+        let source = source.into();
+        let regex = RegexBuilder::new(&source)
+            .case_insensitive(true)
+            .build()
+            .with_context(|| format!("bad regex {source}"))?;
+        Ok(Self { source, regex })
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// The first match in `text`
+    #[must_use]
+    pub fn find<'a>(&self, text: &'a str) -> Option<&'a str> {
+        self.regex.find(text).map(|m| m.as_str())
+    }
+}
+
+#[cfg(debug_assertions)]
+impl fmt::Debug for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.source, f)
+    }
+}
+
+impl Serialize for Pattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Self::new(String::deserialize(deserializer)?)
+            .map_err(|err| de::Error::custom(format!("{err:#}")))
+    }
+}
+
+/// Bytes to search for, written as hex with `??` matching any byte, e.x. `56 4D ?? 61`
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, PartialEq, Eq)]
+pub struct BytePattern(Vec<Option<u8>>);
+
+impl BytePattern {
+    pub fn parse(hex: &str) -> anyhow::Result<Self> {
+        let digits = hex
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>();
+        let (pairs, rest) = digits.as_bytes().as_chunks::<2>();
+        ensure!(rest.is_empty(), "odd number of hex digits in {hex}");
+
+        let bytes = pairs
+            .iter()
+            .map(|pair| match pair {
+                b"??" => Ok(None),
+                _ => std::str::from_utf8(pair)
+                    .ok()
+                    .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                    .map(Some)
+                    .with_context(|| format!("bad hex byte in {hex}")),
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self(bytes))
+    }
+
+    /// The exact bytes of `text`
+    #[must_use]
+    pub fn text(text: &str) -> Self {
+        Self(text.bytes().map(Some).collect())
+    }
+
+    /// Offset of the first match in `data`
+    #[must_use]
+    pub fn find(&self, data: &[u8]) -> Option<usize> {
+        if self.0.is_empty() {
+            return None;
+        }
+
+        data.windows(self.0.len()).position(|window| {
+            window
+                .iter()
+                .zip(&self.0)
+                .all(|(byte, expected)| expected.is_none_or(|expected| expected == *byte))
+        })
+    }
+
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Display for BytePattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, byte) in self.0.iter().enumerate() {
+            if index > 0 {
+                f.write_str(" ")?;
+            }
+            match byte {
+                Some(byte) => write!(f, "{byte:02X}")?,
+                None => f.write_str("??")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for BytePattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for BytePattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Self::parse(&String::deserialize(deserializer)?)
+            .map_err(|err| de::Error::custom(format!("{err:#}")))
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    #[must_use]
+    pub const fn holds(self, left: u64, right: u64) -> bool {
+        match self {
+            Self::Eq => left == right,
+            Self::Ne => left != right,
+            Self::Lt => left < right,
+            Self::Le => left <= right,
+            Self::Gt => left > right,
+            Self::Ge => left >= right,
+        }
+    }
+
+    #[must_use]
+    pub const fn symbol(self) -> &'static str {
+        match self {
+            Self::Eq => "==",
+            Self::Ne => "!=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+        }
+    }
+}
+
+impl RegistryRule {
+    /// Rejects rules that can't be evaluated or would never (or always) match
+    pub fn validate(&self) -> anyhow::Result<()> {
+        ensure!(
+            !self.id.is_empty()
+                && self
+                    .id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_'),
+            "id must be made of letters, digits and _"
+        );
+        ensure!(!self.path.is_empty(), "path is empty");
+        ensure!(
+            self.max_matches > 0,
+            "max_matches is 0, the rule would never report anything"
+        );
+        validate_checks(&self.checks)
+    }
+}
+
+fn validate_checks(checks: &[Check]) -> anyhow::Result<()> {
+    ensure!(!checks.is_empty(), "checks is empty");
+    for (index, check) in checks.iter().enumerate() {
+        check
+            .validate()
+            .with_context(|| format!("check {} ({})", index + 1, check.kind()))?;
+    }
+    Ok(())
+}
+
+fn validate_values(values: &[Cow<'static, str>]) -> anyhow::Result<()> {
+    ensure!(
+        !values.is_empty(),
+        "values is empty, the check would never match"
+    );
+    ensure!(
+        values.iter().all(|v| !v.is_empty()),
+        "values has an empty string, the check would match everything"
+    );
+    Ok(())
+}
+
+impl Check {
+    /// Name of the variant as written in rule files
+    #[must_use]
+    pub const fn kind(&self) -> &'static str {
+        match self {
+            Self::StringStartsWith { .. } => "string_starts_with",
+            Self::StringEquals { .. } => "string_equals",
+            Self::StringContains { .. } => "string_contains",
+            Self::StringMatches { .. } => "string_matches",
+            Self::Number { .. } => "number",
+            Self::BinaryContains { .. } => "binary_contains",
+            Self::KeyNameContains { .. } => "key_name_contains",
+            Self::ValueContains { .. } => "value_contains",
+            Self::KeyExists { .. } => "key_exists",
+            Self::KeyMissing { .. } => "key_missing",
+            Self::RecurseKeys { .. } => "recurse_keys",
+            Self::RecursePattern { .. } => "recurse_pattern",
+        }
+    }
+
+    /// The `penalty` and `bonus` fields, `None` for the recursive checks
+    const fn levels(&self) -> Option<(Option<Level>, Option<Level>)> {
+        match self {
+            Self::StringStartsWith { penalty, bonus, .. }
+            | Self::StringEquals { penalty, bonus, .. }
+            | Self::StringContains { penalty, bonus, .. }
+            | Self::StringMatches { penalty, bonus, .. }
+            | Self::Number { penalty, bonus, .. }
+            | Self::BinaryContains { penalty, bonus, .. }
+            | Self::KeyNameContains { penalty, bonus, .. }
+            | Self::ValueContains { penalty, bonus, .. }
+            | Self::KeyExists { penalty, bonus, .. }
+            | Self::KeyMissing { penalty, bonus, .. } => Some((*penalty, *bonus)),
+            Self::RecurseKeys { .. } | Self::RecursePattern { .. } => None,
+        }
+    }
+
+    /// What a match awards
+    #[must_use]
+    pub fn award(&self) -> Option<(FindingKind, Level)> {
+        let (penalty, bonus) = self.levels()?;
+        bonus
+            .map(|level| (FindingKind::Bonus, level))
+            .or_else(|| penalty.map(|level| (FindingKind::Penalty, level)))
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if let Some((penalty, bonus)) = self.levels() {
+            ensure!(
+                penalty.is_some() != bonus.is_some(),
+                "set either penalty or bonus"
+            );
+        }
+
+        match self {
+            Self::StringStartsWith { key, values, .. }
+            | Self::StringEquals { key, values, .. }
+            | Self::StringContains { key, values, .. } => {
+                ensure!(!key.is_empty(), "key is empty");
+                validate_values(values)
+            }
+            Self::StringMatches { key, pattern, .. } => {
+                ensure!(!key.is_empty(), "key is empty");
+                ensure!(
+                    pattern.find("").is_none(),
+                    "pattern matches the empty string, the check would match everything"
+                );
+                Ok(())
+            }
+            Self::Number { key, .. } => {
+                ensure!(!key.is_empty(), "key is empty");
+                Ok(())
+            }
+            Self::BinaryContains { key, values, .. } => {
+                ensure!(!key.is_empty(), "key is empty");
+                ensure!(
+                    !values.is_empty(),
+                    "values is empty, the check would never match"
+                );
+                ensure!(
+                    values.iter().all(|v| v.0.iter().any(Option::is_some)),
+                    "values has a pattern without any fixed byte, the check would match everything"
+                );
+                Ok(())
+            }
+            Self::KeyNameContains { values, .. } | Self::ValueContains { values, .. } => {
+                validate_values(values)
+            }
+            Self::KeyExists { path, value, .. } | Self::KeyMissing { path, value, .. } => {
+                if path.is_empty() && value.is_none() {
+                    bail!("path and value are empty, the key being checked always exists");
+                }
+                ensure!(
+                    value.as_ref().is_none_or(|v| !v.is_empty()),
+                    "value is empty, leave it out to check the key"
+                );
+                Ok(())
+            }
+            Self::RecurseKeys { checks } => validate_checks(checks),
+            Self::RecursePattern { pattern, checks } => {
+                ensure!(!pattern.is_empty(), "pattern is empty");
+                validate_checks(checks)
+            }
+        }
+    }
+}
+
+/// Picks the `penalty` or `bonus` field out of `Large` or `bonus Large`
+#[doc(hidden)]
+#[macro_export]
+macro_rules! award {
+    (penalty, bonus $level:ident) => {
+        None
+    };
+    (penalty, $level:ident) => {
+        Some(Level::$level)
+    };
+    (bonus, bonus $level:ident) => {
+        Some(Level::$level)
+    };
+    (bonus, $level:ident) => {
+        None
+    };
+}
+
 #[macro_export]
 macro_rules! starts_with {
-    ($key:literal, $($value:literal)|+ => $level:ident) => {
+    ($key:literal, $($value:literal)|+ => $($award:ident)+) => {
         $crate::registry_macros::Check::StringStartsWith {
-            key: $key,
-            values: vec![$($value),+],
-            penalty: Level::$level,
+            key: ::std::borrow::Cow::Borrowed($key),
+            values: vec![$(::std::borrow::Cow::Borrowed($value)),+],
+            penalty: $crate::award!(penalty, $($award)+),
+            bonus: $crate::award!(bonus, $($award)+),
         }
     };
 }
 
 #[macro_export]
 macro_rules! eq {
-    ($key:literal, $($value:literal)|+ => $level:ident) => {
+    ($key:literal, $($value:literal)|+ => $($award:ident)+) => {
         $crate::registry_macros::Check::StringEquals {
-            key: $key,
-            values: vec![$($value),+],
-            penalty: Level::$level
+            key: ::std::borrow::Cow::Borrowed($key),
+            values: vec![$(::std::borrow::Cow::Borrowed($value)),+],
+            penalty: $crate::award!(penalty, $($award)+),
+            bonus: $crate::award!(bonus, $($award)+),
         }
     };
 }
 
 #[macro_export]
 macro_rules! contains {
-    ($key:literal => $level:ident) => {
+    ($key:literal, $($value:literal)|+ => $($award:ident)+) => {
         $crate::registry_macros::Check::StringContains {
-            key: $key,
-            values: Vec::new(),
-            penalty: Level::$level
+            key: ::std::borrow::Cow::Borrowed($key),
+            values: vec![$(::std::borrow::Cow::Borrowed($value)),+],
+            penalty: $crate::award!(penalty, $($award)+),
+            bonus: $crate::award!(bonus, $($award)+),
         }
     };
-    ($key:literal, $($value:literal)|+ => $level:ident) => {
-        $crate::registry_macros::Check::StringContains {
-            key: $key,
-            values: vec![$($value),+],
-            penalty: Level::$level
+}
+
+#[macro_export]
+macro_rules! regex {
+    ($key:literal, $pattern:literal => $($award:ident)+) => {
+        $crate::registry_macros::Check::StringMatches {
+            key: ::std::borrow::Cow::Borrowed($key),
+            pattern: $crate::registry_macros::Pattern::new($pattern).expect("built-in regex"),
+            penalty: $crate::award!(penalty, $($award)+),
+            bonus: $crate::award!(bonus, $($award)+),
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! number {
+    ($key:literal $op:ident $value:literal => $($award:ident)+) => {
+        $crate::registry_macros::Check::Number {
+            key: ::std::borrow::Cow::Borrowed($key),
+            op: $crate::registry_macros::Comparison::$op,
+            value: $value,
+            penalty: $crate::award!(penalty, $($award)+),
+            bonus: $crate::award!(bonus, $($award)+),
+        }
+    };
+}
+
+/// Searches for the exact bytes of the given strings
+#[macro_export]
+macro_rules! binary_contains {
+    ($key:literal, $($value:literal)|+ => $($award:ident)+) => {
+        $crate::registry_macros::Check::BinaryContains {
+            key: ::std::borrow::Cow::Borrowed($key),
+            values: vec![$($crate::registry_macros::BytePattern::text($value)),+],
+            penalty: $crate::award!(penalty, $($award)+),
+            bonus: $crate::award!(bonus, $($award)+),
         }
     };
 }
 
 #[macro_export]
 macro_rules! key_contains {
-    ($($value:literal)|+ => $level:ident) => {
+    ($($value:literal)|+ => $($award:ident)+) => {
         $crate::registry_macros::Check::KeyNameContains {
-            values: vec![$($value),+],
-            penalty: Level::$level
+            values: vec![$(::std::borrow::Cow::Borrowed($value)),+],
+            penalty: $crate::award!(penalty, $($award)+),
+            bonus: $crate::award!(bonus, $($award)+),
         }
     };
 }
 
 #[macro_export]
 macro_rules! any_value_contains {
-    ($($value:literal)|+ => $level:ident) => {
+    ($($value:literal)|+ => $($award:ident)+) => {
         $crate::registry_macros::Check::ValueContains {
-            values: vec![$($value),+],
-            penalty: Level::$level
+            values: vec![$(::std::borrow::Cow::Borrowed($value)),+],
+            penalty: $crate::award!(penalty, $($award)+),
+            bonus: $crate::award!(bonus, $($award)+),
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! key_exists {
+    ($path:literal => $($award:ident)+) => {
+        $crate::registry_macros::Check::KeyExists {
+            path: ::std::borrow::Cow::Borrowed($path),
+            value: None,
+            penalty: $crate::award!(penalty, $($award)+),
+            bonus: $crate::award!(bonus, $($award)+),
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! value_exists {
+    ($value:literal => $($award:ident)+) => {
+        $crate::registry_macros::Check::KeyExists {
+            path: ::std::borrow::Cow::Borrowed(""),
+            value: Some(::std::borrow::Cow::Borrowed($value)),
+            penalty: $crate::award!(penalty, $($award)+),
+            bonus: $crate::award!(bonus, $($award)+),
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! key_missing {
+    ($path:literal => $($award:ident)+) => {
+        $crate::registry_macros::Check::KeyMissing {
+            path: ::std::borrow::Cow::Borrowed($path),
+            value: None,
+            penalty: $crate::award!(penalty, $($award)+),
+            bonus: $crate::award!(bonus, $($award)+),
         }
     };
 }
@@ -112,7 +717,7 @@ macro_rules! recurse {
 macro_rules! recurse_into {
     ($pattern:literal => { $($check:expr),* $(,)? }) => {
         $crate::registry_macros::Check::RecursePattern {
-            pattern: $pattern,
+            pattern: ::std::borrow::Cow::Borrowed($pattern),
             checks: vec![$($check),*]
         }
     };
@@ -120,101 +725,456 @@ macro_rules! recurse_into {
 
 #[macro_export]
 macro_rules! rule {
-    ($path:literal => { $($check:expr),* $(,)? }) => {
+    ($id:literal, $path:literal => { $($check:expr),* $(,)? }) => {
         $crate::registry_macros::RegistryRule {
-            path: $path,
+            id: ::std::borrow::Cow::Borrowed($id),
+            path: ::std::borrow::Cow::Borrowed($path),
+            max_matches: $crate::registry_macros::DEFAULT_MAX_MATCHES,
+            max_bonuses: $crate::registry_macros::DEFAULT_MAX_BONUSES,
             checks: vec![$($check),*],
         }
     };
 }
 
-pub fn execute_checks(flags: &mut Flags, key: &windows_registry::Key, checks: &[Check]) {
+/// The string of a `REG_SZ` or `REG_EXPAND_SZ` value, or the elements of a `REG_MULTI_SZ` one
+fn value_strings(value: RegValue) -> Vec<String> {
+    match value {
+        RegValue::String(s) | RegValue::ExpandString(s) => vec![s],
+        RegValue::MultiString(strings) => strings,
+        _ => Vec::new(),
+    }
+}
+
+fn strings(key: &dyn RegistryKey, name: &str) -> Vec<String> {
+    key.value(name).map(value_strings).unwrap_or_default()
+}
+
+fn number(key: &dyn RegistryKey, name: &str) -> Option<u64> {
+    match key.value(name).ok()? {
+        RegValue::Dword(dword) => Some(dword.into()),
+        RegValue::Qword(qword) => Some(qword),
+        _ => None,
+    }
+}
+
+fn binary(key: &dyn RegistryKey, name: &str) -> Option<Vec<u8>> {
+    match key.value(name).ok()? {
+        RegValue::Binary(data) | RegValue::Other { data, .. } => Some(data),
+        _ => None,
+    }
+}
+
+fn exists(key: &dyn RegistryKey, path: &str, value: Option<&str>) -> bool {
+    let exists_in = |key: &dyn RegistryKey| value.is_none_or(|name| key.value(name).is_ok());
+    if path.is_empty() {
+        exists_in(key)
+    } else {
+        key.open(path).is_ok_and(|key| exists_in(&*key))
+    }
+}
+
+/// `path\value`, leaving out whichever is empty
+fn location(path: &str, value: Option<&str>) -> String {
+    match value {
+        Some(value) if path.is_empty() => value.to_owned(),
+        Some(value) => format!("{path}\\{value}"),
+        None => path.to_owned(),
+    }
+}
+
+/// What a match means as a penalty and as a bonus
+type Reasons = (&'static str, &'static str);
+
+/// Records the finding `award` says a match is worth
+fn flag(
+    flags: &mut Flags,
+    award: Option<(FindingKind, Level)>,
+    rule_id: String,
+    (penalty, bonus): Reasons,
+) -> Option<&mut Finding> {
+    Some(match award? {
+        (FindingKind::Penalty, level) => flags.penalty(level, rule_id, penalty),
+        (FindingKind::Bonus, level) => flags.bonus(level, rule_id, bonus),
+    })
+}
+
+/// Flags the first string of value `name` that `find` matches. `find` returns the text the vendor is guessed from.
+fn flag_string(
+    flags: &mut Flags,
+    rule_id: &str,
+    key: &dyn RegistryKey,
+    name: &str,
+    award: Option<(FindingKind, Level)>,
+    reasons: Reasons,
+    find: impl Fn(&str) -> Option<String>,
+) {
+    for s in strings(key, name) {
+        if let Some(matched) = find(&s) {
+            if let Some(finding) = flag(flags, award, format!("registry.{rule_id}.{name}"), reasons)
+            {
+                finding.evidence(&s).vendor_from(&matched);
+            }
+            return;
+        }
+    }
+}
+
+/// Runs `checks` on `key`, the recursive ones only as far as `traversal` allows
+pub fn execute_checks(
+    flags: &mut Flags,
+    rule_id: &str,
+    key: &dyn RegistryKey,
+    checks: &[Check],
+    traversal: &mut Traversal,
+) {
     for check in checks {
+        let award = check.award();
         match check {
-            Check::StringStartsWith {
-                key: k,
-                values,
-                penalty,
+            Check::StringStartsWith { key: k, values, .. } => flag_string(
+                flags,
+                rule_id,
+                key,
+                k,
+                award,
+                (
+                    "registry value starts with a VM string",
+                    "registry value starts with a hardware vendor string",
+                ),
+                |s| {
+                    let s = s.to_lowercase();
+                    values
+                        .iter()
+                        .find(|value| s.starts_with(&value.to_lowercase()))
+                        .map(ToString::to_string)
+                },
+            ),
+            Check::StringEquals { key: k, values, .. } => flag_string(
+                flags,
+                rule_id,
+                key,
+                k,
+                award,
+                (
+                    "registry value equals a VM string",
+                    "registry value equals a hardware vendor string",
+                ),
+                |s| {
+                    values
+                        .iter()
+                        .find(|value| s.eq_ignore_ascii_case(value))
+                        .map(ToString::to_string)
+                },
+            ),
+            Check::StringContains { key: k, values, .. } => flag_string(
+                flags,
+                rule_id,
+                key,
+                k,
+                award,
+                (
+                    "registry value contains a VM string",
+                    "registry value contains a hardware vendor string",
+                ),
+                |s| {
+                    let s = s.to_lowercase();
+                    values
+                        .iter()
+                        .find(|value| s.contains(&value.to_lowercase()))
+                        .map(ToString::to_string)
+                },
+            ),
+            Check::StringMatches {
+                key: k, pattern, ..
+            } => flag_string(
+                flags,
+                rule_id,
+                key,
+                k,
+                award,
+                (
+                    "registry value matches a VM pattern",
+                    "registry value matches a hardware vendor pattern",
+                ),
+                |s| pattern.find(s).map(str::to_owned),
+            ),
+            Check::Number {
+                key: k, op, value, ..
             } => {
-                if let Ok(v) = key.get_string(k) {
-                    for value in values {
-                        if v.to_lowercase().starts_with(&value.to_lowercase()) {
-                            flags.penalty(*penalty);
-                            break;
-                        }
-                    }
+                if let Some(number) = number(key, k)
+                    && op.holds(number, *value)
+                    && let Some(finding) = flag(
+                        flags,
+                        award,
+                        format!("registry.{rule_id}.{k}"),
+                        (
+                            "registry number has a VM value",
+                            "registry number has a real hardware value",
+                        ),
+                    )
+                {
+                    finding.evidence(format!("{number} {} {value}", op.symbol()));
                 }
             }
-            Check::StringEquals {
-                key: k,
-                values,
-                penalty,
-            } => {
-                if let Ok(v) = key.get_string(k) {
-                    for value in values {
-                        if v.eq_ignore_ascii_case(value) {
-                            flags.penalty(*penalty);
-                            break;
-                        }
-                    }
+            Check::BinaryContains { key: k, values, .. } => {
+                let Some(data) = binary(key, k) else {
+                    continue;
+                };
+                if let Some((offset, pattern)) = values
+                    .iter()
+                    .find_map(|pattern| Some((pattern.find(&data)?, pattern)))
+                    && let Some(finding) = flag(
+                        flags,
+                        award,
+                        format!("registry.{rule_id}.{k}"),
+                        (
+                            "registry data contains a VM byte pattern",
+                            "registry data contains a hardware vendor byte pattern",
+                        ),
+                    )
+                {
+                    let matched = data.get(offset..offset + pattern.len()).unwrap_or_default();
+                    finding
+                        .evidence(format!("{pattern} at offset {offset:#x}"))
+                        .vendor_from(&String::from_utf8_lossy(matched));
                 }
             }
-            Check::StringContains {
-                key: k,
-                values,
-                penalty,
-            } => {
-                if let Ok(v) = key.get_string(k) {
-                    for value in values {
-                        if v.to_lowercase().contains(&value.to_lowercase()) {
-                            flags.penalty(*penalty);
-                            break;
-                        }
-                    }
-                }
-            }
-            Check::KeyNameContains { values, penalty } => {
+            Check::KeyNameContains { values, .. } => {
                 if let Ok(keys) = key.keys() {
                     for key_name in keys {
                         for value in values {
-                            if key_name.to_lowercase().contains(&value.to_lowercase()) {
-                                flags.penalty(*penalty);
+                            if key_name.to_lowercase().contains(&value.to_lowercase())
+                                && let Some(finding) = flag(
+                                    flags,
+                                    award,
+                                    format!("registry.{rule_id}.key_name"),
+                                    (
+                                        "registry key name contains a VM string",
+                                        "registry key name contains a hardware vendor string",
+                                    ),
+                                )
+                            {
+                                finding.evidence(&key_name).vendor_from(value);
+                                break;
                             }
                         }
                     }
                 }
             }
-            Check::ValueContains { values, penalty } => {
-                if let Ok(registry_values) = key.values() {
-                    for (_, reg_value) in registry_values {
-                        if let Ok(string_value) = TryInto::<String>::try_into(reg_value) {
-                            for value in values {
-                                if string_value.to_lowercase().contains(&value.to_lowercase()) {
-                                    flags.penalty(*penalty);
-                                    break;
-                                }
-                            }
-                        }
+            Check::ValueContains { values, .. } => {
+                for (name, reg_value) in key.values().unwrap_or_default() {
+                    if let Some((string_value, value)) =
+                        value_strings(reg_value).into_iter().find_map(|s| {
+                            let lowercase = s.to_lowercase();
+                            let value = values
+                                .iter()
+                                .find(|value| lowercase.contains(&value.to_lowercase()))?;
+                            Some((s, value))
+                        })
+                        && let Some(finding) = flag(
+                            flags,
+                            award,
+                            format!("registry.{rule_id}.any_value"),
+                            (
+                                "registry value contains a VM string",
+                                "registry value contains a hardware vendor string",
+                            ),
+                        )
+                    {
+                        finding
+                            .evidence(format!("{name}: {string_value}"))
+                            .vendor_from(value);
+                    }
+                }
+            }
+            Check::KeyExists { path, value, .. } => {
+                let value = value.as_deref();
+                if exists(key, path, value) {
+                    let location = location(path, value);
+                    if let Some(finding) = flag(
+                        flags,
+                        award,
+                        format!("registry.{rule_id}.{location}"),
+                        (
+                            "registry key or value left by a VM exists",
+                            "registry key or value left by real hardware exists",
+                        ),
+                    ) {
+                        finding.vendor_from(&location).evidence(location);
+                    }
+                }
+            }
+            Check::KeyMissing { path, value, .. } => {
+                let value = value.as_deref();
+                if !exists(key, path, value) {
+                    let location = location(path, value);
+                    if let Some(finding) = flag(
+                        flags,
+                        award,
+                        format!("registry.{rule_id}.{location}"),
+                        (
+                            "registry key or value every real machine has is missing",
+                            "registry key or value a VM would have is missing",
+                        ),
+                    ) {
+                        finding.evidence(location);
                     }
                 }
             }
             Check::RecurseKeys { checks: sub_checks } => {
-                if let Ok(keys) = key.keys() {
-                    for key_name in keys {
-                        if let Ok(sub_key) = key.open(key_name) {
-                            execute_checks(flags, &sub_key, sub_checks);
-                        }
+                // Opened as they are iterated, so the budget bounds the keys read and not just the ones checked
+                for (_, sub_key) in key.subkeys().into_iter().flatten() {
+                    if traversal.exhausted() {
+                        traversal.overflowed = true;
+                        break;
                     }
+                    traversal.descend(flags, rule_id, || Ok(sub_key), sub_checks);
                 }
             }
             Check::RecursePattern {
                 pattern,
                 checks: sub_checks,
-            } => {
-                if let Ok(sub_key) = key.open(pattern) {
-                    execute_checks(flags, &sub_key, sub_checks);
-                }
-            }
+            } => traversal.descend(flags, rule_id, || key.open(pattern), sub_checks),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::registry_key::MemoryKey;
+
+    /// Counts the keys opened under it
+    struct Counting<'a> {
+        key: &'a MemoryKey,
+        opened: &'a Cell<usize>,
+    }
+
+    impl RegistryKey for Counting<'_> {
+        fn open(&self, path: &str) -> anyhow::Result<Box<dyn RegistryKey + '_>> {
+            self.opened.set(self.opened.get() + 1);
+            let key = self.key.subkey(path).context("nk")?;
+            Ok(Box::new(Counting {
+                key,
+                opened: self.opened,
+            }))
+        }
+
+        fn keys(&self) -> anyhow::Result<Vec<String>> {
+            self.key.keys()
+        }
+
+        fn values(&self) -> anyhow::Result<Vec<(String, RegValue)>> {
+            self.key.values()
+        }
+
+        fn value(&self, name: &str) -> anyhow::Result<RegValue> {
+            self.key.value(name)
         }
     }
+
+    fn children(count: usize) -> MemoryKey {
+        let mut root = MemoryKey::default();
+        for index in 0..count {
+            root.subkey_mut(&format!("Child{index}"))
+                .set_value("Name", RegValue::String("VMware".to_owned()));
+        }
+        root
+    }
+
+    fn recurse(root: &MemoryKey, max_keys: usize) -> (Traversal, usize) {
+        let opened = Cell::new(0);
+        let key = Counting {
+            key: root,
+            opened: &opened,
+        };
+        let mut traversal = Traversal::new(TraversalLimits {
+            max_keys,
+            ..TraversalLimits::default()
+        });
+        execute_checks(
+            &mut Flags::new("registry"),
+            "test",
+            &key,
+            &[recurse!(contains!("Name", "vmware" => Large))],
+            &mut traversal,
+        );
+        (traversal, opened.get())
+    }
+
+    #[test]
+    fn recursion_stops_at_the_budget() {
+        let (traversal, opened) = recurse(&children(5), 3);
+        assert!(traversal.overflowed());
+        assert_eq!(traversal.keys(), 3);
+        // The fourth is opened to find out there are more
+        assert_eq!(opened, 4);
+    }
+
+    #[test]
+    fn recursion_within_the_budget() {
+        let (traversal, opened) = recurse(&children(3), 3);
+        assert!(!traversal.overflowed());
+        assert_eq!(opened, 3);
+    }
+
+    /// Ids of what `checks` find on a key with one value of every type and a `Tools` subkey
+    fn matched(checks: &[Check]) -> Vec<String> {
+        let mut key = MemoryKey::default();
+        key.set_value(
+            "SystemBiosVersion",
+            RegValue::MultiString(vec!["LENOVO - 1400".to_owned(), "VBOX   - 1".to_owned()]),
+        );
+        key.set_value("Start", RegValue::Dword(3));
+        key.set_value("Data", RegValue::Binary(b"\0\x01innotek GmbH\0".to_vec()));
+        key.subkey_mut("Tools");
+
+        let mut flags = Flags::new("registry");
+        execute_checks(
+            &mut flags,
+            "test",
+            &key,
+            checks,
+            &mut Traversal::new(TraversalLimits::default()),
+        );
+        flags
+            .findings()
+            .iter()
+            .map(|f| f.rule_id.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn matchers() {
+        assert_eq!(
+            matched(&[
+                regex!("SystemBiosVersion", r"^(vbox|qemu)\s+-" => Large),
+                number!("Start" Le 3 => Large),
+                binary_contains!("Data", "innotek GmbH" => Large),
+                key_exists!("Tools" => Large),
+                key_missing!("Additions" => Large),
+                any_value_contains!("vbox" => Large),
+            ]),
+            [
+                "registry.test.SystemBiosVersion",
+                "registry.test.Start",
+                "registry.test.Data",
+                "registry.test.Tools",
+                "registry.test.Additions",
+                "registry.test.any_value",
+            ]
+        );
+        assert!(
+            matched(&[
+                regex!("SystemBiosVersion", r"^qemu" => Large),
+                number!("Start" Gt 3 => Large),
+                binary_contains!("Data", "VMware" => Large),
+                key_exists!("Additions" => Large),
+                key_missing!("Tools" => Large),
+                any_value_contains!("qemu" => Large),
+            ])
+            .is_empty()
+        );
+    }
 }
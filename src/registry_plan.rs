@@ -0,0 +1,177 @@
+use crate::{
+    registry_key::{MemoryKey, RegistryKey},
+    registry_macros::Check,
+};
+
+/// `HKEY_LOCAL_MACHINE` as far as a plan reads it, shared by the checks that walk the large hardware subtrees
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Default)]
+pub struct Preload {
+    pub local_machine: MemoryKey,
+    /// Most keys the load could open
+    pub max_keys: usize,
+    /// Whether the budget ran out. A key missing from the copy may then still exist in the source.
+    pub truncated: bool,
+}
+
+impl Preload {
+    pub fn load(source: &dyn RegistryKey, plan: &Plan, max_keys: usize) -> Self {
+        let mut local_machine = MemoryKey::default();
+        let mut budget = max_keys;
+        let complete = plan.load(source, &mut local_machine, &mut budget);
+
+        Self {
+            local_machine,
+            max_keys,
+            truncated: !complete,
+        }
+    }
+}
+
+/// What is read under a key, so every key is opened and read once however many rules and checks look at it
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Default)]
+pub struct Plan {
+    /// Read the values of the key
+    values: bool,
+    /// Read the names of the subkeys
+    names: bool,
+    /// Read when the key was last written
+    times: bool,
+    /// What is read under every subkey
+    every: Option<Box<Self>>,
+    /// What is read under specific subkeys, by name
+    named: Vec<(String, Self)>,
+}
+
+impl Plan {
+    /// The plan of the key at `path`, an empty path is this key
+    pub fn at(&mut self, path: &str) -> &mut Self {
+        path.split('\\')
+            .filter(|c| !c.is_empty())
+            .fold(self, Self::child)
+    }
+
+    pub const fn read_values(&mut self) -> &mut Self {
+        self.values = true;
+        self
+    }
+
+    pub const fn read_times(&mut self) -> &mut Self {
+        self.times = true;
+        self
+    }
+
+    /// The plan of every subkey
+    pub fn every(&mut self) -> &mut Self {
+        self.every.get_or_insert_default()
+    }
+
+    fn child(&mut self, name: &str) -> &mut Self {
+        if self.named(name).is_none() {
+            self.named.push((name.to_owned(), Self::default()));
+        }
+
+        self.named
+            .iter_mut()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, plan)| plan)
+            .expect("just added")
+    }
+
+    /// Adds whatever `checks` read when run on this key
+    pub fn add_checks(&mut self, checks: &[Check]) {
+        for check in checks {
+            match check {
+                Check::StringStartsWith { .. }
+                | Check::StringEquals { .. }
+                | Check::StringContains { .. }
+                | Check::StringMatches { .. }
+                | Check::Number { .. }
+                | Check::BinaryContains { .. }
+                | Check::ValueContains { .. } => self.values = true,
+                Check::KeyNameContains { .. } => self.names = true,
+                Check::KeyExists { path, value, .. } | Check::KeyMissing { path, value, .. } => {
+                    let plan = self.at(path);
+                    plan.values |= value.is_some();
+                }
+                Check::RecurseKeys { checks } => self.every().add_checks(checks),
+                Check::RecursePattern { pattern, checks } => self.at(pattern).add_checks(checks),
+            }
+        }
+    }
+
+    fn merge(&mut self, other: &Self) {
+        self.values |= other.values;
+        self.names |= other.names;
+        self.times |= other.times;
+        if let Some(every) = &other.every {
+            self.every.get_or_insert_default().merge(every);
+        }
+        for (name, plan) in &other.named {
+            self.at(name).merge(plan);
+        }
+    }
+
+    fn named(&self, name: &str) -> Option<&Self> {
+        self.named
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, plan)| plan)
+    }
+
+    /// Copies what the plan reads from `key` into `out`, opening at most `budget` keys. Returns false if the
+    /// budget ran out.
+    pub fn load(&self, key: &dyn RegistryKey, out: &mut MemoryKey, budget: &mut usize) -> bool {
+        if self.values {
+            for (name, value) in key.values().unwrap_or_default() {
+                out.set_value(&name, value);
+            }
+        }
+        if self.times {
+            out.last_written = key.last_written().ok();
+        }
+
+        let mut complete = true;
+        if let Some(every) = &self.every {
+            // Opened as they are iterated, so no more than one key past the budget is opened
+            for (name, sub_key) in key.subkeys().into_iter().flatten() {
+                if *budget == 0 {
+                    return false;
+                }
+                *budget -= 1;
+
+                let out = out.subkey_mut(&name);
+                complete &= match self.named(&name) {
+                    Some(named) => {
+                        let mut plan = (**every).clone();
+                        plan.merge(named);
+                        plan.load(&*sub_key, out, budget)
+                    }
+                    None => every.load(&*sub_key, out, budget),
+                };
+            }
+        } else if self.names {
+            for name in key.keys().unwrap_or_default() {
+                out.subkey_mut(&name);
+            }
+        }
+
+        for (name, plan) in &self.named {
+            // Already loaded along with every other subkey
+            if self.every.is_some() && out.subkey(name).is_some() {
+                continue;
+            }
+            if *budget == 0 {
+                return false;
+            }
+            *budget -= 1;
+
+            if let Ok(sub_key) = key.open(name) {
+                complete &= plan.load(&*sub_key, out.subkey_mut(name), budget);
+            }
+        }
+
+        complete
+    }
+}
@@ -0,0 +1,623 @@
+use std::{fmt, fs, path::Path};
+
+use anyhow::Context;
+
+use crate::{
+    flags::{Finding, FindingKind, Flags, Level},
+    reg_export::RegExport,
+    registry::{
+        CURRENT_CONTROL_SET, ControlSets, RegistryRules, RulesFile, builtin_rules,
+        preload_registry, score_registry,
+    },
+    registry_macros::{Check, RegistryRule, TraversalLimits},
+};
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Note,
+    Warning,
+    /// Fails `rules lint`
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Note => "note",
+            Self::Warning => "warning",
+            Self::Error => "error",
+        })
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone)]
+pub struct Problem {
+    pub severity: Severity,
+    /// Id of the rule at fault, if it is about one
+    pub rule: Option<String>,
+    pub message: String,
+}
+
+impl Problem {
+    fn new(severity: Severity, rule: Option<&str>, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            rule: rule.map(str::to_owned),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.rule {
+            Some(rule) => write!(f, "{}: {rule}: {}", self.severity, self.message),
+            None => write!(f, "{}: {}", self.severity, self.message),
+        }
+    }
+}
+
+/// A check that awards something, with what it looks at written out so checks can be compared
+struct Leaf<'a> {
+    rule: &'a str,
+    /// e.x. `check 2.1 (string_equals)`
+    label: String,
+    /// The rule path and the steps the recursive checks above it take, `*` for every subkey
+    location: String,
+    kind: &'static str,
+    /// Name of the value, or what the finding id is built from for checks that don't read one
+    key: String,
+    /// Lowercase, a check matches if any of them does
+    values: Vec<String>,
+    award: Option<(FindingKind, Level)>,
+}
+
+fn lowercase<T: fmt::Display>(values: &[T]) -> Vec<String> {
+    values
+        .iter()
+        .map(|v| v.to_string().to_lowercase())
+        .collect()
+}
+
+/// The value name and values of a check that awards something
+fn compared(check: &Check) -> Option<(String, Vec<String>)> {
+    Some(match check {
+        Check::StringStartsWith { key, values, .. }
+        | Check::StringEquals { key, values, .. }
+        | Check::StringContains { key, values, .. } => (key.to_string(), lowercase(values)),
+        Check::StringMatches { key, pattern, .. } => {
+            (key.to_string(), vec![pattern.as_str().to_owned()])
+        }
+        Check::Number { key, op, value, .. } => {
+            (key.to_string(), vec![format!("{} {value}", op.symbol())])
+        }
+        Check::BinaryContains { key, values, .. } => (key.to_string(), lowercase(values)),
+        Check::KeyNameContains { values, .. } => ("key_name".to_owned(), lowercase(values)),
+        Check::ValueContains { values, .. } => ("any_value".to_owned(), lowercase(values)),
+        Check::KeyExists { path, value, .. } | Check::KeyMissing { path, value, .. } => {
+            let location = match value {
+                Some(value) if path.is_empty() => value.to_string(),
+                Some(value) => format!("{path}\\{value}"),
+                None => path.to_string(),
+            };
+            (location, vec![String::new()])
+        }
+        Check::RecurseKeys { .. } | Check::RecursePattern { .. } => return None,
+    })
+}
+
+/// Walks the checks of a rule, collecting its leaves and reporting recursive checks that can't be reached
+struct Walk<'a> {
+    rule: &'a RegistryRule,
+    max_depth: usize,
+    leaves: Vec<Leaf<'a>>,
+    problems: Vec<Problem>,
+}
+
+impl<'a> Walk<'a> {
+    fn checks(&mut self, checks: &'a [Check], label: &str, location: &str, depth: usize) {
+        for (index, check) in checks.iter().enumerate() {
+            let label = if label.is_empty() {
+                format!("{}", index + 1)
+            } else {
+                format!("{label}.{}", index + 1)
+            };
+            let described = format!("check {label} ({})", check.kind());
+
+            let step = match check {
+                Check::RecurseKeys { .. } => Some("*"),
+                Check::RecursePattern { pattern, .. } => {
+                    if pattern.contains(CURRENT_CONTROL_SET) {
+                        self.problem(
+                            Severity::Error,
+                            format!(
+                                "{described}: {CURRENT_CONTROL_SET} is only resolved in rule paths, the pattern is \
+                                 unreachable"
+                            ),
+                        );
+                    }
+                    if pattern.to_uppercase().starts_with("HKEY_")
+                        || pattern.to_uppercase().starts_with("HKLM")
+                    {
+                        self.problem(
+                            Severity::Error,
+                            format!(
+                                "{described}: the pattern is relative to the key it runs on, a root key makes it \
+                                 unreachable"
+                            ),
+                        );
+                    }
+                    Some(pattern.as_ref())
+                }
+                _ => None,
+            };
+
+            match (check, step) {
+                (
+                    Check::RecurseKeys { checks } | Check::RecursePattern { checks, .. },
+                    Some(step),
+                ) => {
+                    if depth >= self.max_depth {
+                        self.problem(
+                            Severity::Warning,
+                            format!(
+                                "{described}: nested {} levels deep but max_depth is {}, its checks never run",
+                                depth + 1,
+                                self.max_depth
+                            ),
+                        );
+                    }
+                    self.checks(checks, &label, &format!("{location}\\{step}"), depth + 1);
+                }
+                _ => {
+                    if let Some((key, values)) = compared(check) {
+                        self.leaves.push(Leaf {
+                            rule: &self.rule.id,
+                            label: described,
+                            location: location.to_lowercase(),
+                            kind: check.kind(),
+                            key,
+                            values,
+                            award: check.award(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    fn problem(&mut self, severity: Severity, message: String) {
+        self.problems
+            .push(Problem::new(severity, Some(&self.rule.id), message));
+    }
+}
+
+/// Ids that only say where a rule sits, e.x. `rule3`, change meaning when rules are moved around
+fn positional_id(id: &str) -> bool {
+    let stem = id.trim_end_matches(|c: char| c.is_ascii_digit() || c == '_');
+    stem.is_empty() || stem.eq_ignore_ascii_case("rule")
+}
+
+/// Problems of a single rule that don't depend on the others
+fn lint_rule(rule: &RegistryRule) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut problem =
+        |severity, message: String| problems.push(Problem::new(severity, Some(&rule.id), message));
+
+    if let Err(error) = rule.validate() {
+        problem(Severity::Error, format!("{error:#}"));
+    }
+    if positional_id(&rule.id) {
+        problem(
+            Severity::Warning,
+            "id only numbers the rule, name it after what it detects so its findings and profile weights keep \
+             their meaning"
+                .to_owned(),
+        );
+    }
+    if rule.id.chars().any(|c| c.is_ascii_uppercase()) {
+        problem(
+            Severity::Warning,
+            "id has uppercase letters, finding ids and profile weights are case sensitive"
+                .to_owned(),
+        );
+    }
+    let control_set = format!("SYSTEM\\{CURRENT_CONTROL_SET}");
+    if rule.path.contains(CURRENT_CONTROL_SET)
+        && !rule
+            .path
+            .get(..control_set.len())
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(&control_set))
+    {
+        problem(
+            Severity::Warning,
+            format!(
+                "{CURRENT_CONTROL_SET} only names a key right under SYSTEM, the path is unreachable"
+            ),
+        );
+    }
+
+    problems
+}
+
+/// Repeats across the built-in rules that are deliberate, only reported as notes while both rules are built-in
+const KNOWN_REPEATS: &[(&str, &str, &str)] = &[
+    (
+        "video",
+        "device_class",
+        "Control\\Video mirrors the Class key of the display adapter, either can be cleaned up alone",
+    ),
+    (
+        "enum_scsi",
+        "device_containers",
+        "the container outlives the device when the disk is removed",
+    ),
+    (
+        "enum_scsi",
+        "enum_pci",
+        "only the value name is shared, the devices are on different buses",
+    ),
+    (
+        "hardware_config",
+        "bios",
+        "HardwareConfig keeps the firmware of earlier boots, not only the current one",
+    ),
+];
+
+/// Checks of the same kind on the same value, repeated in a rule or across rules. `builtin` are the ids of the
+/// built-in rules in use.
+fn lint_repeats(leaves: &[Leaf<'_>], builtin: &[&str]) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    for (index, leaf) in leaves.iter().enumerate() {
+        let earlier = leaves.iter().take(index).find(|other| {
+            other.kind == leaf.kind
+                && other.key.eq_ignore_ascii_case(&leaf.key)
+                && leaf.values.iter().any(|v| other.values.contains(v))
+        });
+        let Some(earlier) = earlier else {
+            continue;
+        };
+
+        let same_place = earlier.rule == leaf.rule && earlier.location == leaf.location;
+        let message = if same_place
+            && earlier.award == leaf.award
+            && leaf.values.iter().all(|v| earlier.values.contains(v))
+        {
+            format!(
+                "{} is shadowed by {}, which already matches every value it does",
+                leaf.label, earlier.label
+            )
+        } else if earlier.rule == leaf.rule {
+            format!(
+                "{} repeats {} on {}, the same evidence is counted once",
+                leaf.label, earlier.label, leaf.key
+            )
+        } else {
+            format!(
+                "{} repeats {} of rule {} on {}, the same evidence is counted twice",
+                leaf.label, earlier.label, earlier.rule, leaf.key
+            )
+        };
+        let known = KNOWN_REPEATS
+            .iter()
+            .find(|(rule, other, _)| *rule == leaf.rule && *other == earlier.rule)
+            .filter(|_| builtin.contains(&leaf.rule) && builtin.contains(&earlier.rule));
+        match known {
+            Some((.., reason)) => problems.push(Problem::new(
+                Severity::Note,
+                Some(leaf.rule),
+                format!("{message}, known: {reason}"),
+            )),
+            None => problems.push(Problem::new(Severity::Warning, Some(leaf.rule), message)),
+        }
+    }
+
+    problems
+}
+
+/// Lints the rules file at `path`, or the built-in rules.
+///
+/// Everything wrong is reported rather than the first error, the returned set leaves out the rules with errors so
+/// the rest can still be run against fixtures.
+pub fn lint_rules(path: Option<&Path>) -> anyhow::Result<(RegistryRules, Vec<Problem>)> {
+    let mut problems = Vec::new();
+    let file = match path {
+        Some(path) => {
+            RulesFile::read(path).with_context(|| format!("bad rules file {}", path.display()))?
+        }
+        None => RulesFile {
+            include_builtin: true,
+            control_sets: ControlSets::default(),
+            traversal: TraversalLimits::default(),
+            rules: Vec::new(),
+        },
+    };
+    if !file.include_builtin && file.rules.is_empty() {
+        problems.push(Problem::new(
+            Severity::Error,
+            None,
+            "no rules, add some or set include_builtin",
+        ));
+    }
+
+    // The built-in rules that are kept and every rule of the file, duplicates included, so each is linted
+    let kept = if file.include_builtin {
+        builtin_rules()
+    } else {
+        Vec::new()
+    };
+    let replaced = |rule: &RegistryRule| file.rules.iter().any(|r| r.id == rule.id);
+    let mut invalid = Vec::new();
+    for (index, rule) in file.rules.iter().enumerate() {
+        if let Some(first) = file.rules.iter().take(index).position(|r| r.id == rule.id) {
+            invalid.push(rule.id.clone());
+            problems.push(Problem::new(
+                Severity::Error,
+                Some(&rule.id),
+                format!("rule {} uses the id of rule {}", index + 1, first + 1),
+            ));
+        } else if kept.iter().any(|r| r.id == rule.id) {
+            problems.push(Problem::new(
+                Severity::Note,
+                Some(&rule.id),
+                "replaces the built-in rule",
+            ));
+        }
+    }
+
+    let mut leaves = Vec::new();
+    for rule in kept.iter().filter(|r| !replaced(r)).chain(&file.rules) {
+        let mut walk = Walk {
+            rule,
+            max_depth: file.traversal.max_depth,
+            leaves: Vec::new(),
+            problems: lint_rule(rule),
+        };
+        walk.checks(&rule.checks, "", &rule.path, 0);
+
+        if walk.problems.iter().any(|p| p.severity == Severity::Error) {
+            invalid.push(rule.id.clone());
+        }
+        problems.extend(walk.problems);
+        leaves.extend(walk.leaves);
+    }
+    let builtin = kept
+        .iter()
+        .filter(|r| !replaced(r))
+        .map(|r| &*r.id)
+        .collect::<Vec<_>>();
+    problems.extend(lint_repeats(&leaves, &builtin));
+
+    let mut rules = file.into_rules();
+    rules.rules.retain(|rule| !invalid.contains(&rule.id));
+    Ok((rules, problems))
+}
+
+/// Id of the rule a registry finding came from, `registry.<id>.<value name>`. Running out of traversal budget
+/// isn't a match, so those findings are left out.
+fn rule_of(finding: &Finding) -> Option<&str> {
+    if finding.rule_id.ends_with(".traversal_budget") {
+        return None;
+    }
+    finding.rule_id.strip_prefix("registry.")?.split('.').next()
+}
+
+/// The rule ids after `; <name>:` in the header of a fixture
+fn expected<'a>(text: &'a str, name: &str) -> Vec<&'a str> {
+    text.lines()
+        .filter_map(|line| {
+            line.trim()
+                .strip_prefix(';')?
+                .trim()
+                .strip_prefix(name)?
+                .strip_prefix(':')
+        })
+        .flat_map(str::split_whitespace)
+        .collect()
+}
+
+/// Runs `rules` against every `.reg` fixture in `dir`.
+///
+/// A fixture lists the rules that must report something in `; positive:` lines and the ones that must not award a
+/// penalty in `; negative:` lines, `*` for every rule.
+pub fn run_fixtures(rules: &RegistryRules, dir: &Path) -> anyhow::Result<Vec<Problem>> {
+    let mut paths = fs::read_dir(dir)
+        .with_context(|| format!("failed to read {}", dir.display()))?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    paths.retain(|path| {
+        path.extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("reg"))
+    });
+    paths.sort();
+
+    let mut problems = Vec::new();
+    let mut covered = Vec::new();
+    for path in &paths {
+        let name = path.display().to_string();
+        let text = fs::read_to_string(path).with_context(|| format!("failed to read {name}"))?;
+        let mut export = RegExport::default();
+        export.import(&name, &text);
+        for warning in &export.warnings {
+            problems.push(Problem::new(
+                Severity::Warning,
+                None,
+                format!("skipped {warning}"),
+            ));
+        }
+
+        let positive = expected(&text, "positive");
+        let negative = expected(&text, "negative");
+        if positive.is_empty() && negative.is_empty() {
+            problems.push(Problem::new(
+                Severity::Warning,
+                None,
+                format!(
+                    "{name}: no `; positive:` or `; negative:` line, the fixture tests nothing"
+                ),
+            ));
+        }
+
+        let mut flags = Flags::quiet("registry");
+        let preload = preload_registry(&export.snapshot().registry, rules);
+        score_registry(&preload, rules, &mut flags);
+        let fired_by = |id: &str| {
+            flags
+                .findings()
+                .iter()
+                .filter(|finding| rule_of(finding) == Some(id))
+                .collect::<Vec<_>>()
+        };
+
+        for id in positive {
+            if !rules.rules.iter().any(|rule| rule.id == id) {
+                problems.push(Problem::new(
+                    Severity::Note,
+                    Some(id),
+                    format!("{name}: not in the rule set, skipped"),
+                ));
+            } else if fired_by(id).is_empty() {
+                problems.push(Problem::new(
+                    Severity::Error,
+                    Some(id),
+                    format!("{name}: positive fixture, but the rule reported nothing"),
+                ));
+            } else {
+                covered.push(id.to_owned());
+            }
+        }
+
+        let every_rule = negative.contains(&"*");
+        for rule in &rules.rules {
+            if !every_rule && !negative.contains(&rule.id.as_ref()) {
+                continue;
+            }
+            for finding in fired_by(&rule.id)
+                .into_iter()
+                .filter(|f| f.kind == FindingKind::Penalty)
+            {
+                problems.push(Problem::new(
+                    Severity::Error,
+                    Some(&rule.id),
+                    format!(
+                        "{name}: negative fixture, but {} fired ({})",
+                        finding.rule_id,
+                        finding.evidence.as_deref().unwrap_or("no evidence")
+                    ),
+                ));
+            }
+        }
+    }
+
+    for rule in &rules.rules {
+        if !covered.iter().any(|id| *id == rule.id) {
+            problems.push(Problem::new(
+                Severity::Note,
+                Some(&rule.id),
+                "no positive fixture covers the rule",
+            ));
+        }
+    }
+
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_rules_are_clean() {
+        let (rules, problems) = lint_rules(None).unwrap();
+        let warnings = problems
+            .iter()
+            .filter(|p| p.severity > Severity::Note)
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert!(warnings.is_empty(), "{warnings:#?}");
+        assert_eq!(rules.rules.len(), builtin_rules().len());
+    }
+
+    #[test]
+    fn known_repeats_only_between_builtin_rules() {
+        let rule = |id: &str| RegistryRule {
+            id: id.to_owned().into(),
+            ..builtin_rules()
+                .into_iter()
+                .find(|r| r.id == "bios")
+                .unwrap()
+        };
+        let walk = |rule| {
+            let mut walk = Walk {
+                rule,
+                max_depth: 8,
+                leaves: Vec::new(),
+                problems: Vec::new(),
+            };
+            walk.checks(&rule.checks, "", &rule.path, 0);
+            walk.leaves
+        };
+        let (bios, hardware_config) = (rule("bios"), rule("hardware_config"));
+        let leaves = walk(&bios)
+            .into_iter()
+            .chain(walk(&hardware_config))
+            .collect::<Vec<_>>();
+
+        let severities = |builtin| {
+            lint_repeats(&leaves, builtin)
+                .iter()
+                .map(|p| p.severity)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            severities(&["bios", "hardware_config"]),
+            [Severity::Note; 2]
+        );
+        assert_eq!(severities(&["bios"]), [Severity::Warning; 2]);
+    }
+
+    #[test]
+    fn control_set_in_pattern_is_rejected() {
+        let dir = std::env::temp_dir().join(format!("vm-detect-lint-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("rules.toml");
+        fs::write(
+            &path,
+            r#"
+            [[rules]]
+            id = "pattern"
+            path = "SYSTEM"
+            checks = [
+                { type = "recurse_pattern", pattern = "{CurrentControlSet}\\Services", checks = [
+                    { type = "key_exists", path = "vmci", penalty = "large" },
+                ] },
+            ]
+
+            [[rules]]
+            id = "path"
+            path = "SYSTEM\\{CurrentControlSet}\\Services"
+            checks = [{ type = "key_exists", path = "vmci", penalty = "large" }]
+            "#,
+        )
+        .unwrap();
+
+        let (rules, problems) = lint_rules(Some(&path)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let errors = problems
+            .iter()
+            .filter(|p| p.severity == Severity::Error)
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert!(
+            errors
+                .iter()
+                .all(|e| e.contains("pattern") && e.contains("only resolved in rule paths")),
+            "{errors:?}"
+        );
+        let ids = rules.rules.iter().map(|r| &*r.id).collect::<Vec<_>>();
+        assert_eq!(ids, ["path"]);
+    }
+}
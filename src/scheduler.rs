@@ -0,0 +1,504 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError, Sender},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+#[cfg(windows)]
+use windows::Win32::System::Com::{COINIT_MULTITHREADED, CoInitializeEx};
+
+use crate::{
+    checks::{Check, Cost},
+    detect::{CheckReport, run_check},
+    facts::FactSource,
+    flags::{Flags, Level},
+    profile::ScoringProfile,
+};
+
+/// What a check that misses its deadline costs, the `[timeouts]` section of a scoring profile
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeoutPenalty {
+    /// Level of the `<check name>.timed_out` penalty, `[rules]` can reweigh or disable it like any other rule
+    pub level: Level,
+}
+
+impl Default for TimeoutPenalty {
+    fn default() -> Self {
+        // A slow machine is barely a sign of a virtual one
+        Self { level: Level::Tiny }
+    }
+}
+
+/// The deadlines and time budget the scheduler enforces
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy)]
+pub struct Timeouts {
+    /// Deadline of every check, instead of the default for its cost
+    pub check: Option<Duration>,
+    /// Time the whole run may take
+    pub budget: Option<Duration>,
+}
+
+impl Timeouts {
+    fn deadline(&self, check: &dyn Check) -> Duration {
+        self.check.unwrap_or_else(|| check.deadline())
+    }
+}
+
+enum Event {
+    /// Check index, the lane it started on and when
+    Started(usize, usize, Instant),
+    Finished(usize, CheckReport),
+}
+
+struct Slot {
+    check: &'static dyn Check,
+    /// Index of the lane this check is queued on
+    lane: usize,
+    started: Option<Instant>,
+    report: Option<CheckReport>,
+}
+
+struct Scheduler {
+    source: Arc<dyn FactSource>,
+    profile: Arc<ScoringProfile>,
+    timeouts: Timeouts,
+    tx: Sender<Event>,
+    /// Cancellation flag of every lane spawned so far
+    lanes: Vec<Arc<AtomicBool>>,
+    slots: Vec<Slot>,
+}
+
+/// Runs `checks` against `source`, each slow check on its own thread and the fast ones on a shared one.
+///
+/// Returns the reports in the order of `checks` once each one finished or timed out. Threads can't be killed,
+/// so a lane (worker thread) stuck in a check that missed its deadline is cancelled and left to finish on its
+/// own. Its late results are ignored and the checks queued behind it move to a new lane.
+pub fn run_checks(
+    source: Arc<dyn FactSource>,
+    profile: Arc<ScoringProfile>,
+    checks: &[&'static dyn Check],
+    timeouts: Timeouts,
+) -> Vec<CheckReport> {
+    let start = Instant::now();
+    let budget_end = timeouts.budget.map(|budget| start + budget);
+
+    let (tx, rx) = mpsc::channel();
+    let mut scheduler = Scheduler {
+        source,
+        profile,
+        timeouts,
+        tx,
+        lanes: Vec::new(),
+        slots: checks
+            .iter()
+            .map(|&check| Slot {
+                check,
+                lane: 0,
+                started: None,
+                report: None,
+            })
+            .collect(),
+    };
+
+    let mut fast = Vec::new();
+    for (index, check) in checks.iter().enumerate() {
+        if check.cost() >= Cost::Slow {
+            scheduler.spawn_lane(vec![index]);
+        } else {
+            fast.push(index);
+        }
+    }
+    scheduler.spawn_lane(fast);
+
+    while scheduler.slots.iter().any(|slot| slot.report.is_none()) {
+        let wake = scheduler
+            .next_deadline()
+            .into_iter()
+            .chain(budget_end)
+            .min();
+        let timeout = wake.map_or(Duration::MAX, |wake| {
+            wake.saturating_duration_since(Instant::now())
+        });
+
+        match rx.recv_timeout(timeout) {
+            Ok(Event::Started(index, lane, at)) => {
+                // A cancelled lane may have started the check just before it was moved to a new one
+                if let Some(slot) = scheduler.slots.get_mut(index)
+                    && slot.lane == lane
+                {
+                    slot.started.get_or_insert(at);
+                }
+            }
+            Ok(Event::Finished(index, report)) => {
+                if let Some(slot) = scheduler.slots.get_mut(index) {
+                    slot.report.get_or_insert(report);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            // The scheduler holds a sender, so this can't happen
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        let now = Instant::now();
+        if budget_end.is_some_and(|end| now >= end) {
+            scheduler.out_of_budget(now - start);
+            break;
+        }
+        scheduler.expire(now);
+    }
+
+    scheduler
+        .slots
+        .into_iter()
+        .filter_map(|slot| slot.report)
+        .collect()
+}
+
+impl Scheduler {
+    fn next_deadline(&self) -> Option<Instant> {
+        self.slots
+            .iter()
+            .filter(|slot| slot.report.is_none())
+            .filter_map(|slot| Some(slot.started? + self.timeouts.deadline(slot.check)))
+            .min()
+    }
+
+    fn spawn_lane(&mut self, queue: Vec<usize>) {
+        if queue.is_empty() {
+            return;
+        }
+
+        let lane = self.lanes.len();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.lanes.push(Arc::clone(&cancelled));
+
+        let mut work = Vec::with_capacity(queue.len());
+        for index in queue {
+            if let Some(slot) = self.slots.get_mut(index) {
+                slot.lane = lane;
+                work.push((index, slot.check));
+            }
+        }
+
+        let source = Arc::clone(&self.source);
+        let profile = Arc::clone(&self.profile);
+        let tx = self.tx.clone();
+        thread::spawn(move || {
+            #[cfg(windows)]
+            if work.iter().any(|(_, check)| check.needs_com()) {
+                // Joins the process wide MTA set up by `init_com`, so WMI works from this thread
+                let _ = unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) };
+            }
+
+            for (index, check) in work {
+                if cancelled.load(Ordering::Acquire)
+                    || tx.send(Event::Started(index, lane, Instant::now())).is_err()
+                    // Checked again in case the lane was cancelled while the check was being started, the
+                    // scheduler then ignores the start and the check runs on the new lane
+                    || cancelled.load(Ordering::Acquire)
+                {
+                    break;
+                }
+
+                let report = run_check(check, &*source, &profile);
+                if tx.send(Event::Finished(index, report)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Times out the running checks that are past their deadline, moving the checks queued behind them to a
+    /// new lane
+    fn expire(&mut self, now: Instant) {
+        let mut stuck = Vec::new();
+        for slot in &mut self.slots {
+            let deadline = self.timeouts.deadline(slot.check);
+            if let Some(started) = slot.started
+                && slot.report.is_none()
+                && now >= started + deadline
+            {
+                slot.report = Some(timed_out(
+                    slot.check,
+                    self.profile.timeouts.level,
+                    deadline,
+                    now - started,
+                ));
+                stuck.push(slot.lane);
+            }
+        }
+
+        if stuck.is_empty() {
+            return;
+        }
+
+        for (lane, cancelled) in self.lanes.iter().enumerate() {
+            if stuck.contains(&lane) {
+                cancelled.store(true, Ordering::Release);
+            }
+        }
+
+        let requeue = self
+            .slots
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| {
+                stuck.contains(&slot.lane) && slot.started.is_none() && slot.report.is_none()
+            })
+            .map(|(index, _)| index)
+            .collect();
+        self.spawn_lane(requeue);
+    }
+
+    /// Times out every check that hasn't finished yet, including the ones that never started
+    fn out_of_budget(&mut self, elapsed: Duration) {
+        for cancelled in &self.lanes {
+            cancelled.store(true, Ordering::Release);
+        }
+
+        for slot in &mut self.slots {
+            if slot.report.is_none() {
+                let ran_for = slot
+                    .started
+                    .map_or(Duration::ZERO, |started| started.elapsed().min(elapsed));
+                let mut report =
+                    timed_out(slot.check, self.profile.timeouts.level, elapsed, ran_for);
+                if slot.started.is_none() {
+                    report.error = Some("time budget ran out before it started".to_owned());
+                }
+                slot.report = Some(report);
+            }
+        }
+    }
+}
+
+fn timed_out(
+    check: &dyn Check,
+    level: Level,
+    deadline: Duration,
+    elapsed: Duration,
+) -> CheckReport {
+    let name = check.name();
+    let mut flags = Flags::new(name);
+    flags
+        .penalty(
+            level,
+            format!("{name}.timed_out"),
+            "check did not finish in time",
+        )
+        .evidence(format!("{}ms", deadline.as_millis()));
+
+    CheckReport {
+        name,
+        flags,
+        score: 0,
+        elapsed,
+        error: Some(format!("timed out after {}ms", deadline.as_millis())),
+        timed_out: true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::bail;
+
+    use super::*;
+    use crate::{
+        checks::{ErrorPolicy, FnCheck},
+        facts::{
+            AccountFacts, BatteryFacts, BluetoothFacts, DisplayFacts, GraphicsCardFacts,
+            InstalledAppsFacts, LicenseFacts, OsFacts, PrinterFacts, RegistryKey, SysinfoFacts,
+            SystemDeviceFacts, UsbFacts, VariousWmiFacts, WifiFacts,
+        },
+    };
+
+    /// How long a hung check takes, far past any deadline in these tests
+    const HANG: Duration = Duration::from_secs(2);
+    const DEADLINE: Duration = Duration::from_millis(50);
+    /// Reports must be back well before [`HANG`]
+    const ON_TIME: Duration = Duration::from_secs(1);
+
+    /// Hangs in `os`, every other fact fails at once
+    struct Hanging;
+
+    impl FactSource for Hanging {
+        fn system_devices(&self) -> anyhow::Result<SystemDeviceFacts> {
+            bail!("nf")
+        }
+
+        fn os(&self) -> anyhow::Result<OsFacts> {
+            thread::sleep(HANG);
+            bail!("nf")
+        }
+
+        fn installed_apps(&self) -> anyhow::Result<InstalledAppsFacts> {
+            bail!("nf")
+        }
+
+        fn license(&self) -> anyhow::Result<LicenseFacts> {
+            bail!("nf")
+        }
+
+        fn wifi_adapters(&self) -> anyhow::Result<WifiFacts> {
+            bail!("nf")
+        }
+
+        fn displays(&self) -> anyhow::Result<DisplayFacts> {
+            bail!("nf")
+        }
+
+        fn battery(&self) -> anyhow::Result<BatteryFacts> {
+            bail!("nf")
+        }
+
+        fn sysinfo(&self) -> anyhow::Result<SysinfoFacts> {
+            bail!("nf")
+        }
+
+        fn usb_devices(&self) -> anyhow::Result<UsbFacts> {
+            bail!("nf")
+        }
+
+        fn registry(&self) -> anyhow::Result<Box<dyn RegistryKey + '_>> {
+            bail!("nf")
+        }
+
+        fn microsoft_account(&self) -> anyhow::Result<AccountFacts> {
+            bail!("nf")
+        }
+
+        fn graphics_cards(&self) -> anyhow::Result<GraphicsCardFacts> {
+            bail!("nf")
+        }
+
+        fn bluetooth_adapters(&self) -> anyhow::Result<BluetoothFacts> {
+            bail!("nf")
+        }
+
+        fn various_wmi(&self) -> anyhow::Result<VariousWmiFacts> {
+            bail!("nf")
+        }
+
+        fn printers(&self) -> anyhow::Result<PrinterFacts> {
+            bail!("nf")
+        }
+    }
+
+    static HUNG_FAST: FnCheck = FnCheck {
+        id: "hung-fast",
+        name: "hung fast",
+        needs_com: false,
+        cost: Cost::Fast,
+        error_policy: ErrorPolicy::Ignore,
+        run: |source, _, _| source.os().map(drop),
+    };
+
+    static HUNG_SLOW: FnCheck = FnCheck {
+        id: "hung-slow",
+        name: "hung slow",
+        cost: Cost::Slow,
+        ..HUNG_FAST
+    };
+
+    static QUICK: FnCheck = FnCheck {
+        id: "quick",
+        name: "quick",
+        needs_com: false,
+        cost: Cost::Fast,
+        error_policy: ErrorPolicy::Ignore,
+        run: |_, _, flags| {
+            flags.tiny_penalty("quick.ran", "ran");
+            Ok(())
+        },
+    };
+
+    fn run(checks: &[&'static dyn Check], timeouts: Timeouts) -> (Vec<CheckReport>, Duration) {
+        let start = Instant::now();
+        let reports = run_checks(
+            Arc::new(Hanging),
+            Arc::new(ScoringProfile::default()),
+            checks,
+            timeouts,
+        );
+        (reports, start.elapsed())
+    }
+
+    fn ids(report: &CheckReport) -> Vec<&str> {
+        report
+            .flags
+            .findings()
+            .iter()
+            .map(|f| &*f.rule_id)
+            .collect()
+    }
+
+    #[test]
+    fn deadline() {
+        // The quick check is queued behind the hung one on the shared lane, and has to move to a new one
+        let (reports, elapsed) = run(
+            &[&HUNG_FAST, &QUICK, &HUNG_SLOW],
+            Timeouts {
+                check: Some(DEADLINE),
+                budget: None,
+            },
+        );
+        assert!(elapsed < ON_TIME, "took {elapsed:?}");
+
+        let [hung_fast, quick, hung_slow] = reports.as_slice() else {
+            panic!("{} reports", reports.len());
+        };
+        for hung in [hung_fast, hung_slow] {
+            assert!(hung.timed_out);
+            assert_eq!(hung.error.as_deref(), Some("timed out after 50ms"));
+            assert_eq!(ids(hung), [format!("{}.timed_out", hung.name)]);
+        }
+        assert!(!quick.timed_out);
+        assert_eq!(quick.error, None);
+        assert_eq!(ids(quick), ["quick.ran"]);
+    }
+
+    #[test]
+    fn budget() {
+        let (reports, elapsed) = run(
+            &[&HUNG_FAST, &QUICK, &HUNG_SLOW],
+            Timeouts {
+                check: Some(HANG * 2),
+                budget: Some(DEADLINE),
+            },
+        );
+        assert!(elapsed < ON_TIME, "took {elapsed:?}");
+
+        let [hung_fast, queued, hung_slow] = reports.as_slice() else {
+            panic!("{} reports", reports.len());
+        };
+        for report in [hung_fast, queued, hung_slow] {
+            assert!(report.timed_out);
+            assert_eq!(ids(report), [format!("{}.timed_out", report.name)]);
+        }
+        assert_eq!(
+            queued.error.as_deref(),
+            Some("time budget ran out before it started")
+        );
+    }
+
+    #[test]
+    fn no_timeouts() {
+        let (reports, _) = run(
+            &[&QUICK, &QUICK],
+            Timeouts {
+                check: Some(DEADLINE),
+                budget: Some(ON_TIME),
+            },
+        );
+        assert_eq!(reports.len(), 2);
+        assert!(reports.iter().all(|r| !r.timed_out && r.error.is_none()));
+    }
+}
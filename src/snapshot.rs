@@ -0,0 +1,472 @@
+use std::{
+    fs,
+    path::Path,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use anyhow::{Context, anyhow, bail};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::facts::{
+    AccountFacts, BatteryFacts, BluetoothFacts, DisplayFacts, FactSource, GraphicsCardFacts,
+    InstalledAppsFacts, LicenseFacts, MemoryKey, OsFacts, PrinterFacts, RegValue, RegistryKey,
+    SysinfoFacts, SystemDeviceFacts, UsbFacts, VariousWmiFacts, WifiFacts,
+};
+use crate::win_time::FileTime;
+#[cfg(windows)]
+use crate::{
+    DetectOptions,
+    detect::{DetectionReport, detect_with, live_options},
+    facts::LiveSource,
+};
+
+/// Bumped whenever the layout of [`Snapshot`] changes in a way older readers can't handle
+pub const SNAPSHOT_VERSION: u32 = 2;
+/// Oldest version still read, what later versions added defaults to empty
+const OLDEST_SNAPSHOT_VERSION: u32 = 1;
+
+/// Every raw fact a detection run looked at. A fact that failed to collect keeps its error, so replaying
+/// reproduces the same error penalties.
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub captured_at: DateTime<Utc>,
+    /// Whether the checks that need COM were run
+    pub com_enabled: bool,
+    pub system_devices: Result<SystemDeviceFacts, String>,
+    pub os: Result<OsFacts, String>,
+    pub installed_apps: Result<InstalledAppsFacts, String>,
+    pub license: Result<LicenseFacts, String>,
+    pub wifi_adapters: Result<WifiFacts, String>,
+    pub displays: Result<DisplayFacts, String>,
+    pub battery: Result<BatteryFacts, String>,
+    pub sysinfo: Result<SysinfoFacts, String>,
+    pub usb_devices: Result<UsbFacts, String>,
+    /// Only the keys and values under `HKEY_LOCAL_MACHINE` that were visited
+    pub registry: MemoryKey,
+    pub microsoft_account: Result<AccountFacts, String>,
+    pub graphics_cards: Result<GraphicsCardFacts, String>,
+    pub bluetooth_adapters: Result<BluetoothFacts, String>,
+    pub various_wmi: Result<VariousWmiFacts, String>,
+    pub printers: Result<PrinterFacts, String>,
+}
+
+fn not_captured<T>() -> Result<T, String> {
+    Err("not captured".to_owned())
+}
+
+impl Snapshot {
+    #[must_use]
+    pub fn empty() -> Self {
+        Self {
+            version: SNAPSHOT_VERSION,
+            captured_at: Utc::now(),
+            com_enabled: true,
+            system_devices: not_captured(),
+            os: not_captured(),
+            installed_apps: not_captured(),
+            license: not_captured(),
+            wifi_adapters: not_captured(),
+            displays: not_captured(),
+            battery: not_captured(),
+            sysinfo: not_captured(),
+            usb_devices: not_captured(),
+            registry: MemoryKey::default(),
+            microsoft_account: not_captured(),
+            graphics_cards: not_captured(),
+            bluetooth_adapters: not_captured(),
+            various_wmi: not_captured(),
+            printers: not_captured(),
+        }
+    }
+
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        #[derive(Deserialize)]
+        struct Version {
+            version: u32,
+        }
+
+        let Version { version } = serde_json::from_str(json).context("nv")?;
+        if !(OLDEST_SNAPSHOT_VERSION..=SNAPSHOT_VERSION).contains(&version) {
+            bail!(
+                "unsupported snapshot version {version}, expected {OLDEST_SNAPSHOT_VERSION} to {SNAPSHOT_VERSION}"
+            );
+        }
+
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        Ok(fs::write(path, self.to_json()?)?)
+    }
+}
+
+fn replay<T: Clone>(fact: &Result<T, String>) -> anyhow::Result<T> {
+    fact.clone().map_err(|err| anyhow!(err))
+}
+
+impl FactSource for Snapshot {
+    fn system_devices(&self) -> anyhow::Result<SystemDeviceFacts> {
+        replay(&self.system_devices)
+    }
+
+    fn os(&self) -> anyhow::Result<OsFacts> {
+        replay(&self.os)
+    }
+
+    fn installed_apps(&self) -> anyhow::Result<InstalledAppsFacts> {
+        replay(&self.installed_apps)
+    }
+
+    fn license(&self) -> anyhow::Result<LicenseFacts> {
+        replay(&self.license)
+    }
+
+    fn wifi_adapters(&self) -> anyhow::Result<WifiFacts> {
+        replay(&self.wifi_adapters)
+    }
+
+    fn displays(&self) -> anyhow::Result<DisplayFacts> {
+        replay(&self.displays)
+    }
+
+    fn battery(&self) -> anyhow::Result<BatteryFacts> {
+        replay(&self.battery)
+    }
+
+    fn sysinfo(&self) -> anyhow::Result<SysinfoFacts> {
+        replay(&self.sysinfo)
+    }
+
+    fn usb_devices(&self) -> anyhow::Result<UsbFacts> {
+        replay(&self.usb_devices)
+    }
+
+    fn registry(&self) -> anyhow::Result<Box<dyn RegistryKey + '_>> {
+        Ok(Box::new(&self.registry))
+    }
+
+    fn microsoft_account(&self) -> anyhow::Result<AccountFacts> {
+        replay(&self.microsoft_account)
+    }
+
+    fn graphics_cards(&self) -> anyhow::Result<GraphicsCardFacts> {
+        replay(&self.graphics_cards)
+    }
+
+    fn bluetooth_adapters(&self) -> anyhow::Result<BluetoothFacts> {
+        replay(&self.bluetooth_adapters)
+    }
+
+    fn various_wmi(&self) -> anyhow::Result<VariousWmiFacts> {
+        replay(&self.various_wmi)
+    }
+
+    fn printers(&self) -> anyhow::Result<PrinterFacts> {
+        replay(&self.printers)
+    }
+}
+
+/// Runs every check against the live system, recording everything they looked at
+#[cfg(windows)]
+#[must_use]
+pub fn capture(options: &DetectOptions) -> (DetectionReport, Snapshot) {
+    let options = live_options(options);
+    let source = Arc::new(CapturingSource::new(Arc::new(LiveSource)));
+    let report = detect_with(Arc::clone(&source) as Arc<dyn FactSource>, &options);
+
+    let mut snapshot = source.snapshot();
+    snapshot.com_enabled = options.enable_com;
+    (report, snapshot)
+}
+
+/// Passes facts through from another source while recording them into a [`Snapshot`]
+pub struct CapturingSource {
+    inner: Arc<dyn FactSource>,
+    snapshot: Mutex<Snapshot>,
+    registry: Mutex<MemoryKey>,
+}
+
+impl CapturingSource {
+    #[must_use]
+    pub fn new(inner: Arc<dyn FactSource>) -> Self {
+        Self {
+            inner,
+            snapshot: Mutex::new(Snapshot::empty()),
+            registry: Mutex::new(MemoryKey::default()),
+        }
+    }
+
+    /// Everything recorded so far. Checks that timed out may still be running and add to it later.
+    #[must_use]
+    pub fn snapshot(&self) -> Snapshot {
+        let mut snapshot = self
+            .snapshot
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        snapshot.registry = self
+            .registry
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        snapshot
+    }
+
+    fn record<T: Clone>(
+        &self,
+        slot: impl FnOnce(&mut Snapshot) -> &mut Result<T, String>,
+        fact: anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let mut snapshot = self.snapshot.lock().unwrap_or_else(PoisonError::into_inner);
+        *slot(&mut snapshot) = match &fact {
+            Ok(fact) => Ok(fact.clone()),
+            Err(err) => Err(format!("{err:#}")),
+        };
+        fact
+    }
+}
+
+impl FactSource for CapturingSource {
+    fn system_devices(&self) -> anyhow::Result<SystemDeviceFacts> {
+        self.record(|s| &mut s.system_devices, self.inner.system_devices())
+    }
+
+    fn os(&self) -> anyhow::Result<OsFacts> {
+        self.record(|s| &mut s.os, self.inner.os())
+    }
+
+    fn installed_apps(&self) -> anyhow::Result<InstalledAppsFacts> {
+        self.record(|s| &mut s.installed_apps, self.inner.installed_apps())
+    }
+
+    fn license(&self) -> anyhow::Result<LicenseFacts> {
+        self.record(|s| &mut s.license, self.inner.license())
+    }
+
+    fn wifi_adapters(&self) -> anyhow::Result<WifiFacts> {
+        self.record(|s| &mut s.wifi_adapters, self.inner.wifi_adapters())
+    }
+
+    fn displays(&self) -> anyhow::Result<DisplayFacts> {
+        self.record(|s| &mut s.displays, self.inner.displays())
+    }
+
+    fn battery(&self) -> anyhow::Result<BatteryFacts> {
+        self.record(|s| &mut s.battery, self.inner.battery())
+    }
+
+    fn sysinfo(&self) -> anyhow::Result<SysinfoFacts> {
+        self.record(|s| &mut s.sysinfo, self.inner.sysinfo())
+    }
+
+    fn usb_devices(&self) -> anyhow::Result<UsbFacts> {
+        self.record(|s| &mut s.usb_devices, self.inner.usb_devices())
+    }
+
+    fn registry(&self) -> anyhow::Result<Box<dyn RegistryKey + '_>> {
+        Ok(Box::new(RecordingKey {
+            inner: self.inner.registry()?,
+            path: String::new(),
+            record: &self.registry,
+        }))
+    }
+
+    fn microsoft_account(&self) -> anyhow::Result<AccountFacts> {
+        self.record(|s| &mut s.microsoft_account, self.inner.microsoft_account())
+    }
+
+    fn graphics_cards(&self) -> anyhow::Result<GraphicsCardFacts> {
+        self.record(|s| &mut s.graphics_cards, self.inner.graphics_cards())
+    }
+
+    fn bluetooth_adapters(&self) -> anyhow::Result<BluetoothFacts> {
+        self.record(
+            |s| &mut s.bluetooth_adapters,
+            self.inner.bluetooth_adapters(),
+        )
+    }
+
+    fn various_wmi(&self) -> anyhow::Result<VariousWmiFacts> {
+        self.record(|s| &mut s.various_wmi, self.inner.various_wmi())
+    }
+
+    fn printers(&self) -> anyhow::Result<PrinterFacts> {
+        self.record(|s| &mut s.printers, self.inner.printers())
+    }
+}
+
+/// Copies every key and value read through it into `record`
+struct RecordingKey<'a> {
+    inner: Box<dyn RegistryKey + 'a>,
+    path: String,
+    record: &'a Mutex<MemoryKey>,
+}
+
+impl RecordingKey<'_> {
+    fn child_path(&self, name: &str) -> String {
+        if self.path.is_empty() {
+            name.to_owned()
+        } else {
+            format!("{}\\{name}", self.path)
+        }
+    }
+
+    fn with_record(&self, f: impl FnOnce(&mut MemoryKey)) {
+        let mut root = self.record.lock().unwrap_or_else(PoisonError::into_inner);
+        f(root.subkey_mut(&self.path));
+    }
+}
+
+impl RegistryKey for RecordingKey<'_> {
+    fn open(&self, path: &str) -> anyhow::Result<Box<dyn RegistryKey + '_>> {
+        let inner = self.inner.open(path)?;
+        let path = self.child_path(path);
+        self.record
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .subkey_mut(&path);
+
+        Ok(Box::new(RecordingKey {
+            inner,
+            path,
+            record: self.record,
+        }))
+    }
+
+    fn keys(&self) -> anyhow::Result<Vec<String>> {
+        let keys = self.inner.keys()?;
+        self.with_record(|key| {
+            for name in &keys {
+                key.subkey_mut(name);
+            }
+        });
+        Ok(keys)
+    }
+
+    fn values(&self) -> anyhow::Result<Vec<(String, RegValue)>> {
+        let values = self.inner.values()?;
+        self.with_record(|key| {
+            for (name, value) in &values {
+                key.set_value(name, value.clone());
+            }
+        });
+        Ok(values)
+    }
+
+    fn value(&self, name: &str) -> anyhow::Result<RegValue> {
+        let value = self.inner.value(name)?;
+        self.with_record(|key| key.set_value(name, value.clone()));
+        Ok(value)
+    }
+
+    fn last_written(&self) -> anyhow::Result<FileTime> {
+        let last_written = self.inner.last_written()?;
+        self.with_record(|key| key.last_written = Some(last_written));
+        Ok(last_written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DetectOptions, DetectionReport, detect_with};
+
+    /// A `VMware` guest whose WMI failed, the rest of the facts were never captured
+    fn hand_written() -> Snapshot {
+        let mut registry = MemoryKey::default();
+        let pci = registry.subkey_mut(
+            "SYSTEM\\ControlSet001\\Enum\\PCI\\VEN_15AD&DEV_0740&SUBSYS_074015AD&REV_10\\3&61aaa01&0&3F",
+        );
+        pci.set_value(
+            "DeviceDesc",
+            RegValue::String("VMware VMCI Bus Device".to_owned()),
+        );
+        pci.set_value("Service", RegValue::String("vmci".to_owned()));
+        registry
+            .subkey_mut("HARDWARE\\DESCRIPTION\\System\\BIOS")
+            .set_value(
+                "SystemManufacturer",
+                RegValue::String("VMware, Inc.".to_owned()),
+            );
+        registry
+            .subkey_mut("SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion\\Winlogon")
+            .set_value("AutoAdminLogon", RegValue::String("1".to_owned()));
+
+        Snapshot {
+            system_devices: Ok(SystemDeviceFacts {
+                device_names: vec!["Standard PS/2 Keyboard".to_owned()],
+            }),
+            os: Err("WMI is unavailable".to_owned()),
+            registry,
+            ..Snapshot::empty()
+        }
+    }
+
+    /// Score, errored checks and findings, what a replay has to reproduce
+    fn outcome(report: &DetectionReport) -> (i64, Vec<String>, Vec<String>) {
+        let errors = report
+            .checks
+            .iter()
+            .filter_map(|c| Some(format!("{}: {}", c.name, c.error.as_deref()?)))
+            .collect();
+        let findings = report
+            .findings()
+            .map(|f| format!("{}: {} {:?}", f.check, f.rule_id, f.evidence))
+            .collect();
+        (report.score, errors, findings)
+    }
+
+    #[test]
+    fn round_trip() {
+        let options = DetectOptions::default();
+        let source = Arc::new(CapturingSource::new(Arc::new(hand_written())));
+        let captured = detect_with(Arc::clone(&source) as Arc<dyn FactSource>, &options);
+
+        let json = source.snapshot().to_json().unwrap();
+        let snapshot = Snapshot::from_json(&json).unwrap();
+        assert_eq!(snapshot.version, SNAPSHOT_VERSION);
+        assert_eq!(snapshot.os.as_ref().err().unwrap(), "WMI is unavailable");
+        assert_eq!(
+            snapshot
+                .registry
+                .open("HARDWARE\\DESCRIPTION\\System\\BIOS")
+                .unwrap()
+                .value("SystemManufacturer")
+                .unwrap(),
+            RegValue::String("VMware, Inc.".to_owned())
+        );
+        let replayed = detect_with(Arc::new(snapshot), &options);
+
+        let (score, errors, findings) = outcome(&captured);
+        assert!(score < 0);
+        assert!(
+            errors.contains(&"os: WMI is unavailable".to_owned()),
+            "{errors:?}"
+        );
+        assert!(
+            findings.iter().any(|f| f.contains("devices.pci_vmci")),
+            "{findings:?}"
+        );
+        assert_eq!(outcome(&replayed), (score, errors, findings));
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut snapshot = hand_written();
+        snapshot.version = SNAPSHOT_VERSION + 1;
+        Snapshot::from_json(&snapshot.to_json().unwrap())
+            .map(drop)
+            .unwrap_err();
+    }
+}
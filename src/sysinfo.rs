@@ -1,4 +1,6 @@
 use anyhow::Context;
+use serde::{Deserialize, Serialize};
+#[cfg(windows)]
 use windows::Win32::{
     Storage::FileSystem::{DISK_SPACE_INFORMATION, GetDiskSpaceInformationW},
     System::SystemInformation::{
@@ -6,32 +8,164 @@ use windows::Win32::{
         GetTickCount, SYSTEM_INFO,
     },
 };
+#[cfg(windows)]
 use windows_core::w;
 
-use crate::{debug_println, flags::Flags, inspect};
+#[cfg(windows)]
+use crate::inspect;
+use crate::{
+    debug_println,
+    flags::Flags,
+    profile::{bucket, ensure_ascending},
+};
 
-pub fn score_sysinfo(flags: &mut Flags) -> anyhow::Result<()> {
-    let mut memory_in_kilos = 0u64;
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SysinfoFacts {
+    pub memory_in_kilos: Option<u64>,
+    pub processors: u32,
+    pub tick_count_ms: u32,
+    pub integrated_display_size: Option<f64>,
+    /// Set if the C: drive couldn't be read and the current drive was used instead
+    pub c_drive_error: Option<String>,
+    pub disk_space: Option<DiskSpaceReport>,
+}
 
-    if unsafe { GetPhysicallyInstalledSystemMemory(&raw mut memory_in_kilos) }.is_ok() {
-        let memory_in_gigs = memory_in_kilos / (1024 * 1024);
-        debug_println!("memory installed: {memory_in_gigs}GB");
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DiskSpaceReport {
+    pub total_space_gig: u64,
+    pub free_space_gig: u64,
+}
+
+/// Bucket bounds are inclusive upper bounds, anything above the last one lands in the top bucket
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SysinfoThresholds {
+    pub ram_gb: RamBuckets,
+    pub uptime_secs: UptimeBuckets,
+    pub disk_total_gb: DiskSizeBuckets,
+    pub disk_used_gb: DiskUsageBuckets,
+}
 
-        match memory_in_gigs {
-            0..=2 => flags.extreme_penalty(),
-            3..=6 => flags.large_penalty(),
-            7..=8 => flags.medium_penalty(),
-            9..=23 => {}
-            24..=32 => flags.medium_bonus(),
-            _ => flags.large_bonus(),
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RamBuckets {
+    pub tiny: u64,
+    pub low: u64,
+    pub below_average: u64,
+    pub normal: u64,
+    pub high: u64,
+}
+
+impl Default for RamBuckets {
+    fn default() -> Self {
+        Self {
+            tiny: 2,
+            low: 6,
+            below_average: 8,
+            normal: 23,
+            high: 32,
         }
+    }
+}
 
-        if memory_in_gigs % 2 != 0 {
-            flags.large_penalty();
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UptimeBuckets {
+    pub minute: u32,
+    pub minutes: u32,
+}
+
+impl Default for UptimeBuckets {
+    fn default() -> Self {
+        Self {
+            minute: 60,
+            minutes: 180,
         }
-    } else {
-        flags.large_penalty();
     }
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiskSizeBuckets {
+    pub tiny: u64,
+    pub small: u64,
+    pub normal: u64,
+    pub large: u64,
+}
+
+impl Default for DiskSizeBuckets {
+    fn default() -> Self {
+        Self {
+            // Windows 11 requires >= 64gb disk to even install
+            tiny: 64,
+            small: 126,
+            normal: 511,
+            large: 1024,
+        }
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiskUsageBuckets {
+    /// (size on disk) could be anywhere from ~13-27gb
+    /// On a fresh VM install it's 13gb
+    pub windows_install: u64,
+    pub unused: u64,
+    pub barely_used: u64,
+    pub normal: u64,
+    pub used: u64,
+}
+
+impl Default for DiskUsageBuckets {
+    fn default() -> Self {
+        Self {
+            windows_install: 16,
+            unused: 3,
+            barely_used: 16,
+            normal: 64,
+            used: 128,
+        }
+    }
+}
+
+impl SysinfoThresholds {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let ram = &self.ram_gb;
+        ensure_ascending(
+            "ram",
+            &[ram.tiny, ram.low, ram.below_average, ram.normal, ram.high],
+        )?;
+        ensure_ascending(
+            "uptime",
+            &[self.uptime_secs.minute, self.uptime_secs.minutes],
+        )?;
+        let total = &self.disk_total_gb;
+        ensure_ascending(
+            "disk size",
+            &[total.tiny, total.small, total.normal, total.large],
+        )?;
+        let used = &self.disk_used_gb;
+        ensure_ascending(
+            "disk usage",
+            &[used.unused, used.barely_used, used.normal, used.used],
+        )
+    }
+}
+
+#[cfg(windows)]
+pub fn collect_sysinfo() -> SysinfoFacts {
+    let mut memory_in_kilos = 0u64;
+    let memory_in_kilos = unsafe { GetPhysicallyInstalledSystemMemory(&raw mut memory_in_kilos) }
+        .is_ok()
+        .then_some(memory_in_kilos);
 
     let mut system_info: SYSTEM_INFO = unsafe { core::mem::zeroed() };
     unsafe {
@@ -39,11 +173,88 @@ pub fn score_sysinfo(flags: &mut Flags) -> anyhow::Result<()> {
         GetNativeSystemInfo(&raw mut system_info);
     }
 
-    let processors = system_info.dwNumberOfProcessors;
+    // I think this method may just be broken (always 0x80004002 - No such interface supported)
+    // If has valid integrated display like a laptop
+    let integrated_display_size = inspect!(inner, "integrated display size", unsafe {
+        GetIntegratedDisplaySize()
+    })
+    .ok();
+
+    let mut c_drive_error = None;
+    let disk_space = inspect!(inner, "disk space", get_disk_space(&mut c_drive_error)).ok();
+
+    SysinfoFacts {
+        memory_in_kilos,
+        processors: system_info.dwNumberOfProcessors,
+        tick_count_ms: unsafe { GetTickCount() },
+        integrated_display_size,
+        c_drive_error,
+        disk_space,
+    }
+}
+
+pub fn score_sysinfo(
+    facts: &SysinfoFacts,
+    thresholds: &SysinfoThresholds,
+    flags: &mut Flags,
+) -> anyhow::Result<()> {
+    if let Some(memory_in_kilos) = facts.memory_in_kilos {
+        let memory_in_gigs = memory_in_kilos / (1024 * 1024);
+        debug_println!("memory installed: {memory_in_gigs}GB");
+
+        let ram = &thresholds.ram_gb;
+        match bucket(
+            &memory_in_gigs,
+            &[ram.tiny, ram.low, ram.below_average, ram.normal, ram.high],
+        ) {
+            0 => {
+                flags
+                    .extreme_penalty("sysinfo.ram_tiny", "tiny amount of RAM")
+                    .evidence(memory_in_gigs);
+            }
+            1 => {
+                flags
+                    .large_penalty("sysinfo.ram_low", "low amount of RAM")
+                    .evidence(memory_in_gigs);
+            }
+            2 => {
+                flags
+                    .medium_penalty("sysinfo.ram_below_average", "below average amount of RAM")
+                    .evidence(memory_in_gigs);
+            }
+            3 => {}
+            4 => {
+                flags
+                    .medium_bonus("sysinfo.ram_high", "high amount of RAM")
+                    .evidence(memory_in_gigs);
+            }
+            _ => {
+                flags
+                    .large_bonus("sysinfo.ram_very_high", "very high amount of RAM")
+                    .evidence(memory_in_gigs);
+            }
+        }
+
+        if !memory_in_gigs.is_multiple_of(2) {
+            flags
+                .large_penalty("sysinfo.ram_odd", "odd amount of RAM")
+                .evidence(memory_in_gigs);
+        }
+    } else {
+        flags.large_penalty("sysinfo.ram_unknown", "failed to read installed RAM");
+    }
+
+    let processors = facts.processors;
     debug_println!("number of processors: {processors}");
     match processors {
-        0..=1 => flags.large_penalty(),
-        2 => flags.medium_penalty(),
+        0..=1 => {
+            flags
+                .large_penalty("sysinfo.single_processor", "one processor or less")
+                .evidence(processors);
+        }
+        2 => {
+            flags.medium_penalty("sysinfo.two_processors", "only two processors");
+        }
         _ => {}
     }
 
@@ -51,75 +262,124 @@ pub fn score_sysinfo(flags: &mut Flags) -> anyhow::Result<()> {
     //     flags.large_penalty();
     // }
 
-    let tick_count_ms = unsafe { GetTickCount() };
-    let tick_count_sec = tick_count_ms / 1000;
+    let tick_count_sec = facts.tick_count_ms / 1000;
     debug_println!("tick count: {tick_count_sec}s");
 
-    match tick_count_sec {
-        0..=60 => flags.extreme_penalty(),
-        61..=180 => flags.large_penalty(),
+    let uptime = &thresholds.uptime_secs;
+    match bucket(&tick_count_sec, &[uptime.minute, uptime.minutes]) {
+        0 => {
+            flags
+                .extreme_penalty("sysinfo.uptime_minute", "booted moments ago")
+                .evidence(tick_count_sec);
+        }
+        1 => {
+            flags
+                .large_penalty("sysinfo.uptime_minutes", "booted a few minutes ago")
+                .evidence(tick_count_sec);
+        }
         _ => {}
     }
 
-    // I think this method may just be broken (always 0x80004002 - No such interface supported)
-    // If has valid integrated display like a laptop
     // This is also checked in displays.rs but in a different way
-    if matches!(inspect!(inner, "integrated display size", unsafe { GetIntegratedDisplaySize() }), Ok(size) if size > 256.0)
-    {
-        flags.medium_bonus();
+    if matches!(facts.integrated_display_size, Some(size) if size > 256.0) {
+        flags.medium_bonus("sysinfo.integrated_display", "has an integrated display");
     }
 
-    let disk_space = inspect!(inner, "disk space", get_disk_space(flags)).context("gds")?;
-    match disk_space.total_space_gig {
-        // Windows 11 requires >= 64gb disk to even install
-        0..=64 => flags.extreme_penalty(),
-        65..127 => flags.large_penalty(),
-        127..512 => {}
-        512..=1024 => flags.small_bonus(),
-        _ => flags.large_bonus(),
+    if let Some(err) = &facts.c_drive_error {
+        flags
+            .large_penalty("sysinfo.no_c_drive", "failed to read C: disk space")
+            .evidence(err);
     }
 
-    // (size on disk) could be anywhere from ~13-27gb
-    // On a fresh VM install it's 13gb
-    const EST_WINDOWS_DIR_SIZE_GIG: u64 = 16;
+    let disk_space = facts.disk_space.as_ref().context("gds")?;
+    let total_space_gig = disk_space.total_space_gig;
+    let total = &thresholds.disk_total_gb;
+    match bucket(
+        &total_space_gig,
+        &[total.tiny, total.small, total.normal, total.large],
+    ) {
+        0 => {
+            flags
+                .extreme_penalty("sysinfo.disk_tiny", "disk is tiny")
+                .evidence(total_space_gig);
+        }
+        1 => {
+            flags
+                .large_penalty("sysinfo.disk_small", "disk is small")
+                .evidence(total_space_gig);
+        }
+        2 => {}
+        3 => {
+            flags
+                .small_bonus("sysinfo.disk_large", "disk is large")
+                .evidence(total_space_gig);
+        }
+        _ => {
+            flags
+                .large_bonus("sysinfo.disk_huge", "disk is huge")
+                .evidence(total_space_gig);
+        }
+    }
+
+    let usage = &thresholds.disk_used_gb;
     let used_space_minus_windows_installation = disk_space
         .total_space_gig
-        .saturating_sub(disk_space.free_space_gig + EST_WINDOWS_DIR_SIZE_GIG);
+        .saturating_sub(disk_space.free_space_gig + usage.windows_install);
 
     debug_println!(
         "used space minus windows installation: {used_space_minus_windows_installation}GB"
     );
 
-    match used_space_minus_windows_installation {
-        0..=3 => flags.large_penalty(),
-        4..=16 => flags.medium_penalty(),
-        17..=64 => {}
-        65..=128 => flags.small_bonus(),
-        _ => flags.medium_bonus(),
+    let used = used_space_minus_windows_installation;
+    match bucket(
+        &used,
+        &[usage.unused, usage.barely_used, usage.normal, usage.used],
+    ) {
+        0 => {
+            flags
+                .large_penalty(
+                    "sysinfo.disk_unused",
+                    "almost nothing on disk besides windows",
+                )
+                .evidence(used);
+        }
+        1 => {
+            flags
+                .medium_penalty("sysinfo.disk_barely_used", "little on disk besides windows")
+                .evidence(used);
+        }
+        2 => {}
+        3 => {
+            flags
+                .small_bonus("sysinfo.disk_used", "disk is used besides windows")
+                .evidence(used);
+        }
+        _ => {
+            flags
+                .medium_bonus(
+                    "sysinfo.disk_well_used",
+                    "disk is well used besides windows",
+                )
+                .evidence(used);
+        }
     }
 
     Ok(())
 }
 
-#[cfg_attr(debug_assertions, derive(Debug))]
-struct DiskSpaceReport {
-    total_space_gig: u64,
-    free_space_gig: u64,
-}
-
-fn get_disk_space(flags: &mut Flags) -> anyhow::Result<DiskSpaceReport> {
+#[cfg(windows)]
+fn get_disk_space(c_drive_error: &mut Option<String>) -> anyhow::Result<DiskSpaceReport> {
     let mut disk_space_information: DISK_SPACE_INFORMATION = unsafe { core::mem::zeroed() };
     unsafe {
         // Initally try to use main disk in case we are being executed from a USB drive or network
         if let Err(err) = GetDiskSpaceInformationW(w!("C:/"), &raw mut disk_space_information) {
             debug_println!("Error getting C: disk space information: {err:?}");
-            flags.large_penalty();
+            *c_drive_error = Some(err.to_string());
 
             // Fallback to current disk
             GetDiskSpaceInformationW(None, &raw mut disk_space_information)?;
         }
     }
-
     let bytes_per_unit = (disk_space_information.SectorsPerAllocationUnit
         * disk_space_information.BytesPerSector) as u64;
 
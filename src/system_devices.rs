@@ -1,102 +1,171 @@
+use serde::{Deserialize, Serialize};
+#[cfg(windows)]
 use windows::Devices::Enumeration::DeviceInformation;
-use windows_registry::LOCAL_MACHINE;
 
-use crate::flags::Flags;
+use crate::{
+    flags::Flags, hypervisor::Hypervisor, registry::current_control_set, registry_key::RegistryKey,
+    registry_plan::Plan,
+};
 
-pub fn score_system_devices(flags: &mut Flags) -> anyhow::Result<()> {
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct SystemDeviceFacts {
+    pub device_names: Vec<String>,
+}
+
+#[cfg(windows)]
+pub fn collect_system_devices() -> anyhow::Result<SystemDeviceFacts> {
     // (Obviously) This is the bottleneck BUT: There is no Win32 API for this
     let devices = DeviceInformation::FindAllAsync()?.get()?;
 
+    let device_names = devices
+        .into_iter()
+        .filter_map(|device| device.Name().ok())
+        .map(|name| name.to_string_lossy())
+        .collect();
+
+    Ok(SystemDeviceFacts { device_names })
+}
+
+pub fn score_system_devices(
+    facts: &SystemDeviceFacts,
+    local_machine: &dyn RegistryKey,
+    flags: &mut Flags,
+) -> anyhow::Result<()> {
     // No bonuses here any since it's common for hardened VMs to have these modified
-    for device in devices {
-        let Ok(name) = device.Name() else { continue };
-        let lc = name.to_string_lossy().to_lowercase();
+    for name in &facts.device_names {
+        let lc = name.to_lowercase();
 
         if lc.contains("vmware") || lc.contains("virtualbox") || lc.contains("vbox") {
-            flags.end_all_penalty();
+            flags
+                .end_all_penalty("devices.vm_name", "device names a VM vendor")
+                .evidence(name)
+                .vendor_from(name);
         }
 
         if lc.contains("ps/2") {
-            flags.medium_penalty();
+            flags
+                .medium_penalty("devices.ps2", "PS/2 device")
+                .evidence(name);
         }
     }
 
-    for pci in get_registry_pci()? {
+    for pci in get_registry_pci(local_machine)? {
         let dd = pci.device_desc.to_lowercase();
         if dd.contains("vmware") || dd.contains("virtualbox") || dd.contains("vbox") {
-            flags.end_all_penalty();
+            flags
+                .end_all_penalty("devices.pci_vm_description", "PCI device names a VM vendor")
+                .evidence(&pci.device_desc)
+                .vendor_from(&pci.device_desc);
         }
 
         if dd.contains("ps/2") {
-            flags.medium_penalty();
+            flags
+                .medium_penalty("devices.pci_ps2", "PS/2 PCI device")
+                .evidence(&pci.device_desc);
         }
 
         if matches!(pci.service.as_deref(), Some("vmci")) {
-            flags.extreme_penalty();
+            flags
+                .extreme_penalty(
+                    "devices.pci_vmci",
+                    "PCI device uses the VMware VMCI service",
+                )
+                .evidence(&pci.device_desc)
+                .vendor(Hypervisor::VMware);
         }
 
-        if pci
+        if let Some(id) = pci
             .hardware_id
             .iter()
-            .any(|id| id.to_lowercase().contains("vbox"))
+            .find(|id| id.to_lowercase().contains("vbox"))
         {
-            flags.end_all_penalty();
+            flags
+                .end_all_penalty(
+                    "devices.pci_vbox_hardware_id",
+                    "PCI hardware id names VirtualBox",
+                )
+                .evidence(id)
+                .vendor(Hypervisor::VirtualBox);
         }
 
         let mfr = pci.manufacturer.to_lowercase();
         if mfr.contains("vmware") || mfr.contains("virtualbox") || mfr.contains("vbox") {
-            flags.end_all_penalty();
+            flags
+                .end_all_penalty(
+                    "devices.pci_vm_manufacturer",
+                    "PCI manufacturer is a VM vendor",
+                )
+                .evidence(&pci.manufacturer)
+                .vendor_from(&pci.manufacturer);
         }
 
         if mfr.contains("microsoft corporation") {
-            flags.medium_penalty();
+            flags
+                .medium_penalty(
+                    "devices.pci_microsoft",
+                    "PCI device manufactured by microsoft",
+                )
+                .evidence(&pci.device_desc)
+                .vendor(Hypervisor::HyperV);
         }
     }
     Ok(())
 }
 
-#[allow(dead_code)]
-struct PciDevice {
-    class_guid: String,
-    device_desc: String, // ...VMware VMCI Bus Device
-    hardware_id: Vec<String>,
-    manufacturer: String,
-    service: Option<String>, // vmci
-    driver: String,          // also a guid
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PciDevice {
+    pub class_guid: String,
+    pub device_desc: String, // ...VMware VMCI Bus Device
+    pub hardware_id: Vec<String>,
+    pub manufacturer: String,
+    pub service: Option<String>, // vmci
+    pub driver: String,          // also a guid
 }
 
-fn get_registry_pci() -> anyhow::Result<Vec<PciDevice>> {
-    //HKEY_LOCAL_MACHINE\SYSTEM\CurrentControlSet\Enum\PCI\
-    let root = LOCAL_MACHINE.open("SYSTEM\\CurrentControlSet\\Enum\\PCI")?;
+/// Adds the device instances under `Enum\PCI` to the registry preload
+pub fn plan_registry_pci(plan: &mut Plan, control_set: &str) {
+    plan.at(&format!("SYSTEM\\{control_set}\\Enum\\PCI"))
+        .every()
+        .every()
+        .read_values();
+}
+
+pub fn get_registry_pci(local_machine: &dyn RegistryKey) -> anyhow::Result<Vec<PciDevice>> {
+    let control_set = current_control_set(local_machine);
+    let root = local_machine.open(&format!("SYSTEM\\{control_set}\\Enum\\PCI"))?;
     let pci_devices = root
         .keys()?
-        .filter_map(|key| root.open(key).ok())
-        .filter_map(|key| {
-            let key_parents = key.keys().ok()?;
-            let keys = key_parents
-                .filter_map(|s| key.open(s).ok())
-                .collect::<Vec<_>>();
-            Some(keys)
-        })
-        .flatten()
-        .filter_map(|key| {
-            let class_guid = key.get_string("ClassGuid").ok()?;
-            let device_desc = key.get_string("DeviceDesc").ok()?;
-            let hardware_id = key.get_multi_string("HardwareID").ok()?;
-            let manufacturer = key.get_string("Mfg").ok()?;
-            let service = key.get_string("Service").ok();
-            let driver = key.get_string("Driver").ok()?;
-
-            Some(PciDevice {
-                class_guid,
-                device_desc,
-                hardware_id,
-                manufacturer,
-                service,
-                driver,
-            })
+        .into_iter()
+        .filter_map(|key| root.open(&key).ok())
+        .flat_map(|key| {
+            key.keys()
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|instance| read_pci_device(&*key.open(&instance).ok()?))
+                .collect::<Vec<_>>()
         })
         .collect::<Vec<_>>();
 
     Ok(pci_devices)
 }
+
+/// Only the description is required, so partial copies of the registry (exports, snapshots) still list the device
+fn read_pci_device(key: &dyn RegistryKey) -> Option<PciDevice> {
+    let device_desc = key.get_string("DeviceDesc").ok()?;
+    let class_guid = key.get_string("ClassGuid").unwrap_or_default();
+    let hardware_id = key.get_multi_string("HardwareID").unwrap_or_default();
+    let manufacturer = key.get_string("Mfg").unwrap_or_default();
+    let service = key.get_string("Service").ok();
+    let driver = key.get_string("Driver").unwrap_or_default();
+
+    Some(PciDevice {
+        class_guid,
+        device_desc,
+        hardware_id,
+        manufacturer,
+        service,
+        driver,
+    })
+}
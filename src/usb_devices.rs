@@ -1,12 +1,49 @@
-use nusb::{DeviceInfo, InterfaceInfo};
+use serde::{Deserialize, Serialize};
 
-use crate::{debug_println, flags::Flags};
+use crate::{debug_println, flags::Flags, hypervisor::Hypervisor};
 
-pub fn score_usb_devices(flags: &mut Flags) -> anyhow::Result<()> {
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct UsbFacts {
+    pub devices: Vec<UsbDevice>,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct UsbDevice {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub product_string: Option<String>,
+    pub instance_id: String,
+    pub driver: Option<String>,
+    pub interface_strings: Vec<String>,
+}
+
+#[cfg(windows)]
+pub fn collect_usb_devices() -> anyhow::Result<UsbFacts> {
+    let devices = nusb::list_devices()?
+        .map(|dev| UsbDevice {
+            vendor_id: dev.vendor_id(),
+            product_id: dev.product_id(),
+            product_string: dev.product_string().map(str::to_owned),
+            instance_id: dev.instance_id().to_string_lossy().into_owned(),
+            driver: dev.driver().map(str::to_owned),
+            interface_strings: dev
+                .interfaces()
+                .filter_map(|int| int.interface_string())
+                .map(str::to_owned)
+                .collect(),
+        })
+        .collect();
+
+    Ok(UsbFacts { devices })
+}
+
+pub fn score_usb_devices(facts: &UsbFacts, flags: &mut Flags) {
     let mut valid_devices = 0u32;
-    for dev in nusb::list_devices()? {
+    for dev in &facts.devices {
         let score_before = flags.score();
-        score_device(&dev, flags);
+        score_device(dev, flags);
 
         // if score was not decreased by at least 5, treat it as real
         if flags.score() >= (score_before - 5) {
@@ -16,94 +53,105 @@ pub fn score_usb_devices(flags: &mut Flags) -> anyhow::Result<()> {
 
     debug_println!("valid devices: {valid_devices}");
     match valid_devices {
-        0 => flags.large_penalty(),
+        0 => {
+            flags.large_penalty("usb.no_devices", "no valid USB devices");
+        }
         1..=5 => {}
-        _ => flags.large_bonus(),
+        _ => {
+            flags
+                .large_bonus("usb.many_devices", "more than 5 valid USB devices")
+                .evidence(valid_devices);
+        }
     }
-
-    Ok(())
 }
 
 // FIXME maybe remove the bonuses here, since they can be spoofed, or decrease the bonus
-fn score_device(dev: &DeviceInfo, flags: &mut Flags) {
+fn score_device(dev: &UsbDevice, flags: &mut Flags) {
     // https://the-sz.com/products/usbid/index.php
-    if
-    // Vmware
-    dev.vendor_id() == 0x0E0F || dev.vendor_id() == 0x15AD ||
-    // VirtualBox
-    dev.vendor_id() == 0x80EE ||
-    // Parallels
-    dev.vendor_id() == 0x203A ||
-    // QEMU
-    dev.vendor_id() == 0x46F4
-    {
-        flags.end_all_penalty();
+    if let Some(vendor) = Hypervisor::from_usb_vendor(dev.vendor_id) {
+        flags
+            .end_all_penalty("usb.vm_vendor", "USB device from a VM vendor")
+            .evidence(format!("{:04X}", dev.vendor_id))
+            .vendor(vendor);
     }
 
     // Intel bluetooth
-    if dev.vendor_id() == 0x8087
-        && (dev.product_id() == 0x0025 ||
-        dev.product_id() == 0x0026 ||
-        dev.product_id() == 0x0029 ||
-        dev.product_id() == 0x0032 ||
-        dev.product_id() == 0x0033 ||
-        dev.product_id() == 0x0036 ||
+    if dev.vendor_id == 0x8087
+        && (dev.product_id == 0x0025 ||
+        dev.product_id == 0x0026 ||
+        dev.product_id == 0x0029 ||
+        dev.product_id == 0x0032 ||
+        dev.product_id == 0x0033 ||
+        dev.product_id == 0x0036 ||
         // These are bluetooth wireless interfaces
-        dev.product_id() == 0x07DC ||
-        dev.product_id() == 0x0A2A ||
-        dev.product_id() == 0x0A2B ||
+        dev.product_id == 0x07DC ||
+        dev.product_id == 0x0A2A ||
+        dev.product_id == 0x0A2B ||
         // Wireless-AC XYZ Bluetooth
-        dev.product_id() == 0x0AA7 ||
-        dev.product_id() == 0x0AAA)
+        dev.product_id == 0x0AA7 ||
+        dev.product_id == 0x0AAA)
     {
-        flags.medium_bonus();
+        flags
+            .medium_bonus("usb.intel_bluetooth", "intel bluetooth USB device")
+            .evidence(format!("{:04X}", dev.product_id));
     }
 
-    if REAL_VENDORS.iter().any(|v| *v == dev.vendor_id()) {
-        flags.medium_bonus();
+    if REAL_VENDORS.contains(&dev.vendor_id) {
+        flags
+            .medium_bonus("usb.real_vendor", "USB device from a known hardware vendor")
+            .evidence(format!("{:04X}", dev.vendor_id));
     }
 
     #[allow(clippy::collapsible_if)]
-    if let Some(product) = dev.product_string() {
+    if let Some(product) = &dev.product_string {
         if product.contains("VMware") || product.contains("VirtualBox") {
-            flags.end_all_penalty();
+            flags
+                .end_all_penalty("usb.vm_product", "USB product names a VM vendor")
+                .evidence(product)
+                .vendor_from(product);
         }
     }
 
     // VMware
     if dev
-        .instance_id()
+        .instance_id
         .eq_ignore_ascii_case("USB\\VID_0E0F&PID_0003\\6&39D724FE")
     {
-        flags.end_all_penalty();
+        flags
+            .end_all_penalty("usb.vmware_instance", "known VMware USB instance id")
+            .evidence(&dev.instance_id)
+            .vendor(Hypervisor::VMware);
     }
 
     #[allow(clippy::collapsible_if)]
-    if let Some(driver) = dev.driver() {
+    if let Some(driver) = &dev.driver {
         // TODO expand this?
         if driver == "FocusriteUsb" {
-            flags.medium_bonus();
+            flags.medium_bonus("usb.focusrite_driver", "focusrite audio interface driver");
         }
     }
 
-    for interface in dev.interfaces() {
+    for interface in &dev.interface_strings {
         score_interface(interface, flags);
     }
 }
 
-fn score_interface(int: &InterfaceInfo, flags: &mut Flags) {
-    if let Some(str) = int.interface_string() {
-        if str.contains("VMware") || str.contains("VirtualBox") {
-            flags.end_all_penalty();
-        }
+fn score_interface(str: &str, flags: &mut Flags) {
+    if str.contains("VMware") || str.contains("VirtualBox") {
+        flags
+            .end_all_penalty("usb.vm_interface", "USB interface names a VM vendor")
+            .evidence(str)
+            .vendor_from(str);
+    }
 
-        if str == "Keychron Link" {
-            flags.medium_bonus();
-        }
+    if str == "Keychron Link" {
+        flags.medium_bonus("usb.keychron", "keychron receiver");
+    }
 
-        if str.contains("NuPhy") {
-            flags.medium_bonus();
-        }
+    if str.contains("NuPhy") {
+        flags
+            .medium_bonus("usb.nuphy", "nuphy keyboard")
+            .evidence(str);
     }
 }
 
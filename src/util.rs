@@ -1,4 +1,6 @@
+#[cfg(windows)]
 use windows::Devices::Enumeration::{DeviceInformation, DeviceInformationKind};
+#[cfg(windows)]
 use windows_core::HSTRING;
 
 #[cfg(debug_assertions)]
@@ -92,6 +94,7 @@ macro_rules! inspect {
     };
 }
 
+#[cfg(windows)]
 pub fn get_devices_iter(
     selector: &HSTRING,
 ) -> anyhow::Result<impl Iterator<Item = DeviceInformation>> {
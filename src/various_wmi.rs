@@ -1,127 +1,149 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+#[cfg(windows)]
 use wmi::{COMLibrary, WMIConnection};
 
-use crate::flags::Flags;
+use crate::{flags::Flags, hypervisor::Hypervisor};
 
-pub fn score_various_wmi(flags: &mut Flags) -> anyhow::Result<()> {
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct VariousWmiFacts {
+    pub computer_systems: Vec<Win32ComputerSystem>,
+    pub bios: Vec<Win32Bios>,
+    pub user_devices: Vec<CimUserDevice>,
+    pub cards: Vec<CimCard>,
+    pub chassis: Vec<CimChassis>,
+}
+
+#[cfg(windows)]
+pub fn collect_various_wmi() -> anyhow::Result<VariousWmiFacts> {
     let com_con = unsafe { COMLibrary::assume_initialized() };
     let wmi_con = WMIConnection::new(com_con)?;
 
-    for system in wmi_con
-        .raw_query::<Win32ComputerSystem>(
-            "SELECT PowerOnPasswordStatus, Manufacturer, Model FROM Win32_ComputerSystem",
-        )
-        .unwrap_or_default()
-    {
+    Ok(VariousWmiFacts {
+        computer_systems: wmi_con
+            .raw_query(
+                "SELECT PowerOnPasswordStatus, Manufacturer, Model FROM Win32_ComputerSystem",
+            )
+            .unwrap_or_default(),
+        bios: wmi_con.raw_query("SELECT Name, Caption, __RELPATH, __PATH, BIOSVersion, Description, Manufacturer, SMBIOSBIOSVersion, SoftwareElementID, Path, SerialNumber FROM Win32_BIOS").unwrap_or_default(),
+        user_devices: wmi_con.raw_query("SELECT __DYNASTY, __PATH, DeviceID, PNPDeviceID, Path, __RELPATH, __NAMESPACE, Caption, Description, HardwareType FROM CIM_UserDevice").unwrap_or_default(),
+        cards: wmi_con
+            .raw_query("SELECT Product FROM CIM_Card")
+            .unwrap_or_default(),
+        chassis: wmi_con
+            .raw_query("SELECT ChassisTypes, Manufacturer FROM CIM_Chassis")
+            .unwrap_or_default(),
+    })
+}
+
+pub fn score_various_wmi(facts: &VariousWmiFacts, flags: &mut Flags) {
+    for system in &facts.computer_systems {
         system.score(flags);
     }
 
-    for bios in wmi_con.raw_query::<Win32Bios>("SELECT Name, Caption, __RELPATH, __PATH, BIOSVersion, Description, Manufacturer, SMBIOSBIOSVersion, SoftwareElementID, Path, SerialNumber FROM Win32_BIOS").unwrap_or_default() {
+    for bios in &facts.bios {
         bios.score(flags);
     }
 
-    for device in wmi_con.raw_query::<CimUserDevice>("SELECT __DYNASTY, __PATH, DeviceID, PNPDeviceID, Path, __RELPATH, __NAMESPACE, Caption, Description, HardwareType FROM CIM_UserDevice").unwrap_or_default() {
+    for device in &facts.user_devices {
         device.score(flags);
     }
 
-    for card in wmi_con
-        .raw_query::<CimCard>("SELECT Product FROM CIM_Card")
-        .unwrap_or_default()
-    {
+    for card in &facts.cards {
         card.score(flags);
     }
 
-    for ch in wmi_con
-        .raw_query::<CimChassis>("SELECT ChassisTypes, Manufacturer FROM CIM_Chassis")
-        .unwrap_or_default()
-    {
+    for ch in &facts.chassis {
         ch.score(flags);
     }
-
-    Ok(())
 }
 
 // Type 1 = VMware
 // Type 2 = VMW
 // Type 3 = VID_0E0F
 
-#[derive(Deserialize)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
-struct Win32ComputerSystem {
-    power_on_password_status: u16, // == 3 (Suspicious)
+pub struct Win32ComputerSystem {
+    pub power_on_password_status: u16, // == 3 (Suspicious)
     #[serde(default)]
-    manufacturer: String, // Type 1 identifier
+    pub manufacturer: String, // Type 1 identifier
     #[serde(default)]
-    model: String, // Type 1 identifier
+    pub model: String, // Type 1 identifier
 }
 
-#[derive(Deserialize)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
-struct Win32Bios {
+pub struct Win32Bios {
     #[serde(default)]
-    name: String, // Type 2 identifier
+    pub name: String, // Type 2 identifier
     #[serde(default)]
-    caption: String, // Type 2 identifier
+    pub caption: String, // Type 2 identifier
     #[serde(default, rename = "__RELPATH")]
-    alt_rel_path: String, // Type 2 identifier
+    pub alt_rel_path: String, // Type 2 identifier
     #[serde(default, rename = "__PATH")]
-    alt_path: String, // Type 2 identifier
+    pub alt_path: String, // Type 2 identifier
     #[serde(default, rename = "BIOSVersion")]
-    bios_version: String, // Type 1 & 2 identifier
+    pub bios_version: String, // Type 1 & 2 identifier
     #[serde(default)]
-    description: String, // Type 2
+    pub description: String, // Type 2
     #[serde(default)]
-    manufacturer: String, // Type 1 identifier
+    pub manufacturer: String, // Type 1 identifier
     #[serde(default, rename = "SMBIOSBIOSVersion")]
-    sm_bios_bios_version: String, // Type 2 identifier
+    pub sm_bios_bios_version: String, // Type 2 identifier
     #[serde(default, rename = "SoftwareElementID")]
-    software_element_id: String, // Type 2
+    pub software_element_id: String, // Type 2
     #[serde(default)]
-    path: String, // Type 2
+    pub path: String, // Type 2
     #[serde(default)]
-    serial_number: String, // Type 1 identifier
+    pub serial_number: String, // Type 1 identifier
 }
 
-#[derive(Deserialize)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
-struct CimUserDevice {
+pub struct CimUserDevice {
     #[serde(default, rename = "__DYNASTY")]
-    dynasty: String, // Type 3 identifier
+    pub dynasty: String, // Type 3 identifier
     #[serde(default, rename = "__PATH")]
-    alt_path: String, // Type 3 identifier
+    pub alt_path: String, // Type 3 identifier
     #[serde(default, rename = "DeviceID")]
-    device_id: String, // Type 2 & 3 identifier
+    pub device_id: String, // Type 2 & 3 identifier
     #[serde(default, rename = "PNPDeviceID")]
-    pnp_device_id: String, // Type 2 & 3 identifier
+    pub pnp_device_id: String, // Type 2 & 3 identifier
     #[serde(default)]
-    path: String, // Type 2 & 3 identifier
+    pub path: String, // Type 2 & 3 identifier
     #[serde(default, rename = "__RELPATH")]
-    alt_rel_path: String, // Type 2 & 3 identifier
+    pub alt_rel_path: String, // Type 2 & 3 identifier
     #[serde(default, rename = "__NAMESPACE")]
-    namespace: String, // Type 3 identifier
+    pub namespace: String, // Type 3 identifier
     #[serde(default)]
-    caption: String, // Type 1
+    pub caption: String, // Type 1
     #[serde(default)]
-    description: String, // Type 1
+    pub description: String, // Type 1
     #[serde(default)]
-    hardware_type: String, // Type 1
+    pub hardware_type: String, // Type 1
 }
 
-#[derive(Deserialize)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
-struct CimCard {
+pub struct CimCard {
     #[serde(default)]
-    product: String, // ^ Desktop Reference Platform => Detection
-                     // 440BX => Very suspicious
+    pub product: String, // ^ Desktop Reference Platform => Detection
+                         // 440BX => Very suspicious
 }
 
-#[derive(Deserialize)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
-struct CimChassis {
+pub struct CimChassis {
     #[serde(default)]
-    chassis_types: String, // contains {1} **AND**
+    pub chassis_types: String, // contains {1} **AND**
     #[serde(default)]
-    manufacturer: String, // contains No Enclosure
+    pub manufacturer: String, // contains No Enclosure
 }
 
 trait Score {
@@ -129,58 +151,83 @@ trait Score {
 }
 
 impl Score for Win32ComputerSystem {
-    #[rustfmt::skip]
     fn score(&self, flags: &mut Flags) {
         if self.power_on_password_status == 0 {
-            flags.small_penalty();
+            flags.small_penalty(
+                "wmi.power_on_password_disabled",
+                "power on password status is disabled",
+            );
         }
 
-        if is_bad(&self.manufacturer) { flags.extreme_penalty(); }
-        if is_bad(&self.model) { flags.extreme_penalty(); }
+        penalize_bad_fields(
+            flags,
+            "wmi.computer_system_vm_string",
+            "computer system names a VM vendor",
+            &[("Manufacturer", &self.manufacturer), ("Model", &self.model)],
+        );
     }
 }
 
 impl Score for Win32Bios {
-    #[rustfmt::skip]
     fn score(&self, flags: &mut Flags) {
-        if is_bad(&self.name) { flags.extreme_penalty(); }
-        if is_bad(&self.caption) { flags.extreme_penalty(); }
-        if is_bad(&self.alt_rel_path) { flags.extreme_penalty(); }
-        if is_bad(&self.alt_path) { flags.extreme_penalty(); }
-        if is_bad(&self.bios_version) { flags.extreme_penalty(); }
-        if is_bad(&self.description) { flags.extreme_penalty(); }
-        if is_bad(&self.manufacturer) { flags.extreme_penalty(); }
-        if is_bad(&self.sm_bios_bios_version) { flags.extreme_penalty(); }
-        if is_bad(&self.software_element_id) { flags.extreme_penalty(); }
-        if is_bad(&self.path) { flags.extreme_penalty(); }
-        if is_bad(&self.serial_number) { flags.extreme_penalty(); }
+        penalize_bad_fields(
+            flags,
+            "wmi.bios_vm_string",
+            "BIOS names a VM vendor",
+            &[
+                ("Name", &self.name),
+                ("Caption", &self.caption),
+                ("__RELPATH", &self.alt_rel_path),
+                ("__PATH", &self.alt_path),
+                ("BIOSVersion", &self.bios_version),
+                ("Description", &self.description),
+                ("Manufacturer", &self.manufacturer),
+                ("SMBIOSBIOSVersion", &self.sm_bios_bios_version),
+                ("SoftwareElementID", &self.software_element_id),
+                ("Path", &self.path),
+                ("SerialNumber", &self.serial_number),
+            ],
+        );
     }
 }
 
 impl Score for CimUserDevice {
-    #[rustfmt::skip]
     fn score(&self, flags: &mut Flags) {
-        if is_bad(&self.dynasty) { flags.extreme_penalty(); }
-        if is_bad(&self.alt_path) { flags.extreme_penalty(); }
-        if is_bad(&self.device_id) { flags.extreme_penalty(); }
-        if is_bad(&self.pnp_device_id) { flags.extreme_penalty(); }
-        if is_bad(&self.path) { flags.extreme_penalty(); }
-        if is_bad(&self.alt_rel_path) { flags.extreme_penalty(); }
-        if is_bad(&self.namespace) { flags.extreme_penalty(); }
-        if is_bad(&self.caption) { flags.extreme_penalty(); }
-        if is_bad(&self.description) { flags.extreme_penalty(); }
-        if is_bad(&self.hardware_type) { flags.extreme_penalty(); }
+        penalize_bad_fields(
+            flags,
+            "wmi.user_device_vm_string",
+            "user device names a VM vendor",
+            &[
+                ("__DYNASTY", &self.dynasty),
+                ("__PATH", &self.alt_path),
+                ("DeviceID", &self.device_id),
+                ("PNPDeviceID", &self.pnp_device_id),
+                ("Path", &self.path),
+                ("__RELPATH", &self.alt_rel_path),
+                ("__NAMESPACE", &self.namespace),
+                ("Caption", &self.caption),
+                ("Description", &self.description),
+                ("HardwareType", &self.hardware_type),
+            ],
+        );
     }
 }
 
 impl Score for CimCard {
     fn score(&self, flags: &mut Flags) {
         if self.product.contains("Desktop Reference Platform") {
-            flags.extreme_penalty();
+            flags
+                .extreme_penalty(
+                    "wmi.card_reference_platform",
+                    "card is a desktop reference platform",
+                )
+                .evidence(&self.product);
         }
 
         if self.product.contains("440BX") {
-            flags.large_penalty();
+            flags
+                .large_penalty("wmi.card_440bx", "card is a 440BX board")
+                .evidence(&self.product);
         }
     }
 }
@@ -188,7 +235,23 @@ impl Score for CimCard {
 impl Score for CimChassis {
     fn score(&self, flags: &mut Flags) {
         if self.chassis_types.contains("{1}") && self.manufacturer.contains("No Enclosure") {
-            flags.medium_penalty();
+            flags.medium_penalty("wmi.chassis_no_enclosure", "chassis has no enclosure");
+        }
+    }
+}
+
+fn penalize_bad_fields(
+    flags: &mut Flags,
+    rule_id: &'static str,
+    reason: &'static str,
+    fields: &[(&str, &str)],
+) {
+    for (field, value) in fields {
+        if is_bad(value) {
+            flags
+                .extreme_penalty(rule_id, reason)
+                .evidence(format!("{field}: {value}"))
+                .vendor(Hypervisor::VMware);
         }
     }
 }
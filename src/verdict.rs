@@ -0,0 +1,118 @@
+use std::fmt;
+
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Physical,
+    Suspicious,
+    VirtualMachine,
+    /// Too many checks failed to run for the score to mean anything
+    Inconclusive,
+}
+
+impl Verdict {
+    /// Process exit code for this verdict, kept clear of the usual 1 (error) and 2 (usage)
+    #[must_use]
+    pub const fn exit_code(self) -> i32 {
+        match self {
+            Self::Physical => 0,
+            Self::Suspicious => 10,
+            Self::VirtualMachine => 20,
+            Self::Inconclusive => 30,
+        }
+    }
+
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Physical => "physical",
+            Self::Suspicious => "suspicious",
+            Self::VirtualMachine => "virtual machine",
+            Self::Inconclusive => "inconclusive",
+        }
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VerdictThresholds {
+    /// Scores below this are at least suspicious
+    pub suspicious_below: i64,
+    /// Scores below this are a virtual machine
+    pub virtual_machine_below: i64,
+    /// More errored checks than this make the verdict inconclusive, unless the score is a virtual machine anyway
+    pub max_errored_checks: usize,
+}
+
+impl Default for VerdictThresholds {
+    fn default() -> Self {
+        Self {
+            suspicious_below: 0,
+            virtual_machine_below: -100,
+            max_errored_checks: 4,
+        }
+    }
+}
+
+impl VerdictThresholds {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.virtual_machine_below > self.suspicious_below {
+            bail!(
+                "virtual_machine_below ({}) must not be above suspicious_below ({})",
+                self.virtual_machine_below,
+                self.suspicious_below
+            );
+        }
+
+        Ok(())
+    }
+
+    #[must_use]
+    pub const fn classify(&self, score: i64, errored_checks: usize) -> Verdict {
+        // The checks that did run found enough, whatever the others would have
+        if score < self.virtual_machine_below {
+            Verdict::VirtualMachine
+        } else if errored_checks > self.max_errored_checks {
+            Verdict::Inconclusive
+        } else if score < self.suspicious_below {
+            Verdict::Suspicious
+        } else {
+            Verdict::Physical
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify() {
+        let thresholds = VerdictThresholds::default();
+        assert_eq!(thresholds.classify(10, 0), Verdict::Physical);
+        assert_eq!(thresholds.classify(0, 0), Verdict::Physical);
+        assert_eq!(thresholds.classify(-1, 0), Verdict::Suspicious);
+        assert_eq!(thresholds.classify(-100, 4), Verdict::Suspicious);
+        assert_eq!(thresholds.classify(-101, 0), Verdict::VirtualMachine);
+    }
+
+    #[test]
+    fn errored_checks() {
+        let thresholds = VerdictThresholds::default();
+        assert_eq!(thresholds.classify(10, 5), Verdict::Inconclusive);
+        assert_eq!(thresholds.classify(-50, 5), Verdict::Inconclusive);
+        assert_eq!(thresholds.classify(-100, 5), Verdict::Inconclusive);
+        assert_eq!(thresholds.classify(-101, 5), Verdict::VirtualMachine);
+    }
+}
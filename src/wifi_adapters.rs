@@ -1,31 +1,79 @@
+#[cfg(windows)]
 use std::ptr::null_mut;
 
+#[cfg(windows)]
 use anyhow::bail;
-
+use serde::{Deserialize, Serialize};
+#[cfg(windows)]
 use windows::Win32::{
     Foundation::{ERROR_SUCCESS, HANDLE},
     NetworkManagement::WiFi::{WLAN_INTERFACE_INFO_LIST, WlanEnumInterfaces, WlanOpenHandle},
 };
-use windows_core::{Free, GUID};
+#[cfg(windows)]
+use windows_core::Free;
 
 use crate::{debug_println, flags::Flags};
 
-pub fn score_wifi_adapters(flags: &mut Flags) -> anyhow::Result<()> {
-    let devices_len = get_w_lan_devices(flags)?;
+const ZERO_GUID: &str = "00000000-0000-0000-0000-000000000000";
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct WifiFacts {
+    pub interfaces: Vec<WifiInterface>,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WifiInterface {
+    pub guid: String,
+    pub description: String,
+}
+
+pub fn score_wifi_adapters(facts: &WifiFacts, flags: &mut Flags) {
+    let devices_len = facts
+        .interfaces
+        .iter()
+        .filter(|interface| is_valid_interface(interface, flags))
+        .count();
     debug_println!("found {devices_len} valid WiFi adapters");
 
     if devices_len == 0 {
-        flags.medium_penalty();
+        flags.medium_penalty("wifi.none", "no valid WiFi adapters");
     } else {
-        flags.medium_bonus();
+        flags
+            .medium_bonus("wifi.present", "has a valid WiFi adapter")
+            .evidence(devices_len);
     }
+}
 
-    Ok(())
+fn is_valid_interface(interface: &WifiInterface, flags: &mut Flags) -> bool {
+    if interface.guid == ZERO_GUID {
+        flags.large_penalty("wifi.zero_guid", "WiFi adapter has an empty GUID");
+        return false;
+    }
+
+    let description = interface.description.to_lowercase();
+    if description.is_empty()
+        || description.contains("vmware")
+        || description.contains("virtualbox")
+        || description.contains("vbox")
+        || description.contains("hyper-v")
+    {
+        flags
+            .large_penalty("wifi.vm_description", "WiFi adapter names a VM vendor")
+            .evidence(&description)
+            .vendor_from(&description);
+        return false;
+    }
+
+    true
 }
 
+#[cfg(windows)]
 const DW_MAX_CLIENT: u32 = 2;
 
-fn get_w_lan_devices(flags: &mut Flags) -> anyhow::Result<usize> {
+#[cfg(windows)]
+pub fn collect_wifi_adapters() -> anyhow::Result<WifiFacts> {
     let mut h_client = HandleManager::new();
     let mut dw_cur_version = 0;
 
@@ -52,29 +100,15 @@ fn get_w_lan_devices(flags: &mut Flags) -> anyhow::Result<usize> {
 
     let len = unsafe { (*p_if_list).dwNumberOfItems } as usize;
 
-    let devices_len = (0..len)
+    let interfaces = (0..len)
         .filter_map(|i| unsafe { (*p_if_list).InterfaceInfo.get(i) })
-        .filter(|p_if_info| {
-            if p_if_info.InterfaceGuid == GUID::zeroed() {
-                flags.large_penalty();
-                return false;
-            }
-
-            let description =
-                String::from_utf16_lossy(&p_if_info.strInterfaceDescription).to_lowercase();
-            if description.is_empty()
-                || description.contains("vmware")
-                || description.contains("virtualbox")
-                || description.contains("vbox")
-                || description.contains("hyper-v")
-            {
-                flags.large_penalty();
-                return false;
-            }
-
-            true
+        .map(|p_if_info| WifiInterface {
+            guid: format!("{:?}", p_if_info.InterfaceGuid),
+            description: String::from_utf16_lossy(&p_if_info.strInterfaceDescription)
+                .trim_end_matches('\0')
+                .to_owned(),
         })
-        .count();
+        .collect();
 
     // wlan_interface_state_ad_hoc_network_formed = 2
     // wlan_interface_state_associating = 5
@@ -85,17 +119,20 @@ fn get_w_lan_devices(flags: &mut Flags) -> anyhow::Result<usize> {
     // wlan_interface_state_discovering = 6
     // wlan_interface_state_not_ready = 0
 
-    Ok(devices_len)
+    Ok(WifiFacts { interfaces })
 }
 
+#[cfg(windows)]
 struct HandleManager(pub HANDLE);
 
+#[cfg(windows)]
 impl HandleManager {
     const fn new() -> Self {
         Self(unsafe { core::mem::zeroed() })
     }
 }
 
+#[cfg(windows)]
 impl Drop for HandleManager {
     fn drop(&mut self) {
         if !self.0.is_invalid() {
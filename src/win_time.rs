@@ -0,0 +1,254 @@
+//! Windows `SYSTEMTIME` and `FILETIME`, converted in plain Rust so offline hives and snapshots read the same dates
+use std::fmt;
+
+use anyhow::{Context, ensure};
+use chrono::{DateTime, Datelike, NaiveDate, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+
+const TICKS_PER_SECOND: i64 = 10_000_000;
+/// Seconds from 1601-01-01, where `FILETIME` counts from, to the unix epoch
+const UNIX_EPOCH_SECONDS: i64 = 11_644_473_600;
+/// The years `SystemTimeToFileTime` accepts
+const YEARS: std::ops::RangeInclusive<u16> = 1601..=30827;
+
+/// 100ns ticks since 1601-01-01 UTC, 0 when the time was never set
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct FileTime(pub u64);
+
+impl FileTime {
+    #[must_use]
+    pub const fn from_parts(low: u32, high: u32) -> Self {
+        Self(((high as u64) << 32) | low as u64)
+    }
+
+    /// The 8 little endian bytes binary values like `ShutdownTime` hold
+    #[must_use]
+    pub fn from_le_bytes(data: &[u8]) -> Option<Self> {
+        Some(Self(u64::from_le_bytes(data.try_into().ok()?)))
+    }
+
+    /// None for 0 and for the values with the top bit set, which windows doesn't convert either
+    #[must_use]
+    pub fn to_datetime(self) -> Option<DateTime<Utc>> {
+        if self.0 == 0 {
+            return None;
+        }
+        let ticks = i64::try_from(self.0).ok()?;
+        let nanos = u32::try_from(ticks % TICKS_PER_SECOND * 100).ok()?;
+        DateTime::from_timestamp(ticks / TICKS_PER_SECOND - UNIX_EPOCH_SECONDS, nanos)
+    }
+
+    /// None before 1601
+    #[must_use]
+    pub fn from_datetime(time: DateTime<Utc>) -> Option<Self> {
+        let seconds = time.timestamp().checked_add(UNIX_EPOCH_SECONDS)?;
+        let ticks = seconds
+            .checked_mul(TICKS_PER_SECOND)?
+            .checked_add(i64::from(time.timestamp_subsec_nanos() / 100))?;
+        u64::try_from(ticks).ok().map(Self)
+    }
+
+    #[must_use]
+    pub fn to_system_time(self) -> Option<SystemTime> {
+        SystemTime::from_datetime(self.to_datetime()?)
+    }
+}
+
+/// The eight `u16`s windows writes a date as, in UTC unless the value says otherwise
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct SystemTime {
+    pub year: u16,
+    pub month: u16,
+    /// 0 is sunday, windows ignores it when converting
+    pub day_of_week: u16,
+    pub day: u16,
+    pub hour: u16,
+    pub minute: u16,
+    pub second: u16,
+    pub milliseconds: u16,
+}
+
+impl SystemTime {
+    #[must_use]
+    pub const fn from_words(words: [u16; 8]) -> Self {
+        Self {
+            year: words[0],
+            month: words[1],
+            day_of_week: words[2],
+            day: words[3],
+            hour: words[4],
+            minute: words[5],
+            second: words[6],
+            milliseconds: words[7],
+        }
+    }
+
+    /// The 16 little endian bytes binary values like `FirstLogonTime` hold
+    #[must_use]
+    pub fn from_le_bytes(data: &[u8]) -> Option<Self> {
+        let (words, []) = data.as_chunks::<2>() else {
+            return None;
+        };
+        let words: [[u8; 2]; 8] = words.try_into().ok()?;
+        Some(Self::from_words(words.map(u16::from_le_bytes)))
+    }
+
+    /// Fails on anything `SystemTimeToFileTime` would, a month 13, February 30, an hour 24...
+    pub fn to_datetime(&self) -> anyhow::Result<DateTime<Utc>> {
+        ensure!(
+            YEARS.contains(&self.year),
+            "year {} out of range",
+            self.year
+        );
+        ensure!(
+            self.milliseconds < 1000,
+            "{} milliseconds",
+            self.milliseconds
+        );
+
+        let date = NaiveDate::from_ymd_opt(
+            i32::from(self.year),
+            u32::from(self.month),
+            u32::from(self.day),
+        )
+        .with_context(|| format!("{self} is not a date"))?;
+        let time = date
+            .and_hms_milli_opt(
+                u32::from(self.hour),
+                u32::from(self.minute),
+                u32::from(self.second),
+                u32::from(self.milliseconds),
+            )
+            .with_context(|| format!("{self} is not a time"))?;
+
+        Ok(time.and_utc())
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.to_datetime().map(|_| ())
+    }
+
+    pub fn to_file_time(&self) -> anyhow::Result<FileTime> {
+        FileTime::from_datetime(self.to_datetime()?).context("bft")
+    }
+
+    /// None outside the years windows can represent, sub-millisecond precision is dropped
+    #[must_use]
+    pub fn from_datetime(time: DateTime<Utc>) -> Option<Self> {
+        let year = u16::try_from(time.year())
+            .ok()
+            .filter(|year| YEARS.contains(year))?;
+        let narrow = |value: u32| u16::try_from(value).ok();
+        // A leap second counts its nanoseconds past a billion, windows has no such second
+        let milliseconds = time.timestamp_subsec_millis().min(999);
+
+        Some(Self {
+            year,
+            month: narrow(time.month())?,
+            day_of_week: narrow(time.weekday().num_days_from_sunday())?,
+            day: narrow(time.day())?,
+            hour: narrow(time.hour())?,
+            minute: narrow(time.minute())?,
+            second: narrow(time.second())?,
+            milliseconds: narrow(milliseconds)?,
+        })
+    }
+}
+
+impl fmt::Display for SystemTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second, self.milliseconds
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(text: &str) -> DateTime<Utc> {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn file_time_epochs() {
+        assert_eq!(FileTime(0).to_datetime(), None);
+        assert_eq!(
+            FileTime(1).to_datetime(),
+            Some(utc("1601-01-01T00:00:00.0000001Z"))
+        );
+        assert_eq!(
+            FileTime(116_444_736_000_000_000).to_datetime(),
+            Some(DateTime::UNIX_EPOCH)
+        );
+        assert_eq!(
+            FileTime::from_datetime(DateTime::UNIX_EPOCH),
+            Some(FileTime(116_444_736_000_000_000))
+        );
+        assert_eq!(FileTime::from_datetime(utc("1600-12-31T23:59:59Z")), None);
+        assert_eq!(FileTime(1 << 63).to_datetime(), None);
+    }
+
+    #[test]
+    fn file_time_bytes() {
+        let time = FileTime::from_parts(0xd53e_8000, 0x019d_b1de);
+        assert_eq!(time, FileTime(116_444_736_000_000_000));
+        assert_eq!(FileTime::from_le_bytes(&time.0.to_le_bytes()), Some(time));
+        assert_eq!(FileTime::from_le_bytes(&[0; 7]), None);
+    }
+
+    #[test]
+    fn round_trips() {
+        let time = utc("2024-02-29T13:14:15.678Z");
+        let system = SystemTime::from_datetime(time).unwrap();
+        assert_eq!(
+            system,
+            SystemTime::from_words([2024, 2, 4, 29, 13, 14, 15, 678])
+        );
+        assert_eq!(system.to_datetime().unwrap(), time);
+
+        let file_time = system.to_file_time().unwrap();
+        assert_eq!(file_time.to_datetime(), Some(time));
+        assert_eq!(file_time.to_system_time(), Some(system));
+
+        let bytes: Vec<u8> = [2024u16, 2, 4, 29, 13, 14, 15, 678]
+            .into_iter()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        assert_eq!(SystemTime::from_le_bytes(&bytes), Some(system));
+        assert_eq!(SystemTime::from_le_bytes(bytes.get(..14).unwrap()), None);
+    }
+
+    #[test]
+    fn ignores_day_of_week() {
+        let system = SystemTime::from_words([2024, 2, 0xffff, 29, 13, 14, 15, 678]);
+        assert_eq!(
+            system.to_datetime().unwrap(),
+            utc("2024-02-29T13:14:15.678Z")
+        );
+    }
+
+    #[test]
+    fn rejects_out_of_range() {
+        for words in [
+            [1600, 12, 0, 31, 0, 0, 0, 0],
+            [30828, 1, 0, 1, 0, 0, 0, 0],
+            [2024, 13, 0, 1, 0, 0, 0, 0],
+            [2023, 2, 0, 29, 0, 0, 0, 0],
+            [2024, 1, 0, 0, 0, 0, 0, 0],
+            [2024, 1, 0, 1, 24, 0, 0, 0],
+            [2024, 1, 0, 1, 0, 60, 0, 0],
+            [2024, 1, 0, 1, 0, 0, 60, 0],
+            [2024, 1, 0, 1, 0, 0, 0, 1000],
+        ] {
+            SystemTime::from_words(words).validate().unwrap_err();
+        }
+        assert_eq!(SystemTime::from_datetime(utc("1600-12-31T23:59:59Z")), None);
+    }
+}